@@ -0,0 +1,104 @@
+/* gopro.rs - Times the GoPro handler on a synthetic card and counts the filesystem calls it makes
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+// Criterion isn't a dependency, so this is a plain program timing each case a number of times
+// and printing the median. Run with `cargo bench`
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::*;
+use media_interface::fs_provider::{FsProvider, MemoryFs, RealFs};
+use media_interface::{get_handler, HandlerOptions, SourceMediaInterface};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const CLIPS: u32 = 100;
+const PARTS: u32 = 5;
+const PHOTOS: u32 = 200;
+const RUNS: usize = 15;
+
+/// A card of CLIPS clips of PARTS parts each, with their THM and LRV files, and PHOTOS photos
+fn card_files() -> Vec<String> {
+    let mut files = Vec::new();
+    for clip in 1..=CLIPS {
+        for part in 1..=PARTS {
+            files.extend([format!("GX{part:02}{clip:04}.MP4"), format!("GX{part:02}{clip:04}.THM"), format!("GL{part:02}{clip:04}.LRV")]);
+        }
+    }
+    files.extend((CLIPS + 1..=CLIPS + PHOTOS).map(|photo| format!("GOPR{photo:04}.JPG")));
+    files
+}
+
+fn median_time(mut run: impl FnMut()) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS).map(|_| {
+        let start = Instant::now();
+        run();
+        start.elapsed()
+    }).collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+/// Time listing the card and getting the related files of every item on it, and count the
+/// filesystem calls getting the related files of one clip takes
+fn bench_card(name: &str, handler: &dyn SourceMediaInterface, location: &Path, card: &Path, fs: impl FsProvider + 'static) {
+    let (fs, calls) = CountingFs::new(fs);
+    let options: HandlerOptions = HandlerOptions::new(card.to_path_buf(), Box::new(fs));
+
+    let list_thumbnail = median_time(|| { handler.list_thumbnail(location, card, &options).unwrap(); });
+    let list_high_quality = median_time(|| { handler.list_high_quality(location, card, &options).unwrap(); });
+    let items = handler.list_high_quality(location, card, &options).unwrap();
+    let get_related = median_time(|| {
+        for item in &items {
+            handler.get_related(location, Path::new(&item.file_path), &options).unwrap();
+        }
+    });
+
+    calls.read_dir.set(0);
+    calls.metadata.set(0);
+    calls.read.set(0);
+    handler.get_related(location, &card.join("GX010001.MP4"), &options).unwrap();
+
+    println!("{name}: {} items", items.len());
+    println!("  list_thumbnail    {:>10.3} ms", list_thumbnail.as_secs_f64() * 1000.0);
+    println!("  list_high_quality {:>10.3} ms", list_high_quality.as_secs_f64() * 1000.0);
+    println!("  get_related x {:<3} {:>10.3} ms", items.len(), get_related.as_secs_f64() * 1000.0);
+    // Checking each of the 6 kinds of files a part can have one by one takes a call for each
+    println!("  get_related of a clip of {PARTS} parts: {} read_dir, {} metadata, {} read calls, instead of {} checking files one by one",
+        calls.read_dir.get(), calls.metadata.get(), calls.read.get(), 6 * PARTS);
+}
+
+fn main() {
+    let handler = get_handler("GoPro-Hero-Generic-1").unwrap();
+    let files = card_files();
+
+    let card = Path::new(CARD);
+    let paths: Vec<String> = files.iter().map(|file| format!("{}/{}", CARD, file)).collect();
+    let memory_fs = MemoryFs::with_files(&paths.iter().map(String::as_str).collect::<Vec<_>>());
+    bench_card("in memory", handler.as_ref(), Path::new(LOCATION), card, memory_fs);
+
+    let dir = TestDir::new();
+    let card = dir.path.join("card");
+    std::fs::create_dir_all(&card).unwrap();
+    for file in &files {
+        std::fs::write(card.join(file), "").unwrap();
+    }
+    bench_card("on disk", handler.as_ref(), &dir.path, &card, RealFs);
+}
@@ -0,0 +1,40 @@
+/* file_name_parsers.rs - Fuzz target feeding arbitrary file names to the handlers' file name
+ * parsers. Run with `cargo +nightly fuzz run file_name_parsers` from the repository root
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use media_interface::{gopro_hero_generic_1, sony_ilcem4_1};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// Any input is a valid file name as far as the fuzzer is concerned, all that's checked is that
+// none of the parsers panic on it. Each name is tried in the directories the handlers expect
+// their files in
+fuzz_target!(|data: &[u8]| {
+    let name = OsStr::from_bytes(data);
+
+    gopro_hero_generic_1::fuzz_file_name_parsers(&Path::new("/source/DATA/CARD0001").join(name));
+    for dir in ["CLIP", "THMBNL", "SUB", "GENERAL"] {
+        sony_ilcem4_1::fuzz_file_name_parsers(&Path::new("/source/DATA/CARD0001/PRIVATE/M4ROOT").join(dir).join(name));
+    }
+    sony_ilcem4_1::fuzz_file_name_parsers(&Path::new("/source/DATA/CARD0001/DCIM/100MSDCF").join(name));
+});
@@ -0,0 +1,172 @@
+/* android_motion_photo_generic_1.rs - Handler for Android phones that save motion photos as a
+ * single JPG with the video appended to it
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use crate::{SourceMediaInterface, HandlerOptions};
+use std::path::Path;
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+pub struct AndroidMotionPhotoInterface;
+
+/// How far into a JPG to look for the MotionPhoto XMP marker. It sits in an APP1 segment right at
+/// the start of the file
+#[cfg(feature = "motion-photo")]
+const XMP_SEARCH_LEN: usize = 64 * 1024;
+
+/// Most of a Samsung trailer that is searched for its motion photo entry
+#[cfg(feature = "motion-photo")]
+const TRAILER_SEARCH_LEN: usize = 64 * 1024;
+
+#[cfg(feature = "motion-photo")]
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+/// The value of the XMP flag `name`, written either as an attribute or as an element, going by
+/// the first occurrence of it with a value
+#[cfg(feature = "motion-photo")]
+fn xmp_flag(xmp: &[u8], name: &[u8]) -> Option<bool> {
+    (0..xmp.len().saturating_sub(name.len())).filter(|start| xmp[*start..].starts_with(name)).find_map(|start| {
+        let after = &xmp[start + name.len()..];
+        let value = match after {
+            [b'=', b'"' | b'\'', value, ..] | [b'>', value, ..] => *value,
+            _ => return None,
+        };
+        Some(value == b'1')
+    })
+}
+
+/// Whether the photo has a video appended to it. Google phones mark that with a MotionPhoto or,
+/// on older ones, MicroVideo XMP flag of 1. Samsung phones that don't set the flag end the file
+/// with a trailer listing what was appended, which names the video MotionPhoto_Data. Only the
+/// start and the end of the file are read. Without the motion-photo feature this isn't checked
+#[cfg(feature = "motion-photo")]
+fn has_motion(file: &Path, options: &HandlerOptions) -> Result<Option<bool>> {
+    let fs = options.fs.as_ref();
+    let head = fs.read_range(file, 0, XMP_SEARCH_LEN)?;
+    if let Some(flag) = xmp_flag(&head, b"MotionPhoto").or_else(|| xmp_flag(&head, b"MicroVideo")) {
+        return Ok(Some(flag));
+    }
+
+    // The trailer ends in its length and "SEFT"
+    let len = fs.metadata(file)?.len;
+    let Some(end) = len.checked_sub(8) else {
+        return Ok(Some(false));
+    };
+    let footer = fs.read_range(file, end, 8)?;
+    if !footer.ends_with(b"SEFT") {
+        return Ok(Some(false));
+    }
+    let trailer_len = u64::from(u32::from_le_bytes(footer[..4].try_into().expect("the footer is 8 bytes")));
+    let trailer_start = end.saturating_sub(trailer_len);
+    let trailer = fs.read_range(file, trailer_start, usize::try_from(end - trailer_start).unwrap_or(usize::MAX).min(TRAILER_SEARCH_LEN))?;
+    Ok(Some(contains(&trailer, b"MotionPhoto_Data")))
+}
+
+#[cfg(not(feature = "motion-photo"))]
+fn has_motion(_file: &Path, _options: &HandlerOptions) -> Result<Option<bool>> {
+    Ok(None)
+}
+
+fn create_file(path: &Path, options: &HandlerOptions) -> Result<FileItem> {
+    let path_str = path.to_string_lossy().into_owned();
+    match get_extension_str(path)?.to_lowercase().as_str() {
+        "jpg" | "jpeg" => {
+            let mut item = create_simple_file(path_str, JsonFileInfoTypes{ file_type:FileImage, item_type:ItemImage }, None)?;
+            item.has_motion = has_motion(path, options)?;
+            Ok(item)
+        }
+        "mp4" => Ok(create_part_file(path_str, JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemVideo }, 1, 1, None)),
+        ext => Err(anyhow!("Unrecognised extension '{}' in file '{}'", ext, path_str)),
+    }
+}
+
+/// A file of the card as list actions see it, with the ones that aren't photos or videos left to
+/// the unknown file policy
+fn list_file(path: &Path, options: &HandlerOptions) -> Result<Option<FileItem>> {
+    match get_extension_str(path).ok().map(str::to_lowercase).as_deref() {
+        Some("jpg" | "jpeg" | "mp4") => Ok(Some(create_file(path, options)?)),
+        _ => unknown_file(&path.to_string_lossy(), options),
+    }
+}
+
+impl SourceMediaInterface for AndroidMotionPhotoInterface {
+    fn list_thumbnail(&self, source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        self.list_thumbnail_iter(source_media_location, source_media_card, options).collect()
+    }
+    fn list_high_quality(&self, source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        self.list_thumbnail(source_media_location, source_media_card, options)
+    }
+    // Photos are read to check for motion, so files are only classified as they are asked for
+    fn list_thumbnail_iter<'a>(&'a self, _source_media_location: &'a Path, source_media_card: &'a Path, options: &'a HandlerOptions) -> Box<dyn Iterator<Item = Result<FileItem>> + 'a> {
+        let add_context = |err: anyhow::Error| anyhow!("Error filtering dir '{}': {}", source_media_card.display(), err);
+        match dir_entries(source_media_card, options) {
+            Ok(entries) => Box::new(entries.filter_map(move |path| list_file(&path, options).map_err(add_context).transpose())),
+            Err(err) => Box::new(std::iter::once(Err(add_context(err)))),
+        }
+    }
+    fn list_high_quality_iter<'a>(&'a self, source_media_location: &'a Path, source_media_card: &'a Path, options: &'a HandlerOptions) -> Box<dyn Iterator<Item = Result<FileItem>> + 'a> {
+        self.list_thumbnail_iter(source_media_location, source_media_card, options)
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let file = find_file(options.fs.as_ref(), source_media_file).unwrap_or_else(|| source_media_file.to_path_buf());
+        check_related(options.fs.as_ref(), source_media_file, vec![create_file(&file, options)?])
+    }
+    fn name(&self) -> &'static str {
+        "Android-Motion-Photo-Generic-1"
+    }
+}
+
+#[cfg(all(test, feature = "motion-photo"))]
+mod tests {
+    use super::*;
+    use crate::fs_provider::MemoryFs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn only_photos_marked_with_motion_have_it() {
+        let samsung_trailer = [&b"SEFH"[..], b"MotionPhoto_Data", &20u32.to_le_bytes(), b"SEFT"].concat();
+        let photos: [(&str, Vec<u8>); 6] = [
+            ("motion.jpg", br#"<x:xmpmeta GCamera:MotionPhoto="1" GCamera:MotionPhotoVersion="1">"#.to_vec()),
+            ("still.jpg", br#"<x:xmpmeta GCamera:MotionPhoto="0" GCamera:MotionPhotoVersion="1">"#.to_vec()),
+            ("element.jpg", b"<GCamera:MotionPhoto>1</GCamera:MotionPhoto>".to_vec()),
+            ("micro.jpg", br#"<x:xmpmeta GCamera:MicroVideo="1" GCamera:MicroVideoOffset="1234">"#.to_vec()),
+            ("samsung.jpg", [&b"\xff\xd8 image \xff\xd9"[..], b"\0\0\0\x18ftypmp42", &samsung_trailer].concat()),
+            // Bytes of the image data that happen to read "ftyp" don't make it a motion photo
+            ("unmarked.jpg", b"\xff\xd8 ftyp image \xff\xd9".to_vec()),
+        ];
+        let mut fs = MemoryFs::default();
+        for (name, contents) in &photos {
+            fs.add_file_with_contents(&Path::new("/card").join(name), contents.clone(), None);
+        }
+        let options = HandlerOptions::new(PathBuf::from("/card"), Box::new(fs));
+
+        let motion = |name: &str| has_motion(&Path::new("/card").join(name), &options).unwrap();
+        assert_eq!(motion("motion.jpg"), Some(true));
+        assert_eq!(motion("still.jpg"), Some(false));
+        assert_eq!(motion("element.jpg"), Some(true));
+        assert_eq!(motion("micro.jpg"), Some(true));
+        assert_eq!(motion("samsung.jpg"), Some(true));
+        assert_eq!(motion("unmarked.jpg"), Some(false));
+    }
+}
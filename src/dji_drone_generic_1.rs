@@ -0,0 +1,140 @@
+/* dji_drone_generic_1.rs - Handler for DJI drones that write DJI_NNNN named files with .LRF
+ * low resolution proxies and .SRT telemetry next to their videos
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use crate::{SourceMediaInterface, HandlerOptions};
+use std::path::{PathBuf,Path};
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+pub struct DJIInterface;
+
+const VIDEO: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemVideo };
+const VIDEO_PREVIEW: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileVideoPreview, item_type:ItemVideo };
+const VIDEO_TELEMETRY: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileMetadata, item_type:ItemVideo };
+const IMAGE: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileImage, item_type:ItemImage };
+const IMAGE_RAW: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileImageRaw, item_type:ItemImage };
+
+/// The number in a DJI_NNNN file name
+fn get_media_number(file: &Path) -> Result<u32> {
+    let stem = file.file_stem().ok_or_else(|| anyhow!("File {:?} has no name", file))?;
+    osstr_to_str(stem)?.strip_prefix("DJI_").and_then(|number| number.parse::<u32>().ok())
+        .ok_or_else(|| anyhow!("File name {:?} is not of the form DJI_NNNN", file))
+}
+
+impl SourceMediaInterface for DJIInterface {
+    fn list_thumbnail(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        scan_dcim_rolls(source_media_card, options, |ext: &str, path: &PathBuf, path_str: &str| {
+            match ext {
+                "LRF" => Ok(Some(create_part_file(path_str.to_string(), VIDEO_PREVIEW, 1, 1, None))),
+                "MP4" => if find_file(options.fs.as_ref(), &path.with_extension("LRF")).is_some() {
+                    Ok(None)
+                } else {
+                    Ok(Some(create_part_file(path_str.to_string(), VIDEO, 1, 1, None)))
+                },
+                "JPG" => Ok(Some(create_simple_file(path_str.to_string(), IMAGE, None)?)),
+                "DNG" => if find_file(options.fs.as_ref(), &path.with_extension("JPG")).is_some() {
+                    Ok(None)
+                } else {
+                    Ok(Some(create_simple_file(path_str.to_string(), IMAGE_RAW, None)?))
+                },
+                "SRT" => Ok(None),
+                _ => unknown_file(path_str, options),
+            }
+        })
+    }
+    fn list_high_quality(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        scan_dcim_rolls(source_media_card, options, |ext: &str, path: &PathBuf, path_str: &str| {
+            match ext {
+                "MP4" => Ok(Some(create_part_file(path_str.to_string(), VIDEO, 1, 1, None))),
+                "DNG" => Ok(Some(create_simple_file(path_str.to_string(), IMAGE_RAW, None)?)),
+                "JPG" => if find_file(options.fs.as_ref(), &path.with_extension("DNG")).is_some() {
+                    Ok(None)
+                } else {
+                    Ok(Some(create_simple_file(path_str.to_string(), IMAGE, None)?))
+                },
+                // The proxy is only ever a representation of the MP4 next to it
+                "LRF" | "SRT" => Ok(None),
+                _ => unknown_file(path_str, options),
+            }
+        })
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let fs = options.fs.as_ref();
+        let items = match get_extension_str(source_media_file)?.to_ascii_uppercase().as_str() {
+            "MP4" | "LRF" | "SRT" => Ok([
+                create_part_file_if_exists(fs, &source_media_file.with_extension("MP4"), VIDEO, 1, 1, None),
+                create_part_file_if_exists(fs, &source_media_file.with_extension("LRF"), VIDEO_PREVIEW, 1, 1, None),
+                create_simple_file_if_exists(fs, &source_media_file.with_extension("SRT"), VIDEO_TELEMETRY, None)?,
+            ].into_iter().flatten().collect()),
+            "JPG" | "DNG" => Ok([
+                create_simple_file_if_exists(fs, &source_media_file.with_extension("DNG"), IMAGE_RAW, None)?,
+                create_simple_file_if_exists(fs, &source_media_file.with_extension("JPG"), IMAGE, None)?,
+            ].into_iter().flatten().collect()),
+            ext => Err(anyhow!("unexpected file extension '{}' in file '{:?}'", ext, source_media_file)),
+        }?;
+        check_related(fs, source_media_file, items)
+    }
+    fn name(&self) -> &'static str {
+        "DJI-Drone-Generic-1"
+    }
+    fn required_subdirs(&self) -> &'static [&'static str] {
+        &["DCIM"]
+    }
+    fn capture_sequence(&self, file: &Path) -> Option<CaptureSequence> {
+        // Only four digit numbers come from the counter of the drone
+        let number = get_media_number(file).ok().filter(|number| *number < 10000)?;
+        Some(CaptureSequence{ counter: "media", number, wrap: 10000, part: 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card};
+
+    #[test]
+    fn lrf_proxy_is_the_thumbnail_of_its_video() {
+        let options = memory_card("/card", &["/card/DCIM/100MEDIA/DJI_0001.MP4", "/card/DCIM/100MEDIA/DJI_0001.LRF", "/card/DCIM/100MEDIA/DJI_0001.SRT",
+                             "/card/DCIM/100MEDIA/DJI_0002.MP4"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = DJIInterface.list_thumbnail(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100MEDIA/DJI_0001.LRF", "/card/DCIM/100MEDIA/DJI_0002.MP4"]);
+        assert_eq!(items[0].file_type, "video-preview");
+        let items = DJIInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100MEDIA/DJI_0001.MP4", "/card/DCIM/100MEDIA/DJI_0002.MP4"]);
+
+        for file in ["DJI_0001.MP4", "DJI_0001.LRF", "DJI_0001.SRT"] {
+            let items = DJIInterface.get_related(location, &Path::new("/card/DCIM/100MEDIA").join(file), &options).unwrap();
+            assert_eq!(items.iter().map(|item| item.file_type.as_str()).collect::<Vec<_>>(), ["video", "video-preview", "metadata"], "{}", file);
+        }
+    }
+
+    #[test]
+    fn numbers_past_the_counter_have_no_capture_sequence() {
+        assert_eq!(DJIInterface.capture_sequence(Path::new("/card/DCIM/100MEDIA/DJI_9999.MP4")).map(|sequence| sequence.number), Some(9999));
+        for file in ["DJI_20001.MP4", "DJI_4294967295.MP4"] {
+            assert!(DJIInterface.capture_sequence(&Path::new("/card/DCIM/100MEDIA").join(file)).is_none(), "{}", file);
+        }
+    }
+}
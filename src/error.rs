@@ -0,0 +1,75 @@
+/* error.rs - Errors reported by the actions of media-interface
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Handlers report their errors through anyhow, those get wrapped in HandlerParse once they
+/// reach the action that called the handler
+#[derive(Debug, Error)]
+pub enum MediaInterfaceError {
+    #[error("Unknown handler ID '{0}'")]
+    UnknownHandler(String),
+
+    #[error("Couldn't find handler responsible for a dir in the path of the input file")]
+    NoHandlerForPath(PathBuf),
+
+    #[error("List path entered is not a card directory")]
+    NotACardDirectory(PathBuf),
+
+    #[error("Input path is not inside a card directory")]
+    NotInsideCard(PathBuf),
+
+    #[error("{card:?} doesn't look like a {handler} card (missing {})", required_subdirs.join(" or "))]
+    CardStructureMismatch { handler: &'static str, card: PathBuf, required_subdirs: Vec<&'static str> },
+
+    /// `format` is the name of the format the file was read as, like "JSON"
+    #[error("Failed to parse {format} from config file {file:?}: {detail}")]
+    ConfigParse { file: PathBuf, format: &'static str, detail: String },
+
+    #[error("Config file {0:?} has no parent directory to resolve its paths against")]
+    ConfigWithoutParent(PathBuf),
+
+    #[error("Invalid data type on the config file: {0}")]
+    InvalidDataType(String),
+
+    #[error("Config version {version} of {file:?} not supported by this binary (supports {}..={})", supported.start(), supported.end())]
+    UnsupportedConfigVersion { file: PathBuf, version: u32, supported: RangeInclusive<u32> },
+
+    #[error("card_subdir {card_subdir:?} of source media {source_root:?} resolves outside of it")]
+    CardSubdirOutsideSource { card_subdir: PathBuf, source_root: PathBuf },
+
+    /// `context` says what was being done with which path
+    #[error("{context}: {source}")]
+    Io { context: String, source: io::Error },
+
+    #[error("Output doesn't conform to the schema {schema:?}: {}", errors.join("; "))]
+    OutputSchemaMismatch { schema: PathBuf, errors: Vec<String> },
+
+    #[error("handler {handler}: {detail}")]
+    HandlerParse { handler: &'static str, detail: String },
+}
+
+impl MediaInterfaceError {
+    pub fn io(context: String) -> impl FnOnce(io::Error) -> MediaInterfaceError {
+        move |source| MediaInterfaceError::Io{ context, source }
+    }
+}
@@ -0,0 +1,538 @@
+/* fs_provider.rs - Abstraction over the filesystem calls handlers make
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+pub struct FsEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+pub struct FsMetadata {
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// POSIX permission bits, owner and group. Only known on Unix
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// The filesystem operations handlers need. Handlers go through this instead of std::fs so they
+/// can be run against something other than the real filesystem
+pub trait FsProvider {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>>;
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata>;
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    /// Up to `len` bytes starting at `offset`, fewer if the file ends before that
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let data = self.read(path)?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+        Ok(data[start..].iter().take(len).copied().collect())
+    }
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+    /// The path `path` refers to with "." and ".." resolved and, on providers that have them,
+    /// symlinks followed. Fails if there is nothing there
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let resolved = resolve_dots(path);
+        self.metadata(&resolved)?;
+        Ok(resolved)
+    }
+}
+
+/// `path` with "." and ".." resolved without looking at the filesystem
+pub fn resolve_dots(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { resolved.pop(); },
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+pub struct RealFs;
+
+impl FsProvider for RealFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            entries.push(FsEntry{ path: entry.path(), is_dir: entry.file_type()?.is_dir() });
+        }
+        // In the order of their names, like the other providers, instead of whatever order the
+        // filesystem keeps them in, so the output is the same on every machine
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        let metadata = fs::metadata(path)?;
+
+        #[cfg(unix)]
+        let (mode, uid, gid) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some(metadata.mode()), Some(metadata.uid()), Some(metadata.gid()))
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid) = (None, None, None);
+
+        Ok(FsMetadata{ is_dir: metadata.is_dir(), len: metadata.len(), modified: metadata.modified().ok(), mode, uid, gid })
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// Passes everything through to another provider, keeping note of the directories listed along
+/// with their modification time from right before they were
+pub struct RecordingFs {
+    inner: Box<dyn FsProvider>,
+    listed_dirs: Rc<RefCell<BTreeMap<PathBuf, Option<SystemTime>>>>,
+}
+
+impl RecordingFs {
+    pub fn new(inner: Box<dyn FsProvider>, listed_dirs: Rc<RefCell<BTreeMap<PathBuf, Option<SystemTime>>>>) -> RecordingFs {
+        RecordingFs{ inner, listed_dirs }
+    }
+}
+
+impl FsProvider for RecordingFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        let modified = self.inner.metadata(dir).ok().and_then(|metadata| metadata.modified);
+        self.listed_dirs.borrow_mut().entry(dir.to_path_buf()).or_insert(modified);
+        self.inner.read_dir(dir)
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.inner.metadata(path)
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_range(path, offset, len)
+    }
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+}
+
+/// A filesystem tree held in memory, made out of a list of files. Parent directories are
+/// created implicitly. Sizes, modification times and owners come from here instead of a real
+/// filesystem for every one of the output fields that report them, so listings run against it
+/// give the same output every time
+#[derive(Default)]
+pub struct MemoryFs {
+    files: BTreeMap<PathBuf, FsMetadata>,
+    contents: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFs {
+    /// Empty files at `paths`, for the cases where only the names matter
+    pub fn with_files(paths: &[&str]) -> MemoryFs {
+        let mut fs = MemoryFs::default();
+        for path in paths {
+            fs.add_file(Path::new(path), 0, None);
+        }
+        fs
+    }
+    pub fn add_file(&mut self, path: &Path, len: u64, modified: Option<SystemTime>) {
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            self.files.entry(ancestor.to_path_buf()).or_insert(FsMetadata{ is_dir: true, len: 0, modified: None, mode: None, uid: None, gid: None });
+        }
+        self.files.insert(path.to_path_buf(), FsMetadata{ is_dir: false, len, modified, mode: None, uid: None, gid: None });
+    }
+    /// A file with all of its metadata given, like for the owner and permission bits read by
+    /// --stat-owner
+    pub fn add_file_with_metadata(&mut self, path: &Path, metadata: FsMetadata) {
+        self.add_file(path, metadata.len, metadata.modified);
+        self.files.insert(path.to_path_buf(), FsMetadata{ is_dir: false, ..metadata });
+    }
+    pub fn add_file_with_contents(&mut self, path: &Path, contents: Vec<u8>, modified: Option<SystemTime>) {
+        self.add_file(path, contents.len() as u64, modified);
+        self.contents.insert(path.to_path_buf(), contents);
+    }
+}
+
+impl FsProvider for MemoryFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        match self.files.get(dir) {
+            Some(metadata) if metadata.is_dir => {},
+            Some(_) => return Err(io::Error::other(format!("{:?} is not a directory", dir))),
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+        Ok(self.files.iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .map(|(path, metadata)| FsEntry{ path: path.clone(), is_dir: metadata.is_dir })
+            .collect())
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        let metadata = self.files.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(FsMetadata{ is_dir: metadata.is_dir, len: metadata.len, modified: metadata.modified, mode: metadata.mode, uid: metadata.uid, gid: metadata.gid })
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(metadata) if !metadata.is_dir => Ok(self.contents.get(path).cloned().unwrap_or_default()),
+            Some(_) => Err(io::Error::other(format!("{:?} is a directory", path))),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+/// Options for running a handler on a MemoryFs card at `card_root` made of empty `files`
+#[cfg(test)]
+pub(crate) fn memory_card(card_root: &str, files: &[&str]) -> crate::HandlerOptions {
+    crate::HandlerOptions::new(PathBuf::from(card_root), Box::new(MemoryFs::with_files(files)))
+}
+
+/// The paths of `items` in the order they were listed in
+#[cfg(test)]
+pub(crate) fn item_paths(items: &[crate::FileItem]) -> Vec<&str> {
+    items.iter().map(|item| item.file_path.as_str()).collect()
+}
+
+/// The entries of a tar or zip archive, seen as a directory tree under the path of the archive
+/// itself. Only the headers are read up front, the contents of files are read from the archive
+/// when asked for. If every entry is under one top level directory, like the card folder the
+/// archive was made from, that directory is the archive. Paths outside of the archive are passed
+/// through to the real filesystem
+#[cfg(feature = "archive")]
+pub struct ArchiveFs {
+    archive: PathBuf,
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+    /// The zip archive compressed entries are decompressed through, opened once one is read
+    zip: RefCell<Option<zip::ZipArchive<fs::File>>>,
+}
+
+#[cfg(feature = "archive")]
+struct ArchiveEntry {
+    is_dir: bool,
+    /// Where the contents of the file start in the archive
+    offset: u64,
+    len: u64,
+    /// The index of a compressed zip entry, which is read through the zip decoder instead of
+    /// straight from the archive
+    zip_index: Option<usize>,
+    modified: Option<SystemTime>,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+#[cfg(feature = "archive")]
+fn invalid_tar(archive: &Path, detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?} is not a valid tar archive: {}", archive, detail))
+}
+
+#[cfg(feature = "archive")]
+fn invalid_zip(archive: &Path, detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?} is not a valid zip archive: {}", archive, detail))
+}
+
+/// Split an archive entry name into a relative path, refusing ones that would end up outside of
+/// the archive
+#[cfg(feature = "archive")]
+fn archive_entry_path(name: &[u8], invalid: impl Fn(&str) -> io::Error) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in String::from_utf8_lossy(name).split('/') {
+        match component {
+            "" | "." => {},
+            ".." => return Err(invalid("entry outside of the archive")),
+            component => path.push(component),
+        }
+    }
+    Ok(path)
+}
+
+/// Seconds since the epoch of an MS-DOS date and time, taken as UTC as zip archives don't say
+#[cfg(feature = "archive")]
+fn dos_time(date: u64, time: u64) -> Option<SystemTime> {
+    let (year, month, day) = (1980 + (date >> 9) as i64, ((date >> 5) & 0xf) as i64, (date & 0x1f) as i64);
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    // Days since the epoch of the civil date, counting years from March so leap days come last
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era_year = year.rem_euclid(400);
+    let days = (year - era_year) / 400 * 146097 + era_year * 365 + era_year / 4 - era_year / 100 + (153 * month + 2) / 5 + day - 1 - 719468;
+    let seconds = days * 86400 + (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3f) as i64 * 60 + (time & 0x1f) as i64 * 2;
+    SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+/// The error of reading a zip archive as an io::Error, archives the zip crate can't make sense
+/// of being invalid data
+#[cfg(feature = "archive")]
+fn zip_error(archive: &Path, error: zip::result::ZipError) -> io::Error {
+    match error {
+        zip::result::ZipError::Io(error) => error,
+        zip::result::ZipError::UnsupportedArchive(detail) => io::Error::new(io::ErrorKind::Unsupported, format!("{:?}: {}", archive, detail)),
+        zip::result::ZipError::FileNotFound => io::Error::from(io::ErrorKind::NotFound),
+        error => invalid_zip(archive, &error.to_string()),
+    }
+}
+
+#[cfg(feature = "archive")]
+impl ArchiveFs {
+    /// Open a tar or a zip archive, going by its extension
+    pub fn open(archive: &Path) -> io::Result<ArchiveFs> {
+        match archive.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("zip") => ArchiveFs::open_zip(archive),
+            _ => ArchiveFs::open_tar(archive),
+        }
+    }
+
+    pub fn open_tar(archive: &Path) -> io::Result<ArchiveFs> {
+        let file = fs::File::open(archive)?;
+        let metadata = file.metadata()?;
+        let archive_len = metadata.len();
+        let archive_modified = metadata.modified().ok();
+
+        let mut entries = Vec::new();
+        let mut tar = tar::Archive::new(file);
+        for entry in tar.entries_with_seek().map_err(|e| invalid_tar(archive, &e.to_string()))? {
+            let entry = entry.map_err(|e| invalid_tar(archive, &e.to_string()))?;
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            // Links, devices, fifos and global pax headers have no contents to look at
+            if !entry_type.is_file() && !entry_type.is_contiguous() && !entry_type.is_dir() {
+                continue;
+            }
+            let len = entry.size();
+            if entry.raw_file_position().checked_add(len).is_none_or(|data_end| data_end > archive_len) {
+                return Err(invalid_tar(archive, "it ends in the middle of an entry"));
+            }
+            let path = archive_entry_path(&entry.path_bytes(), |detail| invalid_tar(archive, detail))?;
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let number = |field: io::Result<u64>| field.map_err(|e| invalid_tar(archive, &e.to_string()));
+            entries.push((path, ArchiveEntry{
+                is_dir: entry_type.is_dir(),
+                offset: entry.raw_file_position(),
+                len: if entry_type.is_dir() { 0 } else { len },
+                zip_index: None,
+                modified: SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(number(header.mtime())?)),
+                mode: Some(number(header.mode().map(u64::from))? as u32),
+                uid: u32::try_from(number(header.uid())?).ok(),
+                gid: u32::try_from(number(header.gid())?).ok(),
+            }));
+        }
+
+        Ok(ArchiveFs::with_entries(archive, entries, archive_modified))
+    }
+
+    pub fn open_zip(archive: &Path) -> io::Result<ArchiveFs> {
+        let archive_modified = fs::metadata(archive)?.modified().ok();
+        let mut zip = zip::ZipArchive::new(fs::File::open(archive)?).map_err(|e| zip_error(archive, e))?;
+
+        let mut entries = Vec::new();
+        for index in 0..zip.len() {
+            let file = zip.by_index_raw(index).map_err(|e| zip_error(archive, e))?;
+            let path = archive_entry_path(file.name_raw(), |detail| invalid_zip(archive, detail))?;
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            // The extended timestamp is UTC unlike the MS-DOS one
+            let extended_time = file.extra_data_fields().find_map(|field| match field {
+                zip::extra_fields::ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+                _ => None,
+            });
+            let modified = match extended_time {
+                Some(time) => SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(time.into())),
+                None => file.last_modified().and_then(|time| dos_time(time.datepart().into(), time.timepart().into())),
+            };
+            let is_dir = file.is_dir();
+            let stored = file.compression() == zip::CompressionMethod::Stored && !file.encrypted();
+            entries.push((path, ArchiveEntry{
+                is_dir,
+                offset: file.data_start(),
+                len: if is_dir { 0 } else { file.size() },
+                zip_index: (!stored).then_some(index),
+                modified,
+                mode: file.unix_mode(),
+                uid: None,
+                gid: None,
+            }));
+        }
+
+        let mut archive_fs = ArchiveFs::with_entries(archive, entries, archive_modified);
+        archive_fs.zip = RefCell::new(Some(zip));
+        Ok(archive_fs)
+    }
+
+    fn with_entries(archive: &Path, entries: Vec<(PathBuf, ArchiveEntry)>, archive_modified: Option<SystemTime>) -> ArchiveFs {
+        let top_level = entries.first().and_then(|(path, _)| path.components().next()).map(|component| PathBuf::from(component.as_os_str()));
+        let strip = top_level.filter(|top_level| entries.iter().all(|(path, entry)| path.starts_with(top_level) && (path != top_level || entry.is_dir))
+            && entries.iter().any(|(path, _)| path != top_level));
+
+        let directory = || ArchiveEntry{ is_dir: true, offset: 0, len: 0, zip_index: None, modified: archive_modified, mode: None, uid: None, gid: None };
+        let mut tree = BTreeMap::new();
+        tree.insert(archive.to_path_buf(), directory());
+        for (path, mut entry) in entries {
+            let path = match &strip {
+                Some(top_level) => path.strip_prefix(top_level).map(Path::to_path_buf).unwrap_or_default(),
+                None => path,
+            };
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let path = archive.join(path);
+            for ancestor in path.ancestors().skip(1).take_while(|ancestor| *ancestor != archive) {
+                tree.entry(ancestor.to_path_buf()).or_insert_with(directory);
+            }
+            // Directories report the modification time of the archive so that rewriting it
+            // shows as a change to all of them
+            if entry.is_dir {
+                entry.modified = archive_modified;
+            }
+            tree.insert(path, entry);
+        }
+
+        ArchiveFs{ archive: archive.to_path_buf(), entries: tree, zip: RefCell::new(None) }
+    }
+
+    fn entry(&self, path: &Path) -> io::Result<&ArchiveEntry> {
+        self.entries.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+#[cfg(feature = "archive")]
+impl FsProvider for ArchiveFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        if !dir.starts_with(&self.archive) {
+            return RealFs.read_dir(dir);
+        }
+        if !self.entry(dir)?.is_dir {
+            return Err(io::Error::other(format!("{:?} is not a directory", dir)));
+        }
+        Ok(self.entries.iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .map(|(path, entry)| FsEntry{ path: path.clone(), is_dir: entry.is_dir })
+            .collect())
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        if !path.starts_with(&self.archive) {
+            return RealFs.metadata(path);
+        }
+        let entry = self.entry(path)?;
+        Ok(FsMetadata{ is_dir: entry.is_dir, len: entry.len, modified: entry.modified, mode: entry.mode, uid: entry.uid, gid: entry.gid })
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        if !path.starts_with(&self.archive) {
+            return RealFs.read(path);
+        }
+        let len = self.entry(path)?.len;
+        self.read_range(path, 0, usize::try_from(len).unwrap_or(usize::MAX))
+    }
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if !path.starts_with(&self.archive) {
+            return RealFs.read_range(path, offset, len);
+        }
+        let entry = self.entry(path)?;
+        if entry.is_dir {
+            return Err(io::Error::other(format!("{:?} is a directory", path)));
+        }
+        let offset = offset.min(entry.len);
+        if let Some(index) = entry.zip_index {
+            // Compressed data can only be decompressed from the start
+            let mut zip = self.zip.borrow_mut();
+            let zip = zip.as_mut().expect("compressed entries only come from zip archives");
+            let mut file = zip.by_index(index).map_err(|e| zip_error(&self.archive, e))?;
+            io::copy(&mut (&mut file).take(offset), &mut io::sink())?;
+            let mut data = Vec::with_capacity(len.min((entry.len - offset) as usize));
+            file.take(len as u64).read_to_end(&mut data)?;
+            return Ok(data);
+        }
+        RealFs.read_range(&self.archive, entry.offset + offset, len.min((entry.len - offset) as usize))
+    }
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_fs_creates_parent_directories() {
+        let mut fs = MemoryFs::with_files(&["/card/DCIM/100GOPRO/GOPR0001.JPG"]);
+        fs.add_file_with_contents(Path::new("/card/notes.txt"), b"hello".to_vec(), None);
+
+        assert!(fs.metadata(Path::new("/card/DCIM")).unwrap().is_dir);
+        let mut entries: Vec<_> = fs.read_dir(Path::new("/card")).unwrap().into_iter().map(|entry| (entry.path, entry.is_dir)).collect();
+        entries.sort();
+        assert_eq!(entries, [(PathBuf::from("/card/DCIM"), true), (PathBuf::from("/card/notes.txt"), false)]);
+
+        assert_eq!(fs.read(Path::new("/card/notes.txt")).unwrap(), b"hello");
+        assert_eq!(fs.read_range(Path::new("/card/notes.txt"), 1, 3).unwrap(), b"ell");
+        assert_eq!(fs.metadata(Path::new("/card/notes.txt")).unwrap().len, 5);
+        assert!(fs.read_dir(Path::new("/card/notes.txt")).is_err());
+        assert!(!fs.exists(Path::new("/card/missing.txt")));
+    }
+
+    #[test]
+    fn memory_fs_canonicalizes_dots_away() {
+        let fs = MemoryFs::with_files(&["/card/DCIM/100GOPRO/GOPR0001.JPG"]);
+
+        assert_eq!(fs.canonicalize(Path::new("/card/./DCIM/../DCIM/100GOPRO/GOPR0001.JPG")).unwrap(), PathBuf::from("/card/DCIM/100GOPRO/GOPR0001.JPG"));
+        assert!(fs.canonicalize(Path::new("/card/DCIM/../GOPR0001.JPG")).is_err());
+    }
+
+    #[test]
+    fn memory_fs_keeps_fixed_metadata() {
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let mut fs = MemoryFs::default();
+        fs.add_file_with_metadata(Path::new("/card/DCIM/GOPR0001.JPG"), FsMetadata{ is_dir: true, len: 1234, modified: Some(modified), mode: Some(0o640), uid: Some(1000), gid: Some(100) });
+
+        let metadata = fs.metadata(Path::new("/card/DCIM/GOPR0001.JPG")).unwrap();
+        assert_eq!((metadata.is_dir, metadata.len, metadata.modified), (false, 1234, Some(modified)));
+        assert_eq!((metadata.mode, metadata.uid, metadata.gid), (Some(0o640), Some(1000), Some(100)));
+        assert!(fs.metadata(Path::new("/card/DCIM")).unwrap().is_dir);
+    }
+}
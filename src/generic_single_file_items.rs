@@ -18,7 +18,7 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
 
 use anyhow::{Result, anyhow};
-use crate::SourceMediaInterface;
+use crate::{SourceMediaInterface, HandlerOptions};
 use std::path::{PathBuf,Path};
 use crate::helpers::*;
 use crate::FileItem;
@@ -32,37 +32,93 @@ fn filetype(ext: &str) -> Result<JsonFileInfoTypes> {
         "jpg"  => Ok(JsonFileInfoTypes{ file_type:FileImage, item_type:ItemImage }),
         "png"  => Ok(JsonFileInfoTypes{ file_type:FileImage, item_type:ItemImage }),
         "mp4"  => Ok(JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemVideo }),
+        // 3gp/3gpp are the video container, phone recorders write the audio only variant as 3ga
+        "3gp"  => Ok(JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemVideo }),
+        "3gpp" => Ok(JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemVideo }),
         "wav"  => Ok(JsonFileInfoTypes{ file_type:FileAudio, item_type:ItemAudio }),
-        "3gpp" => Ok(JsonFileInfoTypes{ file_type:FileAudio, item_type:ItemAudio }),
+        "3ga"  => Ok(JsonFileInfoTypes{ file_type:FileAudio, item_type:ItemAudio }),
+        "amr"  => Ok(JsonFileInfoTypes{ file_type:FileAudio, item_type:ItemAudio }),
         _ => Err(anyhow!("unknown file extension {:?} trying to determain file type", ext)),
     }
 }
 
-impl SourceMediaInterface for GenericSingleFileItem {
-    fn list_thumbnail(&self, _source_media_location: &Path,  source_media_card: &Path, _known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
-        filter_dir(source_media_card,|_filename: &str, input_ext: Option<&str>, _path: &PathBuf, path_str: &str|{
-            let ext = input_ext.ok_or_else(|| anyhow!("Expected filter_dir to provide a file extension"))?;
-            let types = filetype(ext)?;
-            match types.file_type{
-                FileVideo | FileAudio => Ok(Some(create_part_file(path_str.to_string(), types, 1, 1, None))),
-                FileImage => Ok(Some(create_simple_file(path_str.to_string(), types, None)?)),
-                _ => Err(anyhow!("Unrecognised extension '{}' in file '{}'", ext, path_str)),
+/// The items in `dir`, and with the recursive option the ones in its subdirectories
+fn list_dir(dir: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+    let mut subdirs = Vec::new();
+    let list_file = |_filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str|{
+        let Some(ext) = input_ext else {
+            return unknown_file(path_str, options);
+        };
+        if options.first_frame_as_thumbnail && path_str.ends_with(".thumb.jpg") {
+            return Ok(None);
+        }
+        let Ok(types) = filetype(ext) else {
+            return unknown_file(path_str, options);
+        };
+        match types.file_type{
+            FileVideo if options.first_frame_as_thumbnail => {
+                let mut item = create_part_file(path_str.to_string(), types, 1, 1, None);
+                let thumbnail = generated_thumbnail_path(path);
+                item.needs_thumbnail_generation = Some(!options.fs.exists(&thumbnail));
+                item.generated_thumbnail = Some(thumbnail.to_string_lossy().into_owned());
+                Ok(Some(item))
             }
-        })
+            FileVideo | FileAudio => Ok(Some(create_part_file(path_str.to_string(), types, 1, 1, None))),
+            FileImage => Ok(Some(create_simple_file(path_str.to_string(), types, None)?)),
+            _ => Err(anyhow!("Unrecognised extension '{}' in file '{}'", ext, path_str)),
+        }
+    };
+    let mut items = match options.recursive {
+        true => filter_dir_and_subdirs(dir, options, &mut subdirs, list_file)?,
+        false => filter_dir(dir, options, list_file)?,
+    };
+
+    for subdir in subdirs {
+        items.extend(list_dir(&subdir, options)?);
+    }
+    Ok(items)
+}
+
+impl SourceMediaInterface for GenericSingleFileItem {
+    fn list_thumbnail(&self, _source_media_location: &Path,  source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
+        list_dir(source_media_card, options)
     }
-    fn list_high_quality(&self,  source_media_location: &Path,  source_media_card: &Path, known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
-        self.list_thumbnail(source_media_location, source_media_card, known_missing_files)
+    fn list_high_quality(&self,  source_media_location: &Path,  source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
+        self.list_thumbnail(source_media_location, source_media_card, options)
     }
-    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, _known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>>{
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>{
         let extension = get_extension_str(source_media_file)?;
         let types = filetype(extension)?;
-        match types.file_type{
-            FileVideo => Ok(vec![create_part_file(source_media_file.to_string_lossy().into_owned(), types, 1, 1, None)]),
-            FileImage => Ok(vec![create_simple_file(source_media_file.to_string_lossy().into_owned(), types, None)?]),
-            _ => Err(anyhow!("unexpected file type")),
-        }
+        let file = find_file(options.fs.as_ref(), source_media_file).unwrap_or_else(|| source_media_file.to_path_buf()).to_string_lossy().into_owned();
+        let items = match types.file_type{
+            FileVideo | FileAudio => vec![create_part_file(file, types, 1, 1, None)],
+            FileImage => vec![create_simple_file(file, types, None)?],
+            _ => return Err(anyhow!("unexpected file type")),
+        };
+        check_related(options.fs.as_ref(), source_media_file, items)
     }
     fn name(&self) -> &'static str {
         "Generic-Single-File-Items"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::memory_card;
+
+    #[test]
+    fn phone_recordings_are_typed_by_their_extension() {
+        let files = ["/card/a.3gp", "/card/b.3GPP", "/card/c.3ga", "/card/d.amr"];
+        let options = memory_card("/card", &files);
+        let expected = [("video", "video"), ("video", "video"), ("audio", "audio"), ("audio", "audio")];
+
+        let items = GenericSingleFileItem.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        let types: Vec<_> = items.iter().map(|item| (item.file_type.as_str(), item.item_type.as_str())).collect();
+        assert_eq!(types, expected);
+        for (file, expected) in files.iter().zip(expected) {
+            let items = GenericSingleFileItem.get_related(Path::new("/"), Path::new(file), &options).unwrap();
+            assert_eq!((items[0].file_type.as_str(), items[0].item_type.as_str()), expected, "{}", file);
+        }
+    }
+}
@@ -20,7 +20,7 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
 
 use anyhow::{Result, anyhow};
-use crate::SourceMediaInterface;
+use crate::{SourceMediaInterface, HandlerOptions};
 use std::path::{PathBuf,Path};
 use crate::helpers::*;
 use crate::FileItem;
@@ -35,44 +35,50 @@ const FILE_TYPES: JsonFileInfoTypes = JsonFileInfoTypes {
 };
 
 impl SourceMediaInterface for GNSSTrackerGeneric {
-    fn list_thumbnail(&self, _source_media_location: &Path,  source_media_card: &Path, _known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
-        filter_dir(source_media_card,|_filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str|{
-            let ext = input_ext.ok_or_else(|| anyhow!("Expected filter_dir to provide a file extension"))?;
+    fn list_thumbnail(&self, _source_media_location: &Path,  source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
+        filter_dir(source_media_card, options, |_filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str|{
+            let Some(ext) = input_ext else {
+                return unknown_file(path_str, options);
+            };
             match ext.to_lowercase().as_str() {
                 "gpx" => {
                     Ok(Some(create_simple_file(path_str.to_string(), FILE_TYPES, None)?))
                 }
                 "kml" => {
-                    if ! path.with_extension("gpx").exists() {
+                    if find_file(options.fs.as_ref(), &path.with_extension("gpx")).is_none() {
                         Ok(Some(create_simple_file(path_str.to_string(), FILE_TYPES, None)?))
                     }else{
                         Ok(None)
                     }
                 }
                 "txt" => {
-                    if ! path.with_extension("gpx").exists() && ! path.with_extension("kml").exists() {
+                    if find_file(options.fs.as_ref(), &path.with_extension("gpx")).is_none() && find_file(options.fs.as_ref(), &path.with_extension("kml")).is_none() {
                         Ok(Some(create_simple_file(path_str.to_string(), FILE_TYPES, None)?))
                     }else{
                         Ok(None)
                     }
                 }
-                _ => Err(anyhow!("Unrecognised extension '{}' in file '{}'", ext, path_str)),
+                _ => unknown_file(path_str, options),
             }
         })
     }
-    fn list_high_quality(&self,  source_media_location: &Path,  source_media_card: &Path, known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
-        self.list_thumbnail(source_media_location, source_media_card, known_missing_files)
+    fn list_high_quality(&self,  source_media_location: &Path,  source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
+        self.list_thumbnail(source_media_location, source_media_card, options)
     }
-    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, _known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>>{
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>{
+        let ext = get_extension_str(source_media_file)?;
+        if !["gpx", "kml", "txt"].contains(&ext.to_lowercase().as_str()) {
+            return Err(anyhow!("unexpected file extension '{}' in file '{:?}'", ext, source_media_file));
+        }
         let mut items = Vec::<FileItem>::new();
 
         for extension in ["gpx", "kml", "txt"]{
-            if let Ok(Some(item)) = create_simple_file_if_exists(&source_media_file.with_extension(extension), FILE_TYPES, None) {
+            if let Some(item) = create_simple_file_if_exists(options.fs.as_ref(), &source_media_file.with_extension(extension), FILE_TYPES, None)? {
                 items.push(item);
             }
         }
 
-        Ok(items)
+        check_related(options.fs.as_ref(), source_media_file, items)
     }
     fn name(&self) -> &'static str {
         "GNSS-Tracker-Generic"
@@ -20,8 +20,9 @@
 
 use anyhow::{Result, anyhow, Context};
 use bitflags::bitflags;
-use crate::SourceMediaInterface;
+use crate::{SourceMediaInterface, HandlerOptions, PartsPolicy};
 use std::path::{PathBuf,Path};
+use std::collections::HashMap;
 use crate::helpers::*;
 use crate::FileItem;
 use crate::helpers::ItemType::*;
@@ -31,25 +32,36 @@ use crate::helpers::FileType::*;
 //       GoPro Specific helpers       //
 ////////////////////////////////////////
 
-fn get_gopro_video_part_id(filename:String) -> Result<u8> {
-    return match filename.as_str().get(2..4).ok_or_else(|| anyhow!("Couldn't parse gorpo video part id"))?.parse::<u8>() {
-        Ok(p) => Ok(p),
-        Err(e) => { return Err(anyhow!("Error parsing filename: {}", e)); }
-    };
+const GOPRO_VIDEO_NAME: PartNameScheme = PartNameScheme{ part_pos:2, part_width:2, media_id_pos:4, media_id_len:None };
+/// GoPro cameras number parts with two digits and are not expected to ever go past them
+const GOPRO_MAX_PARTS: u8 = 99;
+
+fn get_gopro_video_part_id(file:&Path) -> Result<u8> {
+    GOPRO_VIDEO_NAME.part_num(file)
 }
 
 bitflags!{
     #[derive(PartialEq)]
-    struct GoProVideoFileType: u8 {
+    struct GoProVideoFileType: u16 {
         const LowBitrateVideo             = 1 << 0;
         const HighBitrateH265Video        = 1 << 1;
         const HighBitrateH264Video        = 1 << 2;
         const WavAudio                    = 1 << 3;
         const ThumbnailPhoto_of_H264Video = 1 << 4;
         const ThumbnailPhoto_of_H265Video = 1 << 5;
+        /// The .360 file of a MAX clip, with both lenses in their raw projection
+        const SphericalVideo              = 1 << 6;
+        /// An MP4 reframed from the .360 file, named after it
+        const ReframedVideo               = 1 << 7;
+        const ThumbnailPhoto_of_SphericalVideo = 1 << 8;
     }
 }
 
+/// The files of a part that each make it a MAX clip
+const SPHERICAL_CLIP_FILE_TYPES: GoProVideoFileType = GoProVideoFileType::SphericalVideo
+    .union(GoProVideoFileType::ReframedVideo)
+    .union(GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo);
+
 enum GoProPhotoFileType{
     JpegPhoto,
     RawPhoto,
@@ -75,16 +87,6 @@ fn create_gopro_photo_file(input_file:&Path, file_type: GoProPhotoFileType ) ->
 
 fn create_gopro_video_file(input_file:&Path, part:u8, file_type: &GoProVideoFileType ) -> Result<PathBuf> {
 
-    let input_filename = input_file.file_name().ok_or_else(|| anyhow!("Couldn't get filename of reference photo file"))?.to_string_lossy();
-
-    let (name, _) = input_filename.rsplit_once('.').ok_or_else(|| anyhow!("Failed to split gopro style filename from it's extension {:?}", input_filename))?;
-
-    if name.len() < 5 { // minimal length, GX/L + NN + One character media id
-        return Err(anyhow!("Input gopro style filename without the extension was not long enough {:?}", name));
-    }
-
-    let media_id = &name[4..];
-
     let new_prefix = match *file_type {
         GoProVideoFileType::LowBitrateVideo => Ok("GL"),
         GoProVideoFileType::HighBitrateH264Video => Ok("GH"),
@@ -92,11 +94,12 @@ fn create_gopro_video_file(input_file:&Path, part:u8, file_type: &GoProVideoFile
         GoProVideoFileType::WavAudio => Ok("GX"),
         GoProVideoFileType::ThumbnailPhoto_of_H264Video => Ok("GH"),
         GoProVideoFileType::ThumbnailPhoto_of_H265Video => Ok("GX"),
+        GoProVideoFileType::SphericalVideo => Ok("GS"),
+        GoProVideoFileType::ReframedVideo => Ok("GS"),
+        GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo => Ok("GS"),
         _ => Err(anyhow!("expected one and only one type")),
     }?;
 
-    let new_part = format!("{:02}", part);
-
     let new_extension = match *file_type {
         GoProVideoFileType::LowBitrateVideo => Ok("LRV"),
         GoProVideoFileType::HighBitrateH264Video => Ok("MP4"),
@@ -104,12 +107,13 @@ fn create_gopro_video_file(input_file:&Path, part:u8, file_type: &GoProVideoFile
         GoProVideoFileType::WavAudio => Ok("WAV"),
         GoProVideoFileType::ThumbnailPhoto_of_H264Video => Ok("THM"),
         GoProVideoFileType::ThumbnailPhoto_of_H265Video => Ok("THM"),
+        GoProVideoFileType::SphericalVideo => Ok("360"),
+        GoProVideoFileType::ReframedVideo => Ok("MP4"),
+        GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo => Ok("THM"),
         _ => Err(anyhow!("expected one and only one type")),
     }?;
 
-    let input_dirname = input_file.parent().context("Couldn't get file's parent directory")?;
-
-    Ok(input_dirname.join(format!("{new_prefix}{new_part}{media_id}.{new_extension}")))
+    GOPRO_VIDEO_NAME.part_file(input_file, part, Some(new_prefix), new_extension)
 }
 
 pub struct GoProInterface;
@@ -118,33 +122,139 @@ pub struct GoProInterface;
 //         File parsing code          //
 ////////////////////////////////////////
 
-struct PartCount{
-    existing_parts_count:u8,
-    all_parts_count:u8,
+/// With `low_bitrate` a part that only has its LRV left counts as an existing part too
+fn count_gopro_parts<F>( base_file:&Path, options: &HandlerOptions, low_bitrate: bool, exists: F ) -> Result<PartCount> where
+    F: Fn(&Path) -> bool,
+{
+    GOPRO_VIDEO_NAME.count_parts(base_file, &options.known_missing_files, options.max_parts.unwrap_or(GOPRO_MAX_PARTS), |file, part| {
+        let mut files = vec![
+            create_gopro_video_file(file, part, &GoProVideoFileType::HighBitrateH265Video)?,
+            create_gopro_video_file(file, part, &GoProVideoFileType::HighBitrateH264Video)?,
+            create_gopro_video_file(file, part, &GoProVideoFileType::SphericalVideo)?,
+            create_gopro_video_file(file, part, &GoProVideoFileType::ReframedVideo)?,
+        ];
+        let lrv_file = create_gopro_video_file(file, part, &GoProVideoFileType::LowBitrateVideo)?;
+        if low_bitrate {
+            files.push(lrv_file);
+            Ok((files, vec![]))
+        } else {
+            Ok((files, vec![lrv_file]))
+        }
+    }, exists)
 }
 
-fn count_gopro_parts( base_file:&Path, known_missing_files: &[PathBuf] ) -> Result<PartCount> {
+/// Whether a part of the clip of `file` has its MP4 (or .360) on disk, or with `low_bitrate` at
+/// least its LRV
+fn part_has_video(file:&Path, part:u8, listing:&DirListing, low_bitrate: bool) -> Result<bool> {
+    Ok(listing.contains(&create_gopro_video_file(file, part, &GoProVideoFileType::HighBitrateH264Video)?) ||
+       listing.contains(&create_gopro_video_file(file, part, &GoProVideoFileType::HighBitrateH265Video)?) ||
+       listing.contains(&create_gopro_video_file(file, part, &GoProVideoFileType::SphericalVideo)?) ||
+       listing.contains(&create_gopro_video_file(file, part, &GoProVideoFileType::ReframedVideo)?) ||
+       (low_bitrate && listing.contains(&create_gopro_video_file(file, part, &GoProVideoFileType::LowBitrateVideo)?)))
+}
 
-    let mut parts:PartCount = PartCount{existing_parts_count:0, all_parts_count:0};
+/// Part number of `file` among the parts of its clip that exist, as used for part_num
+fn existing_part_num(file:&Path, listing:&DirListing, low_bitrate: bool) -> Result<u8> {
+    let mut part_num = 1;
+    for n in 1..get_gopro_video_part_id(file)? {
+        if part_has_video(file, n, listing, low_bitrate)? {
+            part_num += 1;
+        }
+    }
+    Ok(part_num)
+}
 
-    for part in 1..=99 {
+/// A part is accounted for if at least one of its H264, H265, .360 or LRV files exists or is a
+/// known missing file
+fn check_part_accounted_for(file:&Path, part:u8, listing:&DirListing, options:&HandlerOptions) -> Result<()> {
+    let accounted_for = [GoProVideoFileType::HighBitrateH264Video, GoProVideoFileType::HighBitrateH265Video, GoProVideoFileType::SphericalVideo,
+                         GoProVideoFileType::ReframedVideo, GoProVideoFileType::LowBitrateVideo].iter()
+        .map(|file_type| create_gopro_video_file(file, part, file_type))
+        .collect::<Result<Vec<_>>>()?.iter()
+        .any(|file| listing.contains(file) || options.known_missing_files.contains(file));
+    if ! accounted_for {
+        // Every part has an LRV, so that's the file to blame
+        return Err(anyhow::Error::new(ExpectedFileMissing(create_gopro_video_file(file, part, &GoProVideoFileType::LowBitrateVideo)?))
+            .context(format!("Part {} of the clip of {:?} is missing and not listed as a known missing file", part, file)));
+    }
+    Ok(())
+}
 
-        let file_h265 = create_gopro_video_file(base_file, part, &GoProVideoFileType::HighBitrateH265Video)?;
-        let file_h264 = create_gopro_video_file(base_file, part, &GoProVideoFileType::HighBitrateH264Video)?;
+/// Some firmware saves frame grabs of a video as a JPG named like the video's files. Those are
+/// told apart from photos by a THM of the same part of the same video existing next to them
+fn is_video_frame_grab<F>(file:&Path, exists: F) -> bool where
+    F: Fn(&Path) -> bool,
+{
+    let Ok(part) = get_gopro_video_part_id(file) else {
+        return false;
+    };
+    [GoProVideoFileType::ThumbnailPhoto_of_H265Video, GoProVideoFileType::ThumbnailPhoto_of_H264Video].iter()
+        .any(|file_type| create_gopro_video_file(file, part, file_type).is_ok_and(|thumbnail| exists(&thumbnail)))
+}
 
-        if file_h264.exists() || file_h265.exists() {
-            parts.existing_parts_count+=1;
-            parts.all_parts_count+=1;
-        }else if known_missing_files.contains(&file_h264) || known_missing_files.contains(&file_h265) {
-            parts.all_parts_count+=1;
-        }else if part == 0 {
-            return Err(anyhow!("Iniital video file not found"));
-        }else{
-            break;
-        }
+/// A WAV is the audio of the video it's named after, unless none of that video's MP4, LRV and THM
+/// files exist or are known missing. Then it's a standalone audio recording, like a voice memo
+fn is_standalone_audio<F>(file:&Path, known_missing_files:&[PathBuf], exists: F) -> bool where
+    F: Fn(&Path) -> bool,
+{
+    let Ok(part) = get_gopro_video_part_id(file) else {
+        return true;
+    };
+    ![GoProVideoFileType::HighBitrateH264Video, GoProVideoFileType::HighBitrateH265Video, GoProVideoFileType::LowBitrateVideo,
+      GoProVideoFileType::ThumbnailPhoto_of_H264Video, GoProVideoFileType::ThumbnailPhoto_of_H265Video,
+      GoProVideoFileType::SphericalVideo, GoProVideoFileType::ReframedVideo, GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo].iter()
+        .filter_map(|file_type| create_gopro_video_file(file, part, file_type).ok())
+        .any(|video_file| exists(&video_file) || known_missing_files.contains(&video_file))
+}
+
+/// A MAX clip reframed to an MP4 has the same name as its .360 file, which is the higher quality
+/// representation of the same recording
+fn is_reframed_export<F>(file:&Path, exists: F) -> bool where
+    F: Fn(&Path) -> bool,
+{
+    GOPRO_VIDEO_NAME.part_num(file).is_ok_and(|part| {
+        create_gopro_video_file(file, part, &GoProVideoFileType::ReframedVideo).is_ok_and(|reframed| reframed == file) &&
+        create_gopro_video_file(file, part, &GoProVideoFileType::SphericalVideo).is_ok_and(|spherical| exists(&spherical))
+    })
+}
+
+/// The video a THM is the thumbnail of, the .360 file for MAX clips that have one
+fn thumbnail_video<F>(thumbnail:&Path, exists: F) -> PathBuf where
+    F: Fn(&Path) -> bool,
+{
+    let spherical = thumbnail.with_extension("360");
+    if exists(&spherical) {
+        spherical
+    } else {
+        thumbnail.with_extension("MP4")
     }
+}
+
+const STANDALONE_AUDIO: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileAudio, item_type:ItemAudio };
+/// A frame grab is another still of the video next to its THM, see is_video_frame_grab
+const FRAME_GRAB: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileImagePreview, item_type:ItemVideo };
+
+/// Burst, time lapse and continuous photo modes name their photos G<group><frame>.JPG, with a 3
+/// digit group number and a 4 digit frame counter like G0010001.JPG. For such names this is the
+/// "G<group>" prefix the photos of the same burst share
+fn get_burst_group(file:&Path) -> Option<&str> {
+    let name = file.file_stem()?.to_str()?;
+    if name.len() == 8 && name.starts_with('G') && name.bytes().skip(1).all(|c| c.is_ascii_digit()) {
+        Some(&name[..4])
+    } else {
+        None
+    }
+}
 
-    Ok(parts)
+/// The photos of the burst `file` is part of, in the order they were taken
+fn burst_frames(file:&Path, group:&str, options:&HandlerOptions) -> Result<Vec<PathBuf>> {
+    let dir = file.parent().context("Couldn't get file's parent directory")?;
+    let mut frames: Vec<PathBuf> = options.fs.read_dir(dir)?.into_iter()
+        .filter(|entry| !entry.is_dir && get_burst_group(&entry.path) == Some(group) && get_extension_str(&entry.path).is_ok_and(|ext| ext.eq_ignore_ascii_case("JPG")))
+        .map(|entry| entry.path)
+        .collect();
+    frames.sort();
+    Ok(frames)
 }
 
 fn filetype(ext: &str) -> Result<JsonFileInfoTypes> {
@@ -153,6 +263,7 @@ fn filetype(ext: &str) -> Result<JsonFileInfoTypes> {
         "MP4" => Ok(JsonFileInfoTypes{ file_type:FileVideo,        item_type:ItemVideo }),
         "LRV" => Ok(JsonFileInfoTypes{ file_type:FileVideoPreview, item_type:ItemVideo }),
         "WAV" => Ok(JsonFileInfoTypes{ file_type:FileAudio,        item_type:ItemVideo }),
+        "360" => Ok(JsonFileInfoTypes{ file_type:FileVideoRaw,     item_type:ItemVideo }),
 
         "JPG" => Ok(JsonFileInfoTypes{ file_type:FileImage,        item_type:ItemImage }),
         "GPR" => Ok(JsonFileInfoTypes{ file_type:FileImageRaw,     item_type:ItemImage }),
@@ -160,121 +271,394 @@ fn filetype(ext: &str) -> Result<JsonFileInfoTypes> {
     }
 }
 
-impl SourceMediaInterface for GoProInterface {
-    //TODO: handle case where the thumbnail is in the known missing files and the item needs to be
-    //represented by something else
-    fn list_thumbnail( &self, _source_media_location: &Path, source_media_card: &Path, known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>> {
-        filter_dir(source_media_card, |filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str| {
-            let ext = input_ext.ok_or_else(|| anyhow!("Expected filter_dir to porivde a file extension"))?;
-            match ext {
-                "THM" => {
-                    let part_id = get_gopro_video_part_id(filename.to_string())?;
-                    if part_id != 1 {
-                        for n in 1..part_id{
-                            let n_file = create_gopro_video_file(path, n, &GoProVideoFileType::LowBitrateVideo)?; // TODO: It could be the case that we are missing the LRV but the MP4 is there in which case it's better to return a high quality equivelant of the first part of the video than either a later low quality or none at all
-                            if ! known_missing_files.contains(&n_file){
-                                return Ok(None);
-                            }
-                        }
-                    }
+/// Run the file name parsers on an arbitrary file, for the fuzz target. They are expected to
+/// return an error for names they can't handle instead of panicking
+#[cfg(fuzzing)]
+pub fn fuzz_file_name_parsers(file: &Path) {
+    let part = get_gopro_video_part_id(file).unwrap_or(1);
+    for file_type in [GoProVideoFileType::LowBitrateVideo, GoProVideoFileType::HighBitrateH265Video, GoProVideoFileType::HighBitrateH264Video,
+                      GoProVideoFileType::WavAudio, GoProVideoFileType::ThumbnailPhoto_of_H264Video, GoProVideoFileType::ThumbnailPhoto_of_H265Video,
+                      GoProVideoFileType::SphericalVideo, GoProVideoFileType::ReframedVideo, GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo] {
+        let _ = create_gopro_video_file(file, part, &file_type);
+    }
+    let _ = create_gopro_photo_file(file, GoProPhotoFileType::JpegPhoto);
+    let _ = create_gopro_photo_file(file, GoProPhotoFileType::RawPhoto);
+    let _ = GoProInterface.capture_sequence(file);
+}
 
-                    let ret = create_simple_file(path_str.to_string(), filetype(ext)?, Some(path.with_extension("MP4").to_string_lossy().into_owned()))?;
+/// A burst is listed as a single image item represented by its first photo. `first_burst_frames`
+/// remembers the first photo of every burst seen so far so the directory is read once per burst
+fn burst_representative(file:&Path, group:&str, first_burst_frames:&mut HashMap<String, Option<PathBuf>>, options:&HandlerOptions) -> Result<Option<FileItem>> {
+    if !first_burst_frames.contains_key(group) {
+        first_burst_frames.insert(group.to_string(), burst_frames(file, group, options)?.into_iter().next());
+    }
+    if first_burst_frames[group].as_deref() != Some(file) {
+        return Ok(None);
+    }
+    Ok(Some(create_simple_file(file.to_string_lossy().into_owned(), filetype("JPG")?, None)?))
+}
 
-                    Ok(Some(ret))
-                }
-                "JPG" => Ok(Some(create_simple_file(path_str.to_string(), filetype(ext)?, None)?)),
-                "MP4" | "GPR" | "LRV" | "WAV" => Ok(None),
-                _ => Err(anyhow!("Unexpected file {}", path_str)),
+/// Cameras move on to a new NNNGOPRO folder, like 100GOPRO then 101GOPRO, once one fills up
+fn is_roll_folder(name: &str) -> bool {
+    name.len() == 8 && name.is_char_boundary(3) && name[..3].bytes().all(|b| b.is_ascii_digit()) && name[3..].eq_ignore_ascii_case("GOPRO")
+}
+
+/// Whether a card entry is one of the directories media_dirs goes into on its own, which is never
+/// the case without the recursive option
+fn is_media_dir_container(path: &Path, options: &HandlerOptions) -> bool {
+    options.recursive
+        && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| is_roll_folder(name) || name.eq_ignore_ascii_case("DCIM"))
+        && options.fs.metadata(path).is_ok_and(|metadata| metadata.is_dir)
+}
+
+/// The directories to list the media of a card from: the card itself and, with the recursive
+/// option, every roll folder in it or in its DCIM folder in order. A clip never spans two roll
+/// folders so each one is listed on its own
+fn media_dirs(card: &Path, options: &HandlerOptions) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![card.to_path_buf()];
+    if !options.recursive {
+        return Ok(dirs);
+    }
+    let mut roll_folders = Vec::new();
+    for parent in [Some(card.to_path_buf()), find_dir(options.fs.as_ref(), card, "DCIM")].into_iter().flatten() {
+        for entry in dir_fs_entries(&parent, options)? {
+            if entry.is_dir && entry.path.file_name().and_then(|name| name.to_str()).is_some_and(is_roll_folder) {
+                roll_folders.push(entry.path);
             }
-        })
-    }
-    fn list_high_quality( &self, _source_media_location: &Path, source_media_card: &Path, known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>> {
-        filter_dir(source_media_card,|filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str|{
-            let ext = input_ext.ok_or_else(|| anyhow!("Expected filter_dir to porivde a file extension"))?;
-            match ext {
-                "MP4" => {
-                    let part_id = get_gopro_video_part_id(filename.to_string())?;
-                    if part_id != 1 {
-                        for n in 1..part_id{
-                            let h264_file = create_gopro_video_file(path, n, &GoProVideoFileType::HighBitrateH264Video)?;
-                            let h265_file = create_gopro_video_file(path, n, &GoProVideoFileType::HighBitrateH264Video)?;
-                            if ! known_missing_files.contains(&h265_file)|| ! known_missing_files.contains(&h264_file){ //TODO: Same warning as in list_thumbnail about missing files
-                                return Ok(None);
-                            }
-                        }
-                    }
+        }
+    }
+    roll_folders.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    dirs.extend(roll_folders);
+    Ok(dirs)
+}
 
-                    let part_count = count_gopro_parts(path, &known_missing_files)?;
+/// Whether a THM, LRV or MP4 of a clip is of the part that represents the clip in the thumbnail
+/// list with the "first" parts policy: the first part, or a later one when the LRVs of all the
+/// parts before it are known missing files and none of them is listed through its video instead
+fn is_first_listed_part(file:&Path, options:&HandlerOptions) -> Result<bool> {
+    for n in 1..get_gopro_video_part_id(file)? {
+        let part = create_gopro_video_file(file, n, &GoProVideoFileType::LowBitrateVideo)?;
+        if !options.known_missing_files.contains(&part) {
+            return Ok(false);
+        }
+        let exists = |file: &Path| find_file(options.fs.as_ref(), file).is_some();
+        if let Some(thumbnail) = known_missing_thumbnail(&part, options)? && exists(&thumbnail_video(&thumbnail, exists)) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
 
-                    let ret = create_part_file(path_str.to_string(), filetype(ext)?, part_count.existing_parts_count, 1, Some(path_str.to_string()));
+/// The THM of the part of `file` when it's a known missing file and no other THM of the part
+/// exists, so the part has no thumbnail to be listed through
+fn known_missing_thumbnail(file:&Path, options:&HandlerOptions) -> Result<Option<PathBuf>> {
+    let part = get_gopro_video_part_id(file)?;
+    let thumbnails = [GoProVideoFileType::ThumbnailPhoto_of_H265Video, GoProVideoFileType::ThumbnailPhoto_of_H264Video, GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo].iter()
+        .map(|file_type| create_gopro_video_file(file, part, file_type))
+        .collect::<Result<Vec<_>>>()?;
+    if thumbnails.iter().any(|thumbnail| find_file(options.fs.as_ref(), thumbnail).is_some()) {
+        return Ok(None);
+    }
+    Ok(thumbnails.into_iter().find(|thumbnail| options.known_missing_files.contains(thumbnail)))
+}
 
-                    Ok(Some(ret))
-                }
-                "GPR" | "JPG" => {
-                    if ext == "GPR" || !create_gopro_photo_file(path, GoProPhotoFileType::RawPhoto)?.exists() {
-                        return Ok(Some(create_simple_file(path_str.to_string(), filetype(ext)?, None)?));
-                    }
-                    Ok(None)
-                }
-                "THM" | "LRV" | "WAV" => Ok(None),
-                _ => Err(anyhow!("Unexpected file {}", path_str)),
-            }
-        })
+/// A part whose THM is a known missing file is listed through its LRV in the thumbnail list
+/// instead, or through its video if that's gone too
+fn thumbnail_fallback(path:&Path, path_str:&str, ext:&str, dir:&Path, options:&HandlerOptions) -> Result<Option<FileItem>> {
+    let Some(thumbnail) = known_missing_thumbnail(path, options)? else {
+        return Ok(None);
+    };
+    if options.parts_policy == PartsPolicy::First && !is_first_listed_part(path, options)? {
+        return Ok(None);
+    }
+    let exists = |file: &Path| find_file(options.fs.as_ref(), file).is_some();
+    let video = thumbnail_video(&thumbnail, exists);
+    if ext != "LRV" && (path != video || exists(&create_gopro_video_file(path, get_gopro_video_part_id(path)?, &GoProVideoFileType::LowBitrateVideo)?)) {
+        return Ok(None);
     }
-    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>>{
-        let mut items = Vec::<FileItem>::new();
 
-        let ext = get_extension_str(source_media_file)?;
+    let listing = DirListing::read(options.fs.as_ref(), dir)?;
+    let part_count = count_gopro_parts(path, options, options.count_low_bitrate_parts, |file| listing.contains(file))?;
+    let part_num = existing_part_num(path, &listing, options.count_low_bitrate_parts)?;
+    Ok(Some(create_part_file(path_str.to_string(), filetype(ext)?, part_count.existing_parts_count, part_num, Some(video.to_string_lossy().into_owned()))))
+}
 
+fn list_thumbnail_dir(dir: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+    let listing = DirListing::read(options.fs.as_ref(), dir)?;
+    let mut first_burst_frames = HashMap::new();
+    let items = filter_dir(dir, options, |_filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str| {
+        if is_media_dir_container(path, options) {
+            return Ok(None);
+        }
+        let Some(ext) = input_ext else {
+            return unknown_file(path_str, options);
+        };
         match ext {
-            "THM"|"MP4"|"WAV"|"LRV" => {
+            "THM" if options.parts_policy == PartsPolicy::All => {
+                let part_count = count_gopro_parts(path, options, options.count_low_bitrate_parts, |file| listing.contains(file))?;
+                let part_num = existing_part_num(path, &listing, options.count_low_bitrate_parts)?;
 
-                let part_count = count_gopro_parts(source_media_file, &known_missing_files)?;
+                Ok(Some(create_part_file(path_str.to_string(), filetype(ext)?, part_count.existing_parts_count, part_num, Some(thumbnail_video(path, |file| listing.contains(file)).to_string_lossy().into_owned()))))
+            }
+            "THM" => {
+                if !is_first_listed_part(path, options)? {
+                    return Ok(None);
+                }
+                // Only counted to catch recordings with more parts than there can be
+                count_gopro_parts(path, options, options.count_low_bitrate_parts, |file| listing.contains(file))?;
 
-                let mut existing_part_number:u8 = 1;
-                for part in 1..=part_count.all_parts_count {
+                let ret = create_simple_file(path_str.to_string(), filetype(ext)?, Some(thumbnail_video(path, |file| listing.contains(file)).to_string_lossy().into_owned()))?;
 
-                    let file_types = [
-                        GoProVideoFileType::HighBitrateH264Video,
-                        GoProVideoFileType::HighBitrateH265Video,
-                        GoProVideoFileType::LowBitrateVideo,
-                        GoProVideoFileType::ThumbnailPhoto_of_H265Video,
-                        GoProVideoFileType::ThumbnailPhoto_of_H264Video,
-                        GoProVideoFileType::WavAudio,
-                    ];
+                Ok(Some(ret))
+            }
+            "JPG" if is_video_frame_grab(path, |file| listing.contains(file)) => Ok(None),
+            "JPG" if let Some(group) = get_burst_group(path) => burst_representative(path, group, &mut first_burst_frames, options),
+            "JPG" => Ok(Some(create_simple_file(path_str.to_string(), filetype(ext)?, None)?)),
+            "WAV" if is_standalone_audio(path, &options.known_missing_files, |file| listing.contains(file)) =>
+                Ok(Some(create_part_file(path_str.to_string(), STANDALONE_AUDIO, 1, 1, None))),
+            "MP4" | "360" | "LRV" => thumbnail_fallback(path, path_str, ext, dir, options),
+            "GPR" | "WAV" => Ok(None),
+            _ => unknown_file(path_str, options),
+        }
+    })?;
 
-                    let mut found_types = GoProVideoFileType::empty();
+    for thumbnail in options.known_missing_files.iter().filter(|file| file.parent() == Some(dir) && get_extension_str(file).is_ok_and(|ext| ext.eq_ignore_ascii_case("THM"))) {
+        if known_missing_thumbnail(thumbnail, options)?.is_none() || (options.parts_policy == PartsPolicy::First && !is_first_listed_part(thumbnail, options)?) {
+            continue;
+        }
+        let lrv = create_gopro_video_file(thumbnail, get_gopro_video_part_id(thumbnail)?, &GoProVideoFileType::LowBitrateVideo)?;
+        if !listing.contains(&lrv) && !listing.contains(&thumbnail_video(thumbnail, |file| listing.contains(file))) {
+            options.scan_warnings.borrow_mut().push(format!("Known missing thumbnail {:?} has no LRV or MP4 left to stand in for it, leaving the video out", thumbnail));
+        }
+    }
 
-                    for file_type_enum in file_types {
-                        let file = create_gopro_video_file(source_media_file, part, &file_type_enum)?;
-                        let extension = get_extension_str(&file)?;
+    Ok(items)
+}
 
-                        if let Some(item) = create_part_file_if_exists(&file, filetype(extension)?, part_count.existing_parts_count, existing_part_number, None) {
-                            items.push(item);
-                            found_types |= file_type_enum;
-                        }else if known_missing_files.contains(&file){
-                            found_types |= file_type_enum;
+/// With the LRV fallback, a clip that has no MP4 on disk for any of its parts is represented in
+/// list_high_quality by its LRVs, the same way it would be by its MP4s
+fn lrv_fallback(path:&Path, path_str:&str, listing:&DirListing, options:&HandlerOptions) -> Result<Option<FileItem>> {
+    for n in 1..=options.max_parts.unwrap_or(GOPRO_MAX_PARTS) {
+        if part_has_video(path, n, listing, false)? {
+            return Ok(None);
+        }
+    }
+    for n in 1..get_gopro_video_part_id(path)? {
+        if part_has_video(path, n, listing, true)? {
+            if options.parts_policy == PartsPolicy::All {
+                continue;
+            }
+            return Ok(None);
+        }
+        check_part_accounted_for(path, n, listing, options)?;
+    }
+
+    let part_count = count_gopro_parts(path, options, true, |file| listing.contains(file))?;
+    let part_num = existing_part_num(path, listing, true)?;
+
+    let mut ret = create_part_file(path_str.to_string(), filetype("LRV")?, part_count.existing_parts_count, part_num, Some(path_str.to_string()));
+    ret.degraded = Some(true);
+    Ok(Some(ret))
+}
+
+fn list_high_quality_dir(dir: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+    let listing = DirListing::read(options.fs.as_ref(), dir)?;
+    let mut first_burst_frames = HashMap::new();
+    filter_dir(dir, options, |_filename: &str, input_ext: Option<&str>, path: &PathBuf, path_str: &str|{
+        if is_media_dir_container(path, options) {
+            return Ok(None);
+        }
+        let Some(ext) = input_ext else {
+            return unknown_file(path_str, options);
+        };
+        match ext {
+            "MP4" if is_reframed_export(path, |file| listing.contains(file)) => Ok(None),
+            "MP4" | "360" => {
+                // A chaptered clip is represented by the MP4 of its earliest part that has one
+                // on disk. Every part before this one must be accounted for, meaning at least
+                // one of its H264, H265 or LRV files exists or is a known missing file. If an
+                // earlier part has its MP4 on disk, that part represents the clip and this one
+                // is skipped. An earlier part that isn't accounted for at all means files of
+                // the clip are missing without being listed in the errata.
+                // With the "all" parts policy every part with an MP4 is listed instead.
+                let part_id = get_gopro_video_part_id(path)?;
+                for n in 1..part_id {
+                    if part_has_video(path, n, &listing, false)? {
+                        if options.parts_policy == PartsPolicy::All {
+                            continue;
                         }
+                        return Ok(None);
                     }
-                    if found_types != GoProVideoFileType::empty() {
-                        existing_part_number+=1;
-                    }
-                    if ! (found_types.contains(GoProVideoFileType::HighBitrateH264Video) ^ found_types.contains(GoProVideoFileType::HighBitrateH265Video) ){
-                        return Err(anyhow!("expected either an H265 GX video or an H264 GL video. Got either both or none"));
-                    }
-                    if ! (found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H264Video) ^ found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H265Video)) {
-                        return Err(anyhow!("expected either an H265 GX video thumbnail or an H264 GL video thumbnail. Got either both or none"));
-                    }
-                    if ! found_types.contains(GoProVideoFileType::LowBitrateVideo){
-                        return Err(anyhow!("expected a low bitrate LRV video file"));
+                    check_part_accounted_for(path, n, &listing, options)?;
+                }
+
+                let part_count = count_gopro_parts(path, options, options.count_low_bitrate_parts, |file| listing.contains(file))?;
+                let part_num = existing_part_num(path, &listing, options.count_low_bitrate_parts)?;
+
+                let ret = create_part_file(path_str.to_string(), filetype(ext)?, part_count.existing_parts_count, part_num, Some(path_str.to_string()));
+
+                Ok(Some(ret))
+            }
+            // Listing every part with parts that only have their LRV left counted as existing,
+            // those parts are listed through their LRV and marked as degraded
+            "LRV" if options.count_low_bitrate_parts && options.parts_policy == PartsPolicy::All => {
+                let part_id = get_gopro_video_part_id(path)?;
+                if part_has_video(path, part_id, &listing, false)? {
+                    return Ok(None);
+                }
+                for n in 1..part_id {
+                    if ! part_has_video(path, n, &listing, true)? {
+                        check_part_accounted_for(path, n, &listing, options)?;
                     }
                 }
-            },
+
+                let part_count = count_gopro_parts(path, options, true, |file| listing.contains(file))?;
+                let part_num = existing_part_num(path, &listing, true)?;
+
+                let mut ret = create_part_file(path_str.to_string(), filetype(ext)?, part_count.existing_parts_count, part_num, Some(path_str.to_string()));
+                ret.degraded = Some(true);
+
+                Ok(Some(ret))
+            }
+            "LRV" if options.lrv_fallback => lrv_fallback(path, path_str, &listing, options),
+            "JPG" if is_video_frame_grab(path, |file| listing.contains(file)) => Ok(None),
+            "JPG" if let Some(group) = get_burst_group(path) => burst_representative(path, group, &mut first_burst_frames, options),
+            "GPR" | "JPG" => {
+                if ext == "GPR" || !listing.contains(&create_gopro_photo_file(path, GoProPhotoFileType::RawPhoto)?) {
+                    return Ok(Some(create_simple_file(path_str.to_string(), filetype(ext)?, None)?));
+                }
+                Ok(None)
+            }
+            "WAV" if is_standalone_audio(path, &options.known_missing_files, |file| listing.contains(file)) =>
+                Ok(Some(create_part_file(path_str.to_string(), STANDALONE_AUDIO, 1, 1, None))),
+            "THM" | "LRV" | "WAV" => Ok(None),
+            _ => unknown_file(path_str, options),
+        }
+    })
+}
+
+/// The files of every part of the video clip `source_media_file` belongs to, adding them to
+/// `items`
+fn video_related(source_media_file:&Path, options:&HandlerOptions, items:&mut Vec<FileItem>) -> Result<()> {
+    // Every file of every part is in the same directory, so it's read once instead of
+    // checking for each candidate file on its own
+    let dir = source_media_file.parent().ok_or_else(|| anyhow!("Couldn't get file's parent directory"))?;
+    let listing = DirListing::read(options.fs.as_ref(), dir)?;
+
+    let part_count = count_gopro_parts(source_media_file, options, options.count_low_bitrate_parts, |file| listing.contains(file))?;
+
+    let mut existing_part_number:u8 = 1;
+    for part in 1..=part_count.all_parts_count {
+
+        let file_types = [
+            GoProVideoFileType::HighBitrateH264Video,
+            GoProVideoFileType::HighBitrateH265Video,
+            GoProVideoFileType::LowBitrateVideo,
+            GoProVideoFileType::ThumbnailPhoto_of_H265Video,
+            GoProVideoFileType::ThumbnailPhoto_of_H264Video,
+            GoProVideoFileType::SphericalVideo,
+            GoProVideoFileType::ReframedVideo,
+            GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo,
+            GoProVideoFileType::WavAudio,
+        ];
+
+        let mut found_types = GoProVideoFileType::empty();
+
+        for file_type_enum in file_types {
+            let file = create_gopro_video_file(source_media_file, part, &file_type_enum)?;
+            let extension = get_extension_str(&file)?;
+
+            if let Some(found_file) = listing.find(&file) {
+                items.push(create_part_file(found_file.to_string_lossy().into_owned(), filetype(extension)?, part_count.existing_parts_count, existing_part_number, None));
+                found_types |= file_type_enum;
+            }else if options.known_missing_files.contains(&file){
+                found_types |= file_type_enum;
+            }
+        }
+        for thumbnail_type in [GoProVideoFileType::ThumbnailPhoto_of_H265Video, GoProVideoFileType::ThumbnailPhoto_of_H264Video] {
+            let thumbnail = create_gopro_video_file(source_media_file, part, &thumbnail_type)?;
+            if listing.contains(&thumbnail) && let Some(frame_grab) = listing.find(&thumbnail.with_extension("JPG")) {
+                items.push(create_part_file(frame_grab.to_string_lossy().into_owned(), FRAME_GRAB, part_count.existing_parts_count, existing_part_number, None));
+            }
+        }
+        if found_types != GoProVideoFileType::empty() {
+            existing_part_number+=1;
+        }
+        // When only one of the video and its thumbnail is missing, the codec of the other
+        // one tells which file it should have been
+        let missing = |file_type: GoProVideoFileType, error: &str| -> Result<()> {
+            Err(anyhow::Error::new(ExpectedFileMissing(create_gopro_video_file(source_media_file, part, &file_type)?)).context(error.to_string()))
+        };
+        if found_types.intersects(SPHERICAL_CLIP_FILE_TYPES) {
+            // A MAX clip needs its .360 file, or an MP4 reframed from it, and its
+            // thumbnail instead of an H264 or H265 video
+            if ! found_types.intersects(GoProVideoFileType::SphericalVideo | GoProVideoFileType::ReframedVideo) {
+                missing(GoProVideoFileType::SphericalVideo, "expected a .360 video file")?;
+            }
+            if ! found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo) {
+                missing(GoProVideoFileType::ThumbnailPhoto_of_SphericalVideo, "expected a GS video thumbnail")?;
+            }
+        } else {
+            if ! (found_types.contains(GoProVideoFileType::HighBitrateH264Video) ^ found_types.contains(GoProVideoFileType::HighBitrateH265Video) ){
+                let error = "expected either an H265 GX video or an H264 GL video. Got either both or none";
+                match (found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H264Video), found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H265Video)) {
+                    (true, false) if !found_types.contains(GoProVideoFileType::HighBitrateH264Video) => missing(GoProVideoFileType::HighBitrateH264Video, error)?,
+                    (false, true) if !found_types.contains(GoProVideoFileType::HighBitrateH265Video) => missing(GoProVideoFileType::HighBitrateH265Video, error)?,
+                    _ => return Err(anyhow!(error)),
+                }
+            }
+            if ! (found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H264Video) ^ found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H265Video)) {
+                let error = "expected either an H265 GX video thumbnail or an H264 GL video thumbnail. Got either both or none";
+                match (found_types.contains(GoProVideoFileType::HighBitrateH264Video), found_types.contains(GoProVideoFileType::HighBitrateH265Video)) {
+                    (true, false) if !found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H264Video) => missing(GoProVideoFileType::ThumbnailPhoto_of_H264Video, error)?,
+                    (false, true) if !found_types.contains(GoProVideoFileType::ThumbnailPhoto_of_H265Video) => missing(GoProVideoFileType::ThumbnailPhoto_of_H265Video, error)?,
+                    _ => return Err(anyhow!(error)),
+                }
+            }
+        }
+        if ! found_types.contains(GoProVideoFileType::LowBitrateVideo){
+            missing(GoProVideoFileType::LowBitrateVideo, "expected a low bitrate LRV video file")?;
+        }
+    }
+    Ok(())
+}
+
+impl SourceMediaInterface for GoProInterface {
+    fn list_thumbnail( &self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let mut items = Vec::new();
+        for dir in media_dirs(source_media_card, options)? {
+            items.extend(list_thumbnail_dir(&dir, options)?);
+        }
+        Ok(items)
+    }
+    fn list_high_quality( &self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let mut items = Vec::new();
+        for dir in media_dirs(source_media_card, options)? {
+            items.extend(list_high_quality_dir(&dir, options)?);
+        }
+        Ok(items)
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>{
+        let mut items = Vec::<FileItem>::new();
+
+        let ext = get_extension_str(source_media_file)?.to_ascii_uppercase();
+
+        match ext.as_str() {
+            "WAV" if is_standalone_audio(source_media_file, &options.known_missing_files, |file| find_file(options.fs.as_ref(), file).is_some()) => {
+                items.push(create_part_file(source_media_file.to_string_lossy().into_owned(), STANDALONE_AUDIO, 1, 1, None));
+            }
+            "THM"|"MP4"|"360"|"WAV"|"LRV" => video_related(source_media_file, options, &mut items)?,
+            "JPG" if is_video_frame_grab(source_media_file, |file| find_file(options.fs.as_ref(), file).is_some()) => video_related(source_media_file, options, &mut items)?,
+            "JPG" if let Some(group) = get_burst_group(source_media_file) => {
+                for frame in burst_frames(source_media_file, group, options)? {
+                    items.push(create_simple_file(frame.to_string_lossy().into_owned(), filetype("JPG")?, None)?);
+                }
+            }
             "JPG" | "GPR" => {
                 for file_type_enum in [GoProPhotoFileType::JpegPhoto, GoProPhotoFileType::RawPhoto] {
                     let file = create_gopro_photo_file(source_media_file, file_type_enum)?;
                     let extension = get_extension_str(&file)?;
-                    if let Some(v) = create_simple_file_if_exists(&file, filetype(extension)?, None)? {
+                    if let Some(v) = create_simple_file_if_exists(options.fs.as_ref(), &file, filetype(extension)?, None)? {
                         items.push(v);
                     }
                 }
@@ -283,10 +667,338 @@ impl SourceMediaInterface for GoProInterface {
                 return Err(anyhow!("Invalid input file"));
             }
         };
-        Ok(items)
+        check_related(options.fs.as_ref(), source_media_file, items)
     }
 
     fn name(&self) -> &'static str {
         "GoPro-Hero-Generic-1"
     }
+    // Videos and photos are numbered by the same counter: GX011234.MP4 and GOPR1235.JPG
+    fn capture_sequence(&self, file: &Path) -> Option<CaptureSequence> {
+        if let Some(group) = get_burst_group(file) {
+            return Some(CaptureSequence{ counter: "burst", number: group[1..].parse().ok()?, wrap: 1000, part: 0 });
+        }
+        let number = GOPRO_VIDEO_NAME.media_id(file).ok()?.parse::<u32>().ok()?;
+        let part = match get_extension_str(file).ok()?.to_ascii_uppercase().as_str() {
+            "JPG" | "GPR" => 0,
+            _ => get_gopro_video_part_id(file).ok()?,
+        };
+        Some(CaptureSequence{ counter: "media", number, wrap: 10000, part })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card};
+
+    const CHAPTERED_CLIP: [&str; 6] = ["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV",
+                                       "/card/GX020001.MP4", "/card/GX020001.THM", "/card/GL020001.LRV"];
+
+    #[test]
+    fn chaptered_clip_is_listed_once() {
+        let options = memory_card("/card", &CHAPTERED_CLIP);
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4"]);
+        assert_eq!(items[0].part_count, Some(2));
+
+        let items = GoProInterface.list_thumbnail(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.THM"]);
+        assert_eq!(items[0].metadata_file.as_deref(), Some("/card/GX010001.MP4"));
+    }
+
+    /// A card with a recording of `parts` parts
+    fn chaptered_card(parts: u8) -> HandlerOptions {
+        let files: Vec<String> = (1..=parts).flat_map(|part| [format!("/card/GX{part:02}0001.MP4"), format!("/card/GX{part:02}0001.THM"), format!("/card/GL{part:02}0001.LRV")]).collect();
+        memory_card("/card", &files.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn recordings_with_more_than_max_parts_are_an_error() {
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+        // Every part number the two digits fit is a recording the camera can make
+        for (parts, max_parts) in [(GOPRO_MAX_PARTS, None), (3, Some(3))] {
+            let mut options = chaptered_card(parts);
+            options.max_parts = max_parts;
+            let items = GoProInterface.list_high_quality(location, dir, &options).unwrap();
+            assert_eq!(items[0].part_count, Some(parts));
+            GoProInterface.list_thumbnail(location, dir, &options).unwrap();
+            assert_eq!(GoProInterface.get_related(location, Path::new("/card/GX010001.MP4"), &options).unwrap().len(), 3 * parts as usize);
+        }
+
+        let mut options = chaptered_card(4);
+        options.max_parts = Some(3);
+        assert!(GoProInterface.list_high_quality(location, dir, &options).is_err());
+        assert!(GoProInterface.list_thumbnail(location, dir, &options).is_err());
+        assert!(GoProInterface.get_related(location, Path::new("/card/GX010001.MP4"), &options).is_err());
+    }
+
+    #[test]
+    fn parts_policy_lists_the_first_or_every_part() {
+        let mut options = chaptered_card(3);
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4"]);
+        assert_eq!((items[0].part_num, items[0].part_count), (Some(1), Some(3)));
+
+        options.parts_policy = PartsPolicy::All;
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4", "/card/GX020001.MP4", "/card/GX030001.MP4"]);
+        assert_eq!(items.iter().map(|item| (item.part_num, item.part_count)).collect::<Vec<_>>(), [(Some(1), Some(3)), (Some(2), Some(3)), (Some(3), Some(3))]);
+    }
+
+    #[test]
+    fn related_files_of_every_part() {
+        let options = memory_card("/card", &CHAPTERED_CLIP);
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/GX020001.THM"), &options).unwrap();
+        let mut files = item_paths(&items);
+        files.sort();
+        let mut expected = CHAPTERED_CLIP.to_vec();
+        expected.sort();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn frame_grab_belongs_to_its_video() {
+        let options = memory_card("/card", &["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV", "/card/GX010001.JPG", "/card/GOPR0002.JPG"]);
+
+        for list in [GoProInterface::list_thumbnail, GoProInterface::list_high_quality] {
+            let items = list(&GoProInterface, Path::new("/"), Path::new("/card"), &options).unwrap();
+            assert!(!item_paths(&items).contains(&"/card/GX010001.JPG"));
+            assert!(item_paths(&items).contains(&"/card/GOPR0002.JPG"));
+        }
+
+        for file in ["/card/GX010001.MP4", "/card/GX010001.JPG"] {
+            let items = GoProInterface.get_related(Path::new("/"), Path::new(file), &options).unwrap();
+            let frame_grab = items.iter().find(|item| item.file_path == "/card/GX010001.JPG").unwrap();
+            assert_eq!((frame_grab.file_type.as_str(), frame_grab.role.as_deref()), ("image-preview", Some("preview")));
+            assert_eq!(items.len(), 4);
+        }
+
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/GOPR0002.JPG"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GOPR0002.JPG"]);
+    }
+
+    #[test]
+    fn names_are_matched_ignoring_case_and_whitespace() {
+        let options = memory_card("/card", &["/card/GX010001.mp4 ", "/card/GX010001.THM", "/card/GL010001.LRV"]);
+
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/GX010001.THM"), &options).unwrap();
+        let video = items.iter().find(|item| item.file_type == "video").unwrap();
+        assert_eq!(video.file_path, "/card/GX010001.mp4 ");
+        assert_eq!(video.part_count, Some(1));
+        assert_eq!(video.extension.as_deref(), Some("mp4"));
+        assert_eq!(video.container.as_deref(), Some("mp4"));
+        assert_eq!(items.len(), 3);
+
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.mp4 "]);
+        assert_eq!(items[0].part_count, Some(1));
+
+        let items = GoProInterface.list_thumbnail(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.THM"]);
+    }
+
+    #[test]
+    fn missing_lrv_is_an_error_unless_known_missing() {
+        let mut options = memory_card("/card", &["/card/GX010001.MP4", "/card/GX010001.THM"]);
+        let error = GoProInterface.get_related(Path::new("/"), Path::new("/card/GX010001.MP4"), &options).unwrap_err();
+        assert!(error.downcast_ref::<ExpectedFileMissing>().is_some_and(|ExpectedFileMissing(file)| file == Path::new("/card/GL010001.LRV")));
+
+        options.known_missing_files.push(PathBuf::from("/card/GL010001.LRV"));
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/GX010001.MP4"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4", "/card/GX010001.THM"]);
+    }
+
+    #[test]
+    fn clips_are_listed_by_the_earliest_part_with_an_mp4() {
+        let second_part = ["/card/GX020001.MP4", "/card/GX020001.THM", "/card/GL020001.LRV"];
+        let list = |first_part: &[&str], known_missing: &[&str]| {
+            let mut options = memory_card("/card", &[first_part, &second_part[..]].concat());
+            options.known_missing_files = known_missing.iter().map(PathBuf::from).collect();
+            GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options)
+                .map(|items| items.iter().map(|item| (item.file_path.clone(), item.part_count)).collect::<Vec<_>>())
+        };
+        // part_count only counts the parts that have their MP4 on disk
+        let listed = |file: &str, part_count| vec![(file.to_string(), Some(part_count))];
+
+        assert_eq!(list(&["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV"], &[]).unwrap(), listed("/card/GX010001.MP4", 2));
+        // The first part is accounted for by its LRV, or by its MP4 being known to be missing
+        assert_eq!(list(&["/card/GX010001.THM", "/card/GL010001.LRV"], &[]).unwrap(), listed("/card/GX020001.MP4", 1));
+        assert_eq!(list(&["/card/GX010001.THM", "/card/GL010001.LRV"], &["/card/GX010001.MP4"]).unwrap(), listed("/card/GX020001.MP4", 1));
+        assert_eq!(list(&["/card/GX010001.THM"], &["/card/GX010001.MP4", "/card/GL010001.LRV"]).unwrap(), listed("/card/GX020001.MP4", 1));
+        // Nothing left of the first part
+        assert!(list(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn parts_with_only_their_lrv_left_count_with_low_bitrate_parts() {
+        let mut options = memory_card("/card", &["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV", "/card/GX020001.THM", "/card/GL020001.LRV"]);
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4"]);
+        assert_eq!(items[0].part_count, Some(1));
+
+        options.count_low_bitrate_parts = true;
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010001.MP4"]);
+        assert_eq!(items[0].part_count, Some(2));
+
+        options.parts_policy = PartsPolicy::All;
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GL020001.LRV", "/card/GX010001.MP4"]);
+        assert_eq!(items.iter().map(|item| (item.part_num, item.degraded)).collect::<Vec<_>>(), [(Some(2), Some(true)), (Some(1), None)]);
+    }
+
+    #[test]
+    fn burst_photos_are_one_item_and_single_photos_pair_with_their_raw() {
+        let options = memory_card("/card", &["/card/G0010001.JPG", "/card/G0010002.JPG", "/card/G0010003.JPG", "/card/G0020004.JPG", "/card/G0020005.JPG",
+                             "/card/GOPR0006.JPG", "/card/GOPR0006.GPR", "/card/GOPR0007.JPG"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = GoProInterface.list_thumbnail(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/G0010001.JPG", "/card/G0020004.JPG", "/card/GOPR0006.JPG", "/card/GOPR0007.JPG"]);
+        let items = GoProInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/G0010001.JPG", "/card/G0020004.JPG", "/card/GOPR0006.GPR", "/card/GOPR0007.JPG"]);
+
+        let items = GoProInterface.get_related(location, Path::new("/card/G0010002.JPG"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/G0010001.JPG", "/card/G0010002.JPG", "/card/G0010003.JPG"]);
+        let items = GoProInterface.get_related(location, Path::new("/card/GOPR0006.GPR"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GOPR0006.JPG", "/card/GOPR0006.GPR"]);
+        let items = GoProInterface.get_related(location, Path::new("/card/GOPR0007.JPG"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GOPR0007.JPG"]);
+    }
+
+    #[test]
+    fn wavs_without_a_video_are_audio_items_of_their_own() {
+        let mut options = memory_card("/card", &["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV", "/card/GX010001.WAV", "/card/GX010002.WAV"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        for items in [GoProInterface.list_thumbnail(location, dir, &options).unwrap(), GoProInterface.list_high_quality(location, dir, &options).unwrap()] {
+            let standalone = items.iter().find(|item| item.file_path == "/card/GX010002.WAV").unwrap();
+            assert_eq!((standalone.file_type.as_str(), standalone.item_type.as_str()), ("audio", "audio"));
+            assert!(!item_paths(&items).contains(&"/card/GX010001.WAV"));
+        }
+        let items = GoProInterface.get_related(location, Path::new("/card/GX010002.WAV"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010002.WAV"]);
+
+        // The audio of a video is related to its other files, even with the video known missing
+        let items = GoProInterface.get_related(location, Path::new("/card/GX010001.WAV"), &options).unwrap();
+        assert!(item_paths(&items).contains(&"/card/GX010001.MP4") && item_paths(&items).contains(&"/card/GX010001.WAV"), "{:?}", item_paths(&items));
+        assert!(items.iter().all(|item| item.item_type == "video"));
+        options.known_missing_files = vec![PathBuf::from("/card/GX010002.MP4")];
+        let items = GoProInterface.list_high_quality(location, dir, &options).unwrap();
+        assert!(!item_paths(&items).contains(&"/card/GX010002.WAV"));
+    }
+
+    #[test]
+    fn max_clips_are_one_item_of_their_360_file_and_reframed_mp4() {
+        let options = memory_card("/card", &["/card/GS010001.360", "/card/GS010001.MP4", "/card/GS010001.THM", "/card/GL010001.LRV",
+                             "/card/GS010002.MP4", "/card/GS010002.THM", "/card/GL010002.LRV"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = GoProInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GS010001.360", "/card/GS010002.MP4"]);
+        assert_eq!(items[0].file_type, "video-raw");
+
+        for file in ["/card/GS010001.360", "/card/GS010001.MP4", "/card/GS010001.THM", "/card/GL010001.LRV"] {
+            let items = GoProInterface.get_related(location, Path::new(file), &options).unwrap();
+            let mut related = item_paths(&items);
+            related.sort();
+            assert_eq!(related, ["/card/GL010001.LRV", "/card/GS010001.360", "/card/GS010001.MP4", "/card/GS010001.THM"], "{}", file);
+            let rank = |path: &str| items.iter().find(|item| item.file_path == path).unwrap().quality_rank;
+            assert!(rank("/card/GS010001.360") > rank("/card/GS010001.MP4"), "{}", file);
+        }
+    }
+
+    #[test]
+    fn items_are_in_the_roll_of_their_folder() {
+        let options = memory_card("/card", &["/card/DCIM/100GOPRO/GOPR0001.JPG", "/card/DCIM/100GOPRO/GX010002.MP4", "/card/DCIM/100GOPRO/GX010002.THM",
+                             "/card/DCIM/100GOPRO/GL010002.LRV", "/card/DCIM/101GOPRO/GOPR0003.JPG"]);
+        for (dir, roll) in [("/card/DCIM/100GOPRO", "100GOPRO"), ("/card/DCIM/101GOPRO", "101GOPRO")] {
+            let items = GoProInterface.list_high_quality(Path::new("/"), Path::new(dir), &options).unwrap();
+            assert!(!items.is_empty());
+            assert!(items.iter().all(|item| item.roll.as_deref() == Some(roll)), "{}", dir);
+        }
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/DCIM/100GOPRO/GX010002.MP4"), &options).unwrap();
+        assert!(items.iter().all(|item| item.roll.as_deref() == Some("100GOPRO")));
+    }
+
+    #[test]
+    fn recursive_listing_goes_through_every_roll_folder() {
+        let mut options = memory_card("/card", &["/card/DCIM/100GOPRO/GX010001.MP4", "/card/DCIM/100GOPRO/GX010001.THM", "/card/DCIM/100GOPRO/GL010001.LRV",
+                                 "/card/DCIM/100GOPRO/GX020001.MP4", "/card/DCIM/100GOPRO/GX020001.THM", "/card/DCIM/100GOPRO/GL020001.LRV",
+                                 "/card/DCIM/101GOPRO/GX010001.MP4", "/card/DCIM/101GOPRO/GX010001.THM", "/card/DCIM/101GOPRO/GL010001.LRV",
+                                 "/card/DCIM/101GOPRO/GOPR0002.JPG", "/card/DCIM/99GOPRO/GOPR0003.JPG"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+        // Without the option the card is the one folder it's pointed at
+        assert!(GoProInterface.list_high_quality(location, dir, &options).is_err());
+
+        options.recursive = true;
+        // Only folders named with three digits and GOPRO are roll folders
+        let items = GoProInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100GOPRO/GX010001.MP4", "/card/DCIM/101GOPRO/GOPR0002.JPG", "/card/DCIM/101GOPRO/GX010001.MP4"]);
+        // The clip of the same number in the next folder is another recording
+        assert_eq!(items[0].part_count, Some(2));
+        assert_eq!(items[2].part_count, Some(1));
+        let items = GoProInterface.list_thumbnail(location, dir, &options).unwrap();
+        assert_eq!(items.len(), 3);
+    }
+
+    #[test]
+    fn thumbnail_candidates_are_previews_first_in_part_order() {
+        let mut options = memory_card("/card", &CHAPTERED_CLIP);
+        let candidates = GoProInterface.thumbnail_candidates(Path::new("/"), Path::new("/card/GX020001.MP4"), &options);
+        assert_eq!(candidates, ["/card/GX010001.THM", "/card/GX020001.THM", "/card/GL010001.LRV", "/card/GL020001.LRV",
+                                "/card/GX010001.MP4", "/card/GX020001.MP4"].map(PathBuf::from));
+
+        options.known_missing_files = vec![PathBuf::from("/card/GX010001.THM")];
+        let candidates = GoProInterface.thumbnail_candidates(Path::new("/"), Path::new("/card/GX010001.MP4"), &options);
+        assert_eq!(candidates.first(), Some(&PathBuf::from("/card/GX020001.THM")));
+    }
+
+    #[test]
+    fn items_have_the_basename_and_lowercase_extension_of_their_file() {
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &memory_card("/card", &CHAPTERED_CLIP)).unwrap();
+        assert_eq!(items[0].basename.as_deref(), Some("GX010001.MP4"));
+        assert_eq!(items[0].extension.as_deref(), Some("mp4"));
+    }
+
+    #[test]
+    fn videos_with_a_known_missing_thm_are_listed_through_their_lrv_then_their_mp4() {
+        let thumbnails = |files: &[&str], known_missing: &[&str]| {
+            let mut options = memory_card("/card", files);
+            options.known_missing_files = known_missing.iter().map(PathBuf::from).collect();
+            let items = GoProInterface.list_thumbnail(Path::new("/"), Path::new("/card"), &options).unwrap();
+            (items.iter().map(|item| (item.file_path.clone(), item.metadata_file.clone())).collect::<Vec<_>>(), options.scan_warnings.take())
+        };
+        let video = Some("/card/GX010001.MP4".to_string());
+
+        let (items, warnings) = thumbnails(&["/card/GX010001.MP4", "/card/GL010001.LRV"], &["/card/GX010001.THM"]);
+        assert_eq!(items, [("/card/GL010001.LRV".to_string(), video.clone())]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let (items, warnings) = thumbnails(&["/card/GX010001.MP4"], &["/card/GX010001.THM", "/card/GL010001.LRV"]);
+        assert_eq!(items, [("/card/GX010001.MP4".to_string(), video)]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let (items, warnings) = thumbnails(&["/card/GOPR0002.JPG"], &["/card/GX010001.THM", "/card/GL010001.LRV", "/card/GX010001.MP4"]);
+        assert_eq!(items, [("/card/GOPR0002.JPG".to_string(), None)]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("GX010001.THM"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn clips_with_only_their_lrvs_left_are_listed_through_them_with_the_lrv_fallback() {
+        let mut options = memory_card("/card", &["/card/GX010001.THM", "/card/GL010001.LRV", "/card/GX020001.THM", "/card/GL020001.LRV",
+                                 "/card/GX010002.MP4", "/card/GX010002.THM", "/card/GL010002.LRV"]);
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GX010002.MP4"]);
+
+        options.lrv_fallback = true;
+        let items = GoProInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/GL010001.LRV", "/card/GX010002.MP4"]);
+        assert_eq!(items[0].file_type, "video-preview");
+        assert_eq!(items[0].degraded, Some(true));
+        assert_eq!(items[0].part_count, Some(2));
+        assert_eq!(items[1].degraded, None);
+    }
 }
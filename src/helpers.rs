@@ -19,8 +19,11 @@
 
 use anyhow::{Result, anyhow};
 use std::path::{Path, PathBuf};
-use std::fs;
-use crate::FileItem;
+use std::collections::{HashMap, HashSet};
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use crate::{FileItem, HandlerOptions, PermissionErrorPolicy, UnknownFilePolicy};
+use crate::fs_provider::{FsProvider, FsEntry};
 use crate::helpers::ItemType::*;
 use crate::helpers::FileType::*;
 
@@ -28,44 +31,400 @@ pub fn osstr_to_str(os: &std::ffi::OsStr) -> Result<&str> {
     os.to_str().ok_or_else(|| anyhow!("Invalid UTF-8 in {:?}", os))
 }
 
+/// The extension of `file`, without the whitespace and invisible characters sloppy copies leave
+/// around names, see `find_file()`
 pub fn get_extension_str(file:&Path) -> Result<&str> {
-    osstr_to_str(file.extension().ok_or_else(|| anyhow!("File has no extension"))?)
+    Ok(osstr_to_str(file.extension().ok_or_else(|| anyhow!("File has no extension"))?)?.trim_matches(is_invisible_in_name))
 }
 
-pub fn for_each_file_type<F>(dir: &Path, mut f: F) -> Result<()>
+/// Compare names the way a person would, with runs of digits compared by their numeric value
+/// so that "part2" sorts before "part10". Names that only differ in leading zeros are then
+/// compared lexically so the order stays total
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+                let digit_run = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        run.push(c);
+                    }
+                    run.trim_start_matches('0').to_string()
+                };
+                let (a_run, b_run) = (digit_run(&mut a_chars), digit_run(&mut b_chars));
+                let ordering = a_run.len().cmp(&b_run.len()).then_with(|| a_run.cmp(&b_run));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_char), Some(b_char)) => {
+                if a_char != b_char {
+                    return a_char.cmp(&b_char);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Hidden files and directories, like the AppleDouble "._" files and ".Trashes" folders macOS
+/// leaves on cards, are skipped unless include_hidden is set
+pub fn is_skipped_hidden(path: &Path, options: &HandlerOptions) -> bool {
+    !options.include_hidden && path.file_name().is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+pub fn is_excluded_dir(dir: &Path, options: &HandlerOptions) -> bool {
+    options.exclude_dirs.iter().any(|excluded| {
+        if excluded.components().count() == 1 {
+            dir.file_name() == Some(excluded.as_os_str())
+        } else {
+            dir.strip_prefix(&options.card_root).is_ok_and(|relative| relative == excluded)
+        }
+    })
+}
+
+pub fn is_ignored_extension(ext: &str, options: &HandlerOptions) -> bool {
+    options.ignore_extensions.iter().any(|ignored| ignored.eq_ignore_ascii_case(ext))
+}
+
+/// A directory holding a file with this name is skipped along with everything under it
+pub const IGNORE_MARKER: &str = ".media_interface_ignore";
+
+/// A directory holding a file with this name is listed by the handler the file names instead of
+/// the one of the card, see [find_override_dirs]
+pub const OVERRIDE_CONFIG: &str = ".media_interface.json";
+
+/// Whether `dir` is under a directory of the card, other than the card itself, that another
+/// handler is responsible for
+fn in_override_dir(dir: &Path, options: &HandlerOptions) -> bool {
+    dir.ancestors().take_while(|ancestor| ancestor.starts_with(&options.card_root) && *ancestor != options.card_root)
+        .any(|ancestor| options.fs.exists(&ancestor.join(OVERRIDE_CONFIG)))
+}
+
+/// The entries of `dir` handlers should look at: everything but the ignore marker, hidden
+/// entries, excluded directories and files with an ignored extension. Only the directory listing
+/// is read up front, the filtering happens as the iterator is consumed. Whether an entry is a
+/// directory is what the listing says, so symlinks to directories aren't taken for one
+pub fn dir_fs_entries<'a>(dir: &Path, options: &'a HandlerOptions) -> Result<impl Iterator<Item = FsEntry> + 'a> {
+    // The marker also applies to directories below the one it's in, which handlers may scan
+    // directly without going through that one
+    let in_card = |ancestor: &&Path| ancestor.starts_with(&options.card_root);
+    let entries = if dir.ancestors().take_while(in_card).any(|ancestor| options.fs.exists(&ancestor.join(IGNORE_MARKER))) || in_override_dir(dir, options) {
+        Vec::new()
+    } else {
+        match options.fs.read_dir(dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && options.permission_error_policy == PermissionErrorPolicy::SkipWarn => {
+                options.scan_warnings.borrow_mut().push(format!("Skipped {:?} as it can't be read: {}", dir, e));
+                Vec::new()
+            }
+            entries => entries?,
+        }
+    };
+
+    Ok(entries.into_iter().filter(move |entry| {
+        let path = &entry.path;
+        let skipped = path.file_name().is_some_and(|name| name == IGNORE_MARKER || name == OVERRIDE_CONFIG)
+            || (entry.is_dir && options.fs.exists(&path.join(OVERRIDE_CONFIG)))
+            || options.own_files.contains(path)
+            || is_skipped_hidden(path, options)
+            || (entry.is_dir && is_excluded_dir(path, options))
+            || (!entry.is_dir && get_extension_str(path).ok().is_some_and(|ext| is_ignored_extension(ext, options)));
+        !skipped
+    }))
+}
+
+/// The paths of the entries dir_fs_entries gives
+pub fn dir_entries<'a>(dir: &Path, options: &'a HandlerOptions) -> Result<impl Iterator<Item = PathBuf> + 'a> {
+    Ok(dir_fs_entries(dir, options)?.map(|entry| entry.path))
+}
+
+/// The directories under `dir` with an override config, which the handler of the card leaves out
+/// for the handler they name to list. Directories under those aren't looked into, overrides in
+/// them apply when the directory they are in is listed. Unreadable directories are left for the
+/// handler to report
+pub fn find_override_dirs(dir: &Path, options: &HandlerOptions) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let entries = match options.fs.read_dir(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Ok(found),
+        entries => entries?,
+    };
+    for entry in entries {
+        if !entry.is_dir || is_skipped_hidden(&entry.path, options) || is_excluded_dir(&entry.path, options) || options.fs.exists(&entry.path.join(IGNORE_MARKER)) {
+            continue;
+        }
+        if options.fs.exists(&entry.path.join(OVERRIDE_CONFIG)) {
+            found.push(entry.path);
+        } else {
+            found.extend(find_override_dirs(&entry.path, options)?);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+pub fn for_each_file_type<F>(dir: &Path, options: &HandlerOptions, mut f: F) -> Result<()>
+where
+    F: FnMut(&PathBuf, String, String, Option<&str>) -> Result<()>,
+{
+    for path in dir_entries(dir, options)? {
+        with_file_type(&path, &mut f)?;
+    }
+    Ok(())
+}
+
+fn with_file_type<F>(path: &PathBuf, f: &mut F) -> Result<()>
 where
     F: FnMut(&PathBuf, String, String, Option<&str>) -> Result<()>,
 {
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+    // In upper case, the way the devices write them, so handlers match extensions ignoring case
+    let ext = get_extension_str(path).ok().map(str::to_ascii_uppercase);
 
-        let ext = get_extension_str(&path).ok();
+    let path_str = osstr_to_str(path.as_os_str())?.to_string();
 
-        let path_str = osstr_to_str(path.as_os_str())?.to_string();
+    let filename = path.file_name().ok_or_else(|| anyhow!("Failed to get filename"))?;
+    let filename_str = osstr_to_str(filename)?.to_string();
 
-        let filename = path.file_name().ok_or_else(|| anyhow!("Failed to get filename"))?;
-        let filename_str = osstr_to_str(filename)?.to_string();
+    f(path, filename_str, path_str, ext.as_deref())
+}
 
-        match f(&path, filename_str, path_str, ext){
-            Ok(()) => {},
-            Err(e) => { return Err(e); }
+/// For list iterators that have all of their items ready at once
+pub fn collected_items_iter<'a>(items: Result<Vec<FileItem>>) -> Box<dyn Iterator<Item = Result<FileItem>> + 'a> {
+    match items {
+        Ok(items) => Box::new(items.into_iter().map(Ok)),
+        Err(e) => Box::new(std::iter::once(Err(e))),
+    }
+}
+
+/// Whether an MP4/MOV file has a top level `moov` box. Recordings that got interrupted before
+/// the camera could write it out are left without one and can't be played. Only the box headers
+/// are read, walking from one to the next
+pub fn has_moov_box(fs: &dyn FsProvider, file: &Path) -> std::io::Result<bool> {
+    let file_len = fs.metadata(file)?.len;
+    let mut offset = 0;
+    while offset + 8 <= file_len {
+        let header = fs.read_range(file, offset, 16)?;
+        if header.len() < 8 {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            return Ok(true);
         }
+        let box_size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            // Extends to the end of the file
+            0 => break,
+            // The real size follows the type as a 64 bit number
+            1 if header.len() == 16 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            size => u64::from(size),
+        };
+        if box_size < 8 {
+            break;
+        }
+        offset = offset.saturating_add(box_size);
     }
-    Ok(())
+    Ok(false)
+}
+
+/// The container format of a media file going by its extension, the same for extensions that
+/// name different uses of one format, like the LRV proxies GoPro cameras write as MP4. None for
+/// files that aren't audio or video
+pub fn container_from_extension(file: &Path) -> Option<&'static str> {
+    match get_extension_str(file).ok()?.to_ascii_uppercase().as_str() {
+        "MP4" | "M4V" | "M4A" | "LRV" | "LRF" | "360" => Some("mp4"),
+        "MOV" => Some("mov"),
+        "3GP" | "3GPP" | "3GA" => Some("3gp"),
+        "MKV" => Some("mkv"),
+        "MTS" | "M2TS" => Some("mts"),
+        "AVI" => Some("avi"),
+        "WAV" => Some("wav"),
+        "AMR" => Some("amr"),
+        _ => None,
+    }
+}
+
+/// The container format of an ISO base media file going by the major brand of its `ftyp` box,
+/// telling QuickTime files apart from MP4 whatever their extension. None when the file doesn't
+/// start with an `ftyp` box
+#[cfg(feature = "media-probe")]
+pub fn ftyp_container(fs: &dyn FsProvider, file: &Path) -> std::io::Result<Option<&'static str>> {
+    let header = fs.read_range(file, 0, 12)?;
+    if header.len() < 12 || &header[4..8] != b"ftyp" {
+        return Ok(None);
+    }
+    Ok(Some(match &header[8..12] {
+        b"qt  " => "mov",
+        brand if brand.starts_with(b"3g") => "3gp",
+        _ => "mp4",
+    }))
+}
+
+/// The names of the entries of a single directory, read once so that many candidate files can be
+/// checked for existence without a stat call for each one
+pub struct DirListing<'a> {
+    fs: &'a dyn FsProvider,
+    dir: PathBuf,
+    names: HashSet<OsString>,
+    /// The files of the directory by their name as `find_file()` matches it
+    folded_names: HashMap<String, PathBuf>,
+}
+
+impl<'a> DirListing<'a> {
+    pub fn read(fs: &'a dyn FsProvider, dir: &Path) -> Result<DirListing<'a>> {
+        let entries = fs.read_dir(dir)?;
+        let names = entries.iter().filter_map(|entry| entry.path.file_name()).map(|name| name.to_os_string()).collect();
+        let mut folded_names = HashMap::new();
+        for entry in entries.into_iter().filter(|entry| !entry.is_dir) {
+            if let Some(name) = entry.path.file_name().and_then(|name| name.to_str()).map(fold_name) {
+                folded_names.entry(name).or_insert(entry.path);
+            }
+        }
+        Ok(DirListing{ fs, dir: dir.to_path_buf(), names, folded_names })
+    }
+
+    /// Same as `find_file()` for files directly under the listed directory. Other files fall
+    /// back to asking the filesystem
+    pub fn find(&self, file: &Path) -> Option<PathBuf> {
+        match (file.parent(), file.file_name()) {
+            (Some(parent), Some(name)) if parent == self.dir => if self.names.contains(name) {
+                Some(file.to_path_buf())
+            } else {
+                self.folded_names.get(&fold_name(name.to_str()?)).cloned()
+            },
+            _ => find_file(self.fs, file),
+        }
+    }
+
+    /// Whether `find()` finds the file
+    pub fn contains(&self, file: &Path) -> bool {
+        match (file.parent(), file.file_name()) {
+            (Some(parent), Some(name)) if parent == self.dir => self.names.contains(name)
+                || name.to_str().is_some_and(|name| self.folded_names.contains_key(&fold_name(name))),
+            _ => find_file(self.fs, file).is_some(),
+        }
+    }
+}
+
+/// Describes where a device puts the part (chapter) number of a multi-part recording in its
+/// filenames. For example GoPro's GX020212.MP4 has the part number 02 at position 2 and the media
+/// id 0212 from position 4 to the end of the name
+pub struct PartNameScheme {
+    /// Character position of the first digit of the part number
+    pub part_pos: usize,
+    /// Number of zero padded digits of the part number
+    pub part_width: usize,
+    /// Character position the media id starts at
+    pub media_id_pos: usize,
+    /// Length of the media id, None if it runs to the end of the name
+    pub media_id_len: Option<usize>,
+}
+
+pub struct PartCount{
+    pub existing_parts_count:u8,
+    pub all_parts_count:u8,
+}
+
+impl PartNameScheme {
+    fn split_name<'a>(&self, file:&'a Path) -> Result<(&'a str, &'a str)> {
+        let filename = osstr_to_str(file.file_name().ok_or_else(|| anyhow!("Couldn't get filename of part file"))?)?;
+        let (name, ext) = filename.rsplit_once('.').ok_or_else(|| anyhow!("Failed to split filename from it's extension {:?}", filename))?;
+
+        let min_len = (self.part_pos + self.part_width).max(self.media_id_pos + self.media_id_len.unwrap_or(1));
+        if !name.is_ascii() || name.len() < min_len {
+            return Err(anyhow!("Filename without the extension was not long enough to contain a part number and media id {:?}", name));
+        }
+        Ok((name, ext))
+    }
+
+    pub fn media_id<'a>(&self, file:&'a Path) -> Result<&'a str> {
+        let (name, _) = self.split_name(file)?;
+        Ok(match self.media_id_len {
+            Some(len) => &name[self.media_id_pos..self.media_id_pos+len],
+            None => &name[self.media_id_pos..],
+        })
+    }
+
+    pub fn part_num(&self, file:&Path) -> Result<u8> {
+        let (name, _) = self.split_name(file)?;
+        name[self.part_pos..self.part_pos+self.part_width].parse::<u8>()
+            .map_err(|e| anyhow!("Error parsing part number of {:?}: {}", name, e))
+    }
+
+    /// The file of part `part` of the recording `reference_file` belongs to. If `prefix` is set
+    /// it replaces everything before the part number
+    pub fn part_file(&self, reference_file:&Path, part:u8, prefix:Option<&str>, extension:&str) -> Result<PathBuf> {
+        let (name, _) = self.split_name(reference_file)?;
+        let prefix = prefix.unwrap_or(&name[..self.part_pos]);
+        let rest = &name[self.part_pos+self.part_width..];
+        let dir = reference_file.parent().ok_or_else(|| anyhow!("Couldn't get file's parent directory"))?;
+
+        Ok(dir.join(format!("{prefix}{part:0width$}{rest}.{extension}", width=self.part_width)))
+    }
+
+    /// Count the parts of the recording `reference_file` belongs to, starting from part 1.
+    /// `candidates` returns the files of a part that make it exist, and the companion files that
+    /// only show the part belongs to the recording. A part exists if one of the first exists. A
+    /// part that doesn't exist is still counted if one of its files is known to be missing or one
+    /// of its companion files exists. Counting stops at the first part that is neither. Recordings
+    /// that appear to have more than `max_parts` parts are an error, as that's more than the device
+    /// ever records and is more likely a damaged or badly named directory. The limit can be no
+    /// more than what the part number field fits
+    pub fn count_parts<C, E>(&self, reference_file:&Path, known_missing_files: &[PathBuf], max_parts: u8, candidates: C, exists: E) -> Result<PartCount> where
+        C: Fn(&Path, u8) -> Result<(Vec<PathBuf>, Vec<PathBuf>)>,
+        E: Fn(&Path) -> bool,
+    {
+        let mut parts = PartCount{existing_parts_count:0, all_parts_count:0};
+        let max_part = 10u32.pow(self.part_width as u32).saturating_sub(1).min(u8::MAX as u32) as u8;
+        let max_parts = max_parts.min(max_part);
+
+        for part in 1..=max_part {
+            let (files, companions) = candidates(reference_file, part)?;
+
+            let counted = if files.iter().any(|file| exists(file)) {
+                parts.existing_parts_count+=1;
+                true
+            }else{
+                files.iter().chain(companions.iter()).any(|file| known_missing_files.contains(file))
+                    || companions.iter().any(|file| exists(file))
+            };
+            if !counted {
+                break;
+            }
+            if part > max_parts {
+                return Err(anyhow!("Recording of {:?} appears to have more than the maximum of {} parts", reference_file, max_parts));
+            }
+            parts.all_parts_count+=1;
+        }
+
+        Ok(parts)
+    }
+}
+
+/// Where a file falls in the order a device captured its items, going by the counter the device
+/// numbers its files with. `number` wraps around to 0 after `wrap - 1`
+pub struct CaptureSequence {
+    /// Devices may number different kinds of files with separate counters
+    pub counter: &'static str,
+    pub number: u32,
+    pub wrap: u32,
+    pub part: u8,
 }
 
 #[allow(clippy::enum_variant_names)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum FileType{
    FileVideo,
    FileVideoPreview,
-   #[allow(dead_code)]
    FileVideoRaw,
 
    FileImage,
    FileImagePreview,
    FileImageRaw,
+   /// The motion part of a live/motion photo. It's a companion of the image, not a video item
+   FileLivePhotoMotion,
 
    FileAudio,
 
@@ -75,11 +434,63 @@ pub enum FileType{
 }
 
 #[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum ItemType{
     ItemVideo,
     ItemImage,
     ItemAudio,
     ItemGNSSTrack,
+    /// 360 photo or video
+    ItemPanorama,
+}
+
+impl FileType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileVideo           => "video",
+            FileVideoPreview    => "video-preview",
+            FileVideoRaw        => "video-raw",
+            FileImage           => "image",
+            FileImagePreview    => "image-preview",
+            FileImageRaw        => "image-raw",
+            FileLivePhotoMotion => "live-photo-motion",
+            FileAudio           => "audio",
+            FileMetadata        => "metadata",
+            FileGNSSTrack       => "gnss-track"
+        }
+    }
+    /// How good a representation of its item a file of this type is, higher is better: raw
+    /// files over full quality ones over previews, with metadata last
+    pub fn quality_rank(&self) -> u8 {
+        match self {
+            FileVideoRaw | FileImageRaw                                    => 3,
+            FileVideo | FileImage | FileAudio | FileGNSSTrack              => 2,
+            FileVideoPreview | FileImagePreview | FileLivePhotoMotion      => 1,
+            FileMetadata                                                   => 0,
+        }
+    }
+    pub fn parse(name: &str) -> Result<FileType> {
+        [FileVideo, FileVideoPreview, FileVideoRaw, FileImage, FileImagePreview, FileImageRaw, FileLivePhotoMotion, FileAudio, FileMetadata, FileGNSSTrack]
+            .into_iter().find(|file_type| file_type.as_str() == name)
+            .ok_or_else(|| anyhow!("Unknown file type {:?}", name))
+    }
+}
+
+impl ItemType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemVideo     => "video",
+            ItemImage     => "image",
+            ItemAudio     => "audio",
+            ItemGNSSTrack => "gnss-track",
+            ItemPanorama  => "panorama",
+        }
+    }
+    pub fn parse(name: &str) -> Result<ItemType> {
+        [ItemVideo, ItemImage, ItemAudio, ItemGNSSTrack, ItemPanorama]
+            .into_iter().find(|item_type| item_type.as_str() == name)
+            .ok_or_else(|| anyhow!("Unknown item type {:?}", name))
+    }
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -88,8 +499,70 @@ pub struct JsonFileInfoTypes{
     pub item_type: ItemType,
 }
 
-pub fn create_simple_file_if_exists(file_path:&Path, json_file_info: JsonFileInfoTypes, metadata_file:Option<String>) -> Result<Option<FileItem>> {
-    if file_path.exists(){
+impl JsonFileInfoTypes {
+    /// What the file is to its item, a part of a recording made of multiple files aside
+    pub fn role(&self) -> &'static str {
+        match self.file_type {
+            FileVideoPreview | FileImagePreview                   => "preview",
+            FileMetadata                                          => "metadata",
+            FileLivePhotoMotion                                   => "sidecar",
+            FileAudio if self.item_type != ItemAudio              => "sidecar",
+            FileGNSSTrack if self.item_type != ItemGNSSTrack      => "sidecar",
+            _                                                     => "primary",
+        }
+    }
+}
+
+/// Characters sloppy copies leave around file names that don't show up when looking at them
+fn is_invisible_in_name(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{feff}')
+}
+
+/// Find `file_path` on the filesystem. If it doesn't exist as is, look for a file next to it that
+/// only differs in case or in whitespace and invisible characters around the name, as left
+/// behind by copies that didn't preserve names exactly
+pub fn find_file(fs: &dyn FsProvider, file_path: &Path) -> Option<PathBuf> {
+    if fs.exists(file_path) {
+        return Some(file_path.to_path_buf());
+    }
+
+    find_in_entries(&fs.read_dir(file_path.parent()?).ok()?, file_path)
+}
+
+/// A file name the way `find_file()` compares it, so names that only differ in case or in what
+/// surrounds them are the same
+fn fold_name(name: &str) -> String {
+    name.trim_matches(is_invisible_in_name).to_ascii_uppercase()
+}
+
+/// The file among the entries of the directory of `file_path` that `find_file()` settles for when
+/// it doesn't exist as is
+fn find_in_entries(entries: &[FsEntry], file_path: &Path) -> Option<PathBuf> {
+    let wanted = fold_name(file_path.file_name()?.to_str()?);
+    entries.iter()
+        .find(|entry| !entry.is_dir && entry.path.file_name().and_then(|name| name.to_str()).is_some_and(|name| fold_name(name) == wanted))
+        .map(|entry| entry.path.clone())
+}
+
+/// Find the directory `relative` under `base`, matching each component of it ignoring case when
+/// it doesn't exist as is, for cards copied through filesystems that fold the case of names
+pub fn find_dir(fs: &dyn FsProvider, base: &Path, relative: &str) -> Option<PathBuf> {
+    let mut dir = base.to_path_buf();
+    for wanted in relative.split('/').filter(|component| !component.is_empty()) {
+        let exact = dir.join(wanted);
+        dir = if fs.metadata(&exact).is_ok_and(|metadata| metadata.is_dir) {
+            exact
+        } else {
+            fs.read_dir(&dir).ok()?.into_iter()
+                .find(|entry| entry.is_dir && entry.path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.eq_ignore_ascii_case(wanted)))?
+                .path
+        };
+    }
+    Some(dir)
+}
+
+pub fn create_simple_file_if_exists(fs: &dyn FsProvider, file_path:&Path, json_file_info: JsonFileInfoTypes, metadata_file:Option<String>) -> Result<Option<FileItem>> {
+    if let Some(file_path) = find_file(fs, file_path) {
         Ok(Some(create_simple_file(file_path.to_string_lossy().into_owned(), json_file_info, metadata_file)?))
     }else{
         Ok(None)
@@ -108,21 +581,24 @@ pub fn create_simple_file_if_exists(file_path:&Path, json_file_info: JsonFileInf
 //    }
 //}
 
-pub fn create_part_file_if_exists(file_path:&Path, json_file_info: JsonFileInfoTypes, part_count:u8, part_num:u8, metadata_file:Option<String>) -> Option<FileItem> {
-    if file_path.exists(){
-        Some(create_part_file(file_path.to_string_lossy().into_owned(), json_file_info, part_count, part_num, metadata_file))
-    }else{
-        None
-    }
+pub fn create_part_file_if_exists(fs: &dyn FsProvider, file_path:&Path, json_file_info: JsonFileInfoTypes, part_count:u8, part_num:u8, metadata_file:Option<String>) -> Option<FileItem> {
+    find_file(fs, file_path).map(|file_path| create_part_file(file_path.to_string_lossy().into_owned(), json_file_info, part_count, part_num, metadata_file))
 }
 
-pub fn create_part_file_that_exists(file_path:&PathBuf, json_file_info: JsonFileInfoTypes, part_count:u8, part_num:u8, metadata_file:Option<String>, known_missing_files: &[PathBuf]) -> Result<Option<FileItem>> {
-    if file_path.exists(){
-        Ok(Some(create_part_file(file_path.to_string_lossy().into_owned(), json_file_info, part_count, part_num, metadata_file)))
+/// The error handlers fail with when a file of an item that should be there isn't and isn't a
+/// known missing file either. Can be downcast to from the anyhow error, also under context added
+/// on top of it
+#[derive(Debug, thiserror::Error)]
+#[error("File {0:?} expected to exist")]
+pub struct ExpectedFileMissing(pub PathBuf);
+
+pub fn create_part_file_that_exists(fs: &dyn FsProvider, file_path:&PathBuf, json_file_info: JsonFileInfoTypes, part_count:u8, part_num:u8, metadata_file:Option<String>, known_missing_files: &[PathBuf]) -> Result<Option<FileItem>> {
+    if let Some(found_path) = find_file(fs, file_path) {
+        Ok(Some(create_part_file(found_path.to_string_lossy().into_owned(), json_file_info, part_count, part_num, metadata_file)))
     }else if known_missing_files.contains(file_path){
         Ok(None)
     }else{
-        Err(anyhow::anyhow!("File {:?} expected to exist", file_path.to_string_lossy().into_owned()))
+        Err(ExpectedFileMissing(file_path.clone()).into())
     }
 }
 
@@ -132,33 +608,48 @@ pub fn create_simple_file(file_path:String, json_file_info: JsonFileInfoTypes, m
         json_file_info.file_type == FileType::FileVideoRaw { // TODO: Make this a compile time check
         return Err(anyhow::anyhow!("Internal error: Tried to generate simple file for video item"));
     }
+    // The motion part of a live photo is video data but belongs to a single image item
+    if json_file_info.file_type == FileType::FileLivePhotoMotion && json_file_info.item_type != ItemType::ItemImage {
+        return Err(anyhow::anyhow!("Internal error: Tried to generate live photo motion file for non-image item"));
+    }
     Ok(create_simple_file_unchecked(file_path, json_file_info, metadata_file))
 }
 
 #[allow(clippy::redundant_field_names)]
 fn create_simple_file_unchecked(file_path:String, json_file_info: JsonFileInfoTypes, metadata_file:Option<String>) -> FileItem {
+    let roll = Path::new(&file_path).parent().and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned());
+    let container = container_from_extension(Path::new(&file_path)).map(str::to_string);
+    let basename = Path::new(&file_path).file_name().map(|name| name.to_string_lossy().into_owned());
+    let extension = get_extension_str(Path::new(&file_path)).ok().map(str::to_lowercase);
     FileItem{
         file_path:file_path,
-        file_type:match json_file_info.file_type{
-            FileVideo         => "video",
-            FileVideoPreview  => "video-preview",
-            FileVideoRaw      => "video-raw",
-            FileImage         => "image",
-            FileImagePreview  => "image-preview",
-            FileImageRaw      => "image-raw",
-            FileAudio         => "audio",
-            FileMetadata      => "metadata",
-            FileGNSSTrack     => "gnss-track"
-        }.to_string(),
-        item_type:match json_file_info.item_type{
-            ItemVideo     => "video",
-            ItemImage     => "image",
-            ItemAudio     => "audio",
-            ItemGNSSTrack => "gnss-track",
-        }.to_string(),
+        file_type:json_file_info.file_type.as_str().to_string(),
+        item_type:json_file_info.item_type.as_str().to_string(),
+        quality_rank : Some(json_file_info.file_type.quality_rank()),
         part_count :    None,
         part_num :      None,
         metadata_file : metadata_file,
+        generated_thumbnail :        None,
+        needs_thumbnail_generation : None,
+        mode :                       None,
+        uid :                        None,
+        gid :                        None,
+        thumbnail_base64 :           None,
+        degraded :                   None,
+        has_motion :                 None,
+        item_total_size :            None,
+        playable :                   None,
+        thumbnail_path :             None,
+        tags :                       None,
+        caption :                    None,
+        role :                       Some(json_file_info.role().to_string()),
+        roll :                       roll,
+        item_id :                    None,
+        container :                  container,
+        thumbnail_candidates :       None,
+        basename :                   basename,
+        extension :                  extension,
+        handler :                    None,
     }
 }
 
@@ -167,16 +658,95 @@ pub fn create_part_file(file_path:String, json_file_info: JsonFileInfoTypes, par
     let mut ret = create_simple_file_unchecked(file_path, json_file_info, metadata_file);
     ret.part_count = Some(part_count);
     ret.part_num = Some(part_num);
+    if part_num > 1 && ret.role.as_deref() == Some("primary") {
+        ret.role = Some("part".to_string());
+    }
     ret
 }
 
-pub fn filter_dir<F>(source_dir: &Path, mut filter: F) -> Result<Vec<FileItem>>
+/// Where a thumbnail generated from the first frame of a video is expected to be stored, next to
+/// the video as `<name>.thumb.jpg`
+pub fn generated_thumbnail_path(video_file: &Path) -> PathBuf {
+    video_file.with_extension("thumb.jpg")
+}
+
+/// When a group of related files has exactly one metadata file, link it as the metadata_file of
+/// every video file of the group that doesn't already have one
+pub fn link_metadata_file(items: &mut [FileItem]) {
+    let mut metadata_files = items.iter().filter(|item| item.file_type == FileMetadata.as_str());
+    let (Some(metadata_file), None) = (metadata_files.next(), metadata_files.next()) else {
+        return;
+    };
+    let metadata_file = metadata_file.file_path.clone();
+
+    let video_types = [FileVideo.as_str(), FileVideoPreview.as_str(), FileVideoRaw.as_str()];
+    for item in items {
+        if video_types.contains(&item.file_type.as_str()) && item.metadata_file.is_none() {
+            item.metadata_file = Some(metadata_file.clone());
+        }
+    }
+}
+
+/// Clear the metadata_file of the items it points to a file that neither exists nor is a known
+/// missing file, returning the items and metadata files cleared, so clients aren't sent after
+/// files that aren't there
+pub fn drop_dead_metadata_files(items: &mut [FileItem], fs: &dyn FsProvider, known_missing_files: &[PathBuf]) -> Vec<(String, String)> {
+    let mut dropped = Vec::new();
+    for item in items {
+        let Some(metadata_file) = &item.metadata_file else {
+            continue;
+        };
+        let path = Path::new(metadata_file);
+        if find_file(fs, path).is_none() && !known_missing_files.iter().any(|missing| missing == path) {
+            dropped.push((item.file_path.clone(), metadata_file.clone()));
+            item.metadata_file = None;
+        }
+    }
+    dropped
+}
+
+/// Order the files of a get_related result by part, and within each part the primary file
+/// first, then previews, sidecars and metadata, better quality files first among equals. The
+/// order is stable, so files that compare equal keep the order the handler gave them
+pub fn sort_related(items: &mut [FileItem]) {
+    let role_priority = |item: &FileItem| match item.role.as_deref() {
+        Some("primary" | "part") => 0,
+        Some("preview") => 1,
+        Some("sidecar") => 2,
+        Some("metadata") => 3,
+        _ => 4,
+    };
+    items.sort_by_key(|item| (item.part_num.unwrap_or(1), role_priority(item), std::cmp::Reverse(item.quality_rank)));
+}
+
+/// The get_related result `items` of `source_media_file`, or an error if the file isn't one of
+/// them, because it doesn't exist or because the handler didn't find it to belong to the item it
+/// is named after
+pub fn check_related(fs: &dyn FsProvider, source_media_file: &Path, items: Vec<FileItem>) -> Result<Vec<FileItem>> {
+    let Some(found) = find_file(fs, source_media_file) else {
+        return Err(anyhow!("File {:?} doesn't exist", source_media_file));
+    };
+    if !items.iter().any(|item| Path::new(&item.file_path) == found) {
+        return Err(anyhow!("File {:?} doesn't belong to the item it is named after", source_media_file));
+    }
+    Ok(items)
+}
+
+/// The result for a file a handler doesn't recognise, going by the unknown file policy
+pub fn unknown_file(path_str: &str, options: &HandlerOptions) -> Result<Option<FileItem>> {
+    match options.unknown_file_policy {
+        UnknownFilePolicy::Error => Err(anyhow!("Unexpected file {}", path_str)),
+        UnknownFilePolicy::Ignore => Ok(None),
+    }
+}
+
+pub fn filter_dir<F>(source_dir: &Path, options: &HandlerOptions, mut filter: F) -> Result<Vec<FileItem>>
 where
     F:FnMut(&str, Option<&str>, &PathBuf, &str)->Result<Option<FileItem>>,
 {
     let mut items = Vec::<FileItem>::new();
 
-    for_each_file_type(source_dir,
+    for_each_file_type(source_dir, options,
         |path:&PathBuf, filename: String, path_str: String, ext: Option<&str>| {
             if let Some(item) = filter(&filename, ext, path, &path_str)? {
                 items.push(item);
@@ -188,3 +758,234 @@ where
 
     Ok(items)
 }
+
+/// filter_dir for handlers that go into subdirectories: those are put in `subdirs` instead of
+/// being given to `filter`. A symlink to a directory is given to `filter` like any other file,
+/// so a link up the tree doesn't get gone around in forever
+pub fn filter_dir_and_subdirs<F>(source_dir: &Path, options: &HandlerOptions, subdirs: &mut Vec<PathBuf>, mut filter: F) -> Result<Vec<FileItem>>
+where
+    F:FnMut(&str, Option<&str>, &PathBuf, &str)->Result<Option<FileItem>>,
+{
+    let mut items = Vec::<FileItem>::new();
+
+    let mut filter_file = |path:&PathBuf, filename: String, path_str: String, ext: Option<&str>| {
+        if let Some(item) = filter(&filename, ext, path, &path_str)? {
+            items.push(item);
+        }
+        Ok(())
+    };
+    dir_fs_entries(source_dir, options).and_then(|entries| {
+        for entry in entries {
+            if entry.is_dir {
+                subdirs.push(entry.path);
+            } else {
+                with_file_type(&entry.path, &mut filter_file)?;
+            }
+        }
+        Ok(())
+    })
+    .map_err(|err| anyhow::anyhow!("Error filtering dir '{}': {}",source_dir.display(), err))?;
+
+    Ok(items)
+}
+
+/// The items of every roll folder, like 100MEDIA, in the DCIM folder of a card, as `filter` makes
+/// them out of each file and its extension. Files without an extension go by the unknown file
+/// policy
+pub fn scan_dcim_rolls<F>(card: &Path, options: &HandlerOptions, mut filter: F) -> Result<Vec<FileItem>>
+where
+    F: FnMut(&str, &PathBuf, &str) -> Result<Option<FileItem>>,
+{
+    let mut items = Vec::<FileItem>::new();
+
+    let dcim = find_dir(options.fs.as_ref(), card, "DCIM").unwrap_or_else(|| card.join("DCIM"));
+    for mediadir in options.fs.read_dir(&dcim)? {
+        if !mediadir.is_dir || is_excluded_dir(&mediadir.path, options) || is_skipped_hidden(&mediadir.path, options) {
+            continue;
+        }
+        items.extend(filter_dir(&mediadir.path, options, |_filename: &str, ext: Option<&str>, path: &PathBuf, path_str: &str| {
+            match ext {
+                Some(ext) => filter(ext, path, path_str),
+                None => unknown_file(path_str, options),
+            }
+        })?);
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card, MemoryFs};
+
+    /// Dashcam style names with the part number after the media id, like CLIP0042_P003.MOV
+    const SUFFIX_PARTS: PartNameScheme = PartNameScheme{ part_pos:10, part_width:3, media_id_pos:4, media_id_len:Some(4) };
+
+    #[test]
+    fn part_names_with_the_part_number_last() {
+        let file = Path::new("/card/CLIP0042_P003.MOV");
+        assert_eq!(SUFFIX_PARTS.media_id(file).unwrap(), "0042");
+        assert_eq!(SUFFIX_PARTS.part_num(file).unwrap(), 3);
+        assert_eq!(SUFFIX_PARTS.part_file(file, 12, None, "MOV").unwrap(), Path::new("/card/CLIP0042_P012.MOV"));
+        assert!(SUFFIX_PARTS.part_num(Path::new("/card/CLIP0042_P.MOV")).is_err());
+    }
+
+    #[test]
+    fn parts_are_counted_up_to_the_first_one_missing() {
+        let on_card = [Path::new("/card/CLIP0042_P001.MOV"), Path::new("/card/CLIP0042_P002.MOV"), Path::new("/card/CLIP0042_P004.MOV")];
+        let candidates = |file: &Path, part: u8| Ok((vec![SUFFIX_PARTS.part_file(file, part, None, "MOV")?], Vec::new()));
+        let exists = |file: &Path| on_card.contains(&file);
+        let reference = Path::new("/card/CLIP0042_P001.MOV");
+
+        let count = SUFFIX_PARTS.count_parts(reference, &[], 255, candidates, exists).unwrap();
+        assert_eq!((count.existing_parts_count, count.all_parts_count), (2, 2));
+
+        // A part known to be missing is still one of the recording's
+        let known_missing = [PathBuf::from("/card/CLIP0042_P003.MOV")];
+        let count = SUFFIX_PARTS.count_parts(reference, &known_missing, 255, candidates, exists).unwrap();
+        assert_eq!((count.existing_parts_count, count.all_parts_count), (3, 4));
+
+        assert!(SUFFIX_PARTS.count_parts(reference, &known_missing, 3, candidates, exists).is_err());
+    }
+
+    #[test]
+    fn live_photo_motion_files_belong_to_image_items_only() {
+        let item = create_simple_file("/card/IMG_0001.MOV".to_string(), JsonFileInfoTypes{ file_type: FileLivePhotoMotion, item_type: ItemImage }, None).unwrap();
+        assert_eq!((item.file_type.as_str(), item.item_type.as_str(), item.role.as_deref()), ("live-photo-motion", "image", Some("sidecar")));
+
+        assert!(create_simple_file("/card/IMG_0001.MOV".to_string(), JsonFileInfoTypes{ file_type: FileLivePhotoMotion, item_type: ItemVideo }, None).is_err());
+        assert!(create_simple_file("/card/IMG_0001.MOV".to_string(), JsonFileInfoTypes{ file_type: FileVideo, item_type: ItemImage }, None).is_err());
+    }
+
+    #[test]
+    fn the_metadata_file_of_a_group_is_linked_to_its_videos() {
+        use crate::{gopro_hero_generic_1::GoProInterface, sony_ilcem4_1::SonyInterface, SourceMediaInterface};
+
+        let options = memory_card("/card", &[
+            "/card/PRIVATE/M4ROOT/CLIP/C0001.MP4", "/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML", "/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4",
+        ]);
+        let mut items = SonyInterface.get_related(Path::new("/"), Path::new("/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"), &options).unwrap();
+        link_metadata_file(&mut items);
+        let linked: Vec<(&str, Option<&str>)> = items.iter().map(|item| (item.file_path.as_str(), item.metadata_file.as_deref())).collect();
+        let xml = Some("/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML");
+        assert_eq!(linked, [("/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML", None), ("/card/PRIVATE/M4ROOT/CLIP/C0001.MP4", xml),
+                            ("/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", None), ("/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4", xml)]);
+
+        // GoPro clips have no metadata file, so what the handler set is left as it is
+        let options = memory_card("/card", &["/card/GX010001.MP4", "/card/GX010001.THM", "/card/GL010001.LRV"]);
+        let items = GoProInterface.get_related(Path::new("/"), Path::new("/card/GX010001.MP4"), &options).unwrap();
+        let mut linked = items.clone();
+        link_metadata_file(&mut linked);
+        assert_eq!(linked.iter().map(|item| &item.metadata_file).collect::<Vec<_>>(), items.iter().map(|item| &item.metadata_file).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn natural_order_compares_digit_runs_by_value() {
+        let natural = |names: &[&'static str]| {
+            let mut names = names.to_vec();
+            names.sort_by(|a, b| natural_cmp(a, b));
+            names
+        };
+        let lexical = |names: &[&'static str]| {
+            let mut names = names.to_vec();
+            names.sort();
+            names
+        };
+
+        let gopro = ["GX0100010.MP4", "GX010002.MP4", "GX010001.MP4"];
+        assert_eq!(lexical(&gopro), ["GX010001.MP4", "GX0100010.MP4", "GX010002.MP4"]);
+        assert_eq!(natural(&gopro), ["GX010001.MP4", "GX010002.MP4", "GX0100010.MP4"]);
+
+        let sony = ["DCIM/99MSDCF/C10000.MP4", "DCIM/100MSDCF/C9999.MP4", "DCIM/100MSDCF/C10000.MP4"];
+        assert_eq!(lexical(&sony), ["DCIM/100MSDCF/C10000.MP4", "DCIM/100MSDCF/C9999.MP4", "DCIM/99MSDCF/C10000.MP4"]);
+        assert_eq!(natural(&sony), ["DCIM/99MSDCF/C10000.MP4", "DCIM/100MSDCF/C9999.MP4", "DCIM/100MSDCF/C10000.MP4"]);
+
+        // Zero padded names of the same width sort the same either way, and leading zeros only
+        // break ties
+        let padded = ["GX020001.MP4", "GX010002.MP4", "GX010001.MP4"];
+        assert_eq!(natural(&padded), lexical(&padded));
+        assert_eq!(natural(&["part02", "part2", "part10"]), ["part02", "part2", "part10"]);
+    }
+
+    #[test]
+    fn directories_with_an_ignore_marker_are_skipped() {
+        use crate::{generic_single_file_items::GenericSingleFileItem, sony_ilcem4_1::SonyInterface, SourceMediaInterface};
+
+        let mut options = memory_card("/card", &[
+            "/card/photo.jpg", "/card/scratch/.media_interface_ignore", "/card/scratch/clip.mp4", "/card/scratch/2024/photo.jpg",
+        ]);
+        options.recursive = true;
+        assert_eq!(item_paths(&GenericSingleFileItem.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap()), ["/card/photo.jpg"]);
+
+        let options = memory_card("/card", &[
+            "/card/DCIM/100MSDCF/DSC00001.JPG", "/card/DCIM/101MSDCF/.media_interface_ignore", "/card/DCIM/101MSDCF/DSC00002.JPG",
+        ]);
+        assert_eq!(item_paths(&SonyInterface.list_thumbnail(Path::new("/"), Path::new("/card"), &options).unwrap()), ["/card/DCIM/100MSDCF/DSC00001.JPG"]);
+    }
+
+    #[test]
+    fn quality_rank_puts_raw_over_full_quality_over_previews() {
+        let rank = |file_type| create_simple_file("/card/DSC00001".to_string(), JsonFileInfoTypes{ file_type, item_type: ItemImage }, None).unwrap().quality_rank.unwrap();
+        assert!(rank(FileImageRaw) > rank(FileImage));
+        assert!(rank(FileImage) > rank(FileImagePreview));
+        assert!(rank(FileImagePreview) > rank(FileMetadata));
+
+        let video_rank = |file_type| create_part_file("/card/C0001".to_string(), JsonFileInfoTypes{ file_type, item_type: ItemVideo }, 1, 1, None).quality_rank.unwrap();
+        assert!(video_rank(FileVideoRaw) > video_rank(FileVideo));
+        assert!(video_rank(FileVideo) > video_rank(FileVideoPreview));
+    }
+
+    /// An ISO media box of `box_type` with `len` bytes of content
+    fn mp4_box(box_type: &[u8; 4], len: usize) -> Vec<u8> {
+        let mut data = u32::try_from(8 + len).unwrap().to_be_bytes().to_vec();
+        data.extend(box_type);
+        data.resize(8 + len, 0);
+        data
+    }
+
+    #[test]
+    fn videos_are_playable_with_a_top_level_moov_box() {
+        let mut fs = MemoryFs::default();
+        let with_moov = [mp4_box(b"ftyp", 16), mp4_box(b"mdat", 100), mp4_box(b"moov", 20)].concat();
+        // Interrupted before the index was written, with "moov" inside the media data only
+        let truncated = [mp4_box(b"ftyp", 16), mp4_box(b"mdat", 4), b"moov".to_vec(), vec![0; 92]].concat();
+        let mut large_mdat = [mp4_box(b"ftyp", 16), b"\0\0\0\x01mdat".to_vec(), 116u64.to_be_bytes().to_vec(), vec![0; 100]].concat();
+        large_mdat.extend(mp4_box(b"moov", 20));
+        for (name, contents) in [("with_moov.mp4", with_moov), ("truncated.mp4", truncated), ("large_mdat.mp4", large_mdat), ("empty.mp4", Vec::new())] {
+            fs.add_file_with_contents(&Path::new("/card").join(name), contents, None);
+        }
+
+        assert!(has_moov_box(&fs, Path::new("/card/with_moov.mp4")).unwrap());
+        assert!(!has_moov_box(&fs, Path::new("/card/truncated.mp4")).unwrap());
+        assert!(has_moov_box(&fs, Path::new("/card/large_mdat.mp4")).unwrap());
+        assert!(!has_moov_box(&fs, Path::new("/card/empty.mp4")).unwrap());
+        assert!(has_moov_box(&fs, Path::new("/card/missing.mp4")).is_err());
+    }
+
+    #[test]
+    fn containers_of_video_extensions() {
+        for (name, container) in [("GX010001.MP4", Some("mp4")), ("GL010001.LRV", Some("mp4")), ("GS010001.360", Some("mp4")), ("clip.mov", Some("mov")),
+                                  ("clip.3gpp", Some("3gp")), ("clip.mkv", Some("mkv")), ("00001.MTS", Some("mts")), ("clip.avi", Some("avi")),
+                                  ("photo.jpg", None), ("clip", None)] {
+            assert_eq!(container_from_extension(Path::new(name)), container, "{}", name);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "media-probe")]
+    fn ftyp_brands_tell_quicktime_apart_from_mp4() {
+        let mut fs = MemoryFs::default();
+        for (name, brand) in [("quicktime.mp4", b"qt  "), ("iso.mov", b"isom"), ("phone.mp4", b"3gp5")] {
+            let mut ftyp = mp4_box(b"ftyp", 8);
+            ftyp[8..12].copy_from_slice(brand);
+            fs.add_file_with_contents(&Path::new("/card").join(name), ftyp, None);
+        }
+        fs.add_file_with_contents(Path::new("/card/mdat_first.mp4"), mp4_box(b"mdat", 16), None);
+
+        assert_eq!(ftyp_container(&fs, Path::new("/card/quicktime.mp4")).unwrap(), Some("mov"));
+        assert_eq!(ftyp_container(&fs, Path::new("/card/iso.mov")).unwrap(), Some("mp4"));
+        assert_eq!(ftyp_container(&fs, Path::new("/card/phone.mp4")).unwrap(), Some("3gp"));
+        assert_eq!(ftyp_container(&fs, Path::new("/card/mdat_first.mp4")).unwrap(), None);
+    }
+}
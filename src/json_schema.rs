@@ -0,0 +1,165 @@
+/* json_schema.rs - Checks JSON values against a JSON Schema
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use serde_json::{Map, Value};
+
+/// The keywords `validate` checks values against
+const VALIDATION_KEYWORDS: &[&str] = &["type", "const", "enum", "minimum", "maximum", "properties", "required", "additionalProperties", "items", "$ref"];
+
+/// Keywords that don't constrain values
+const ANNOTATION_KEYWORDS: &[&str] = &["$schema", "$id", "$comment", "$defs", "definitions", "title", "description", "default", "examples"];
+
+/// Every way `value` doesn't conform to `schema`, each starting with the JSON pointer of the
+/// offending part of `value`. Only the keywords the output schema of this project uses are
+/// implemented: type, const, enum, minimum, maximum, properties, required,
+/// additionalProperties, items and $ref to a local definition. A schema with any other keyword
+/// is reported as such instead of being checked, as the value could break the constraints of it
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    find_unsupported_keywords(schema, "", &mut errors);
+    if errors.is_empty() {
+        validate_at(value, schema, schema, "", &mut errors);
+    }
+    errors
+}
+
+/// Report every keyword of `schema` and the schemas in it that `validate` doesn't implement,
+/// along with the JSON pointer of the schema it is in
+fn find_unsupported_keywords(schema: &Value, pointer: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(_) => return,
+        Value::Object(schema) => schema,
+        _ => {
+            errors.push(format!("schema at {} is neither an object nor a boolean", if pointer.is_empty() { "/" } else { pointer }));
+            return;
+        }
+    };
+    for (keyword, subschema) in schema {
+        let keyword_pointer = format!("{}/{}", pointer, escape_pointer(keyword));
+        match keyword.as_str() {
+            "properties" | "$defs" | "definitions" => if let Value::Object(subschemas) = subschema {
+                for (name, subschema) in subschemas {
+                    find_unsupported_keywords(subschema, &format!("{}/{}", keyword_pointer, escape_pointer(name)), errors);
+                }
+            },
+            "items" | "additionalProperties" => find_unsupported_keywords(subschema, &keyword_pointer, errors),
+            keyword if VALIDATION_KEYWORDS.contains(&keyword) || ANNOTATION_KEYWORDS.contains(&keyword) => {},
+            keyword => errors.push(format!("unsupported keyword {:?} at {}", keyword, if pointer.is_empty() { "/" } else { pointer })),
+        }
+    }
+}
+
+/// JSON pointers escape ~ and / in names
+fn escape_pointer(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+fn type_matches(value: &Value, type_name: &str) -> bool {
+    match type_name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        _ => false,
+    }
+}
+
+/// Looks up a "#/..." reference in the root schema
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+fn validate_at(value: &Value, schema: &Value, root: &Value, pointer: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(format!("{}: not allowed", pointer));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => {
+            errors.push(format!("{}: schema is neither an object nor a boolean", pointer));
+            return;
+        }
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve_ref(root, reference) {
+            Some(referenced) => validate_at(value, referenced, root, pointer, errors),
+            None => errors.push(format!("{}: unresolvable $ref {:?}", pointer, reference)),
+        }
+    }
+
+    if let Some(type_names) = schema.get("type") {
+        let allowed: Vec<&str> = match type_names {
+            Value::String(type_name) => vec![type_name.as_str()],
+            Value::Array(type_names) => type_names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.iter().any(|type_name| type_matches(value, type_name)) {
+            errors.push(format!("{}: expected {}, got {}", pointer, allowed.join(" or "), value));
+            return;
+        }
+    }
+
+    if let Some(expected) = schema.get("const") && value != expected {
+        errors.push(format!("{}: expected {}, got {}", pointer, expected, value));
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") && !allowed.contains(value) {
+        errors.push(format!("{}: {} is not one of {}", pointer, value, Value::Array(allowed.clone())));
+    }
+    if let (Some(minimum), Some(number)) = (schema.get("minimum").and_then(Value::as_f64), value.as_f64()) && number < minimum {
+        errors.push(format!("{}: {} is less than the minimum of {}", pointer, number, minimum));
+    }
+    if let (Some(maximum), Some(number)) = (schema.get("maximum").and_then(Value::as_f64), value.as_f64()) && number > maximum {
+        errors.push(format!("{}: {} is more than the maximum of {}", pointer, number, maximum));
+    }
+
+    if let Value::Object(object) = value {
+        validate_object(object, schema, root, pointer, errors);
+    }
+    if let (Value::Array(array), Some(items)) = (value, schema.get("items")) {
+        for (index, item) in array.iter().enumerate() {
+            validate_at(item, items, root, &format!("{}/{}", pointer, index), errors);
+        }
+    }
+}
+
+fn validate_object(object: &Map<String, Value>, schema: &Map<String, Value>, root: &Value, pointer: &str, errors: &mut Vec<String>) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(format!("{}: missing required property {:?}", pointer, name));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, property) in object {
+        let property_pointer = format!("{}/{}", pointer, escape_pointer(name));
+        match (properties.and_then(|properties| properties.get(name)), schema.get("additionalProperties")) {
+            (Some(property_schema), _) => validate_at(property, property_schema, root, &property_pointer, errors),
+            (None, Some(additional)) => validate_at(property, additional, root, &property_pointer, errors),
+            (None, None) => {},
+        }
+    }
+}
@@ -0,0 +1,283 @@
+/* lib.rs - The handlers and the types they share with the media-interface executable
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{PathBuf,Path};
+
+pub mod gopro_hero_generic_1;
+pub mod sony_ilcem4_1;
+pub mod generic_single_file_items;
+pub mod helpers;
+pub mod gnss_tracker_generic;
+pub mod fs_provider;
+pub mod manifest_driven_generic;
+pub mod ricoh_theta_generic_1;
+pub mod android_motion_photo_generic_1;
+pub mod dji_drone_generic_1;
+pub mod olympus_generic_1;
+pub mod stereo_pair_generic_1;
+pub mod error;
+pub mod json_schema;
+#[cfg(feature = "checksum")]
+pub mod sha256;
+
+use error::MediaInterfaceError;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum UnknownFilePolicy {
+    Error,
+    Ignore,
+}
+
+/// What to do about a directory of the card that can't be read for lack of permissions
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum PermissionErrorPolicy {
+    Error,
+    /// Skip the directory, adding a warning to the output
+    SkipWarn,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum PartsPolicy {
+    First,
+    All,
+}
+
+//////////////////
+// Handler data //
+//////////////////
+pub trait SourceMediaInterface {
+    fn list_thumbnail(&self, source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>;
+    fn list_high_quality(&self, source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>;
+    /// Every file of the item `source_media_file` belongs to, always including `source_media_file`
+    /// itself. A file the handler doesn't recognise as one of its device's is an error, and the
+    /// result is only empty for files the unknown file policy says to ignore
+    fn get_related(&self, source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>;
+    /// Streaming versions of the list actions, for consumers that handle items as they are found
+    /// or stop early. The default ones run the whole list action first, handlers that can find
+    /// their items one at a time override them
+    fn list_thumbnail_iter<'a>(&'a self, source_media_location: &'a Path, source_media_card: &'a Path, options: &'a HandlerOptions) -> Box<dyn Iterator<Item = Result<FileItem>> + 'a> {
+        helpers::collected_items_iter(self.list_thumbnail(source_media_location, source_media_card, options))
+    }
+    fn list_high_quality_iter<'a>(&'a self, source_media_location: &'a Path, source_media_card: &'a Path, options: &'a HandlerOptions) -> Box<dyn Iterator<Item = Result<FileItem>> + 'a> {
+        helpers::collected_items_iter(self.list_high_quality(source_media_location, source_media_card, options))
+    }
+    fn name(&self) -> &'static str;
+    /// Directories, relative to the card, that every card of the device has at least one of.
+    /// Checked before the handler runs so that pointing it at the wrong kind of card gets a clear
+    /// error. Empty if the handler expects no particular structure
+    fn required_subdirs(&self) -> &'static [&'static str] {
+        &[]
+    }
+    /// Every file of the item `source_media_file` belongs to that a client could show as its
+    /// thumbnail, best first: preview images, preview videos, then the full images and videos,
+    /// each in part order. Ones known to be missing are left out
+    fn thumbnail_candidates(&self, source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Vec<PathBuf> {
+        let Ok(related) = self.get_related(source_media_location, source_media_file, options) else {
+            return Vec::new();
+        };
+        let candidate_types = [helpers::FileType::FileImagePreview, helpers::FileType::FileVideoPreview, helpers::FileType::FileImage, helpers::FileType::FileVideo];
+        candidate_types.iter().flat_map(|candidate_type| {
+            let mut files: Vec<&FileItem> = related.iter().filter(|file| file.file_type == candidate_type.as_str()).collect();
+            files.sort_by_key(|file| file.part_num.unwrap_or(1));
+            files
+        })
+            .map(|file| PathBuf::from(&file.file_path))
+            .filter(|path| !options.known_missing_files.contains(path) && options.fs.exists(path))
+            .collect()
+    }
+    /// The preview of the video item `source_media_file` belongs to, so that clients showing a
+    /// gallery don't need to go through get_related for each item. Preview images are preferred
+    /// over preview videos, ones known to be missing are never picked
+    fn thumbnail_path(&self, source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Option<PathBuf> {
+        let related = self.get_related(source_media_location, source_media_file, options).ok()?;
+        [helpers::FileType::FileImagePreview, helpers::FileType::FileVideoPreview].iter().find_map(|preview_type| {
+            related.iter()
+                .filter(|file| file.file_type == preview_type.as_str())
+                .map(|file| PathBuf::from(&file.file_path))
+                .find(|path| !options.known_missing_files.contains(path) && options.fs.exists(path))
+        })
+    }
+    /// Where the file falls in the order the device captured its items, if its name says
+    fn capture_sequence(&self, _file: &Path) -> Option<helpers::CaptureSequence> {
+        None
+    }
+}
+
+/// Per invocation settings handed to a handler along with the paths it operates on
+pub struct HandlerOptions {
+    pub known_missing_files: Vec<PathBuf>,
+    /// Directories to skip while scanning. Single component entries match a directory by name
+    /// anywhere under the card, longer entries match a path relative to the card directory
+    pub exclude_dirs: Vec<PathBuf>,
+    /// Extensions of files to skip while scanning, matched case insensitively
+    pub ignore_extensions: Vec<String>,
+    /// Files in the card that belong to this tool rather than the device, like tags files, so
+    /// are skipped while scanning
+    pub own_files: Vec<PathBuf>,
+    /// Scan subdirectories of the card as well. Only handlers of devices that keep all their
+    /// files in one directory look at this
+    pub recursive: bool,
+    /// Scan hidden (dot prefixed) files and directories instead of skipping them
+    pub include_hidden: bool,
+    pub unknown_file_policy: UnknownFilePolicy,
+    /// The card directory the input path belongs to
+    pub card_root: PathBuf,
+    pub first_frame_as_thumbnail: bool,
+    pub fs: Box<dyn fs_provider::FsProvider>,
+    pub parts_policy: PartsPolicy,
+    pub count_low_bitrate_parts: bool,
+    /// List clips that have no MP4 left on disk for any of their parts through their LRV in
+    /// list_high_quality, marked as degraded
+    pub lrv_fallback: bool,
+    /// Most parts a recording may have before it's taken for a damaged or badly named directory
+    /// and reported as an error. Handlers use the limit of their device if None
+    pub max_parts: Option<u8>,
+    pub permission_error_policy: PermissionErrorPolicy,
+    /// Warnings about the scan handlers can't return along with their result, like directories
+    /// skipped by the permission error policy. Taken by the caller once the handler returns
+    pub scan_warnings: std::cell::RefCell<Vec<String>>,
+}
+
+impl HandlerOptions {
+    /// The options the command line defaults to, for the card `card_root` on `fs`
+    pub fn new(card_root: PathBuf, fs: Box<dyn fs_provider::FsProvider>) -> HandlerOptions {
+        HandlerOptions{
+            known_missing_files: Vec::new(),
+            exclude_dirs: Vec::new(),
+            ignore_extensions: Vec::new(),
+            own_files: Vec::new(),
+            recursive: false,
+            include_hidden: false,
+            unknown_file_policy: UnknownFilePolicy::Error,
+            card_root,
+            first_frame_as_thumbnail: false,
+            fs,
+            parts_policy: PartsPolicy::First,
+            count_low_bitrate_parts: false,
+            lrv_fallback: false,
+            max_parts: None,
+            permission_error_policy: PermissionErrorPolicy::Error,
+            scan_warnings: std::cell::RefCell::new(Vec::new()),
+        }
+    }
+}
+
+pub fn handler_factories() -> Vec<fn() -> Box<dyn SourceMediaInterface>> {
+    vec![
+        || Box::new(gopro_hero_generic_1::GoProInterface),
+        || Box::new(sony_ilcem4_1::SonyInterface),
+        || Box::new(generic_single_file_items::GenericSingleFileItem),
+        || Box::new(gnss_tracker_generic::GNSSTrackerGeneric),
+        || Box::new(manifest_driven_generic::ManifestDrivenGeneric),
+        || Box::new(ricoh_theta_generic_1::RicohThetaInterface),
+        || Box::new(android_motion_photo_generic_1::AndroidMotionPhotoInterface),
+        || Box::new(dji_drone_generic_1::DJIInterface),
+        || Box::new(olympus_generic_1::OlympusInterface),
+        || Box::new(stereo_pair_generic_1::StereoPairInterface),
+    ]
+}
+
+pub fn get_handler(id: &str) -> Result<Box<dyn SourceMediaInterface>, MediaInterfaceError> {
+    for factory in handler_factories() {
+        let instance = factory();
+        if instance.name() == id {
+            return Ok(instance);
+        }
+    }
+
+    Err(MediaInterfaceError::UnknownHandler(id.to_string()))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileItem {
+    pub file_path: String,
+    pub file_type: String,
+    pub item_type: String,
+    /// Higher is a better representation of the item, see FileType::quality_rank
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality_rank: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub part_count: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub part_num: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_thumbnail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub needs_thumbnail_generation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_base64: Option<String>,
+    /// Set when the file stands in for a higher quality one that no longer exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub degraded: Option<bool>,
+    /// Set for photos that have a video embedded in the same file, like Android motion photos
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_motion: Option<bool>,
+    /// Size in bytes of all the files of the item this file represents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item_total_size: Option<u64>,
+    /// For MP4/MOV videos, whether the file has the index it needs to be played
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playable: Option<bool>,
+    /// For videos, the best preview of the item on the card
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_path: Option<String>,
+    /// From the tags file of the source media, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// What the file is to its item: "primary", "part" for the later parts of a recording split
+    /// in multiple files, "preview", "metadata" or "sidecar"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// Name of the folder the file is in, the roll of cameras that split their shots in numbered
+    /// folders like 100MSDCF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<String>,
+    /// The file representing the item the file belongs to in the list, shared by every file of
+    /// the item with --flatten-parts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item_id: Option<String>,
+    /// Container format of audio and video files, like "mp4" or "mov", whatever the extension
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// Files to try in turn as the thumbnail of the item, best first, with --thumbnail-candidates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_candidates: Option<Vec<String>>,
+    /// The name of the file, extension included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basename: Option<String>,
+    /// The extension of the file in lower case, as extensions are matched ignoring case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    /// The handler that listed the file, when a list action is given more than one card
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handler: Option<String>,
+}
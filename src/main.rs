@@ -17,18 +17,30 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
 
-use anyhow::{Result};
-use clap::{Parser, ArgGroup};
+use anyhow::{anyhow, Result};
+use clap::{Parser, ArgGroup, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::path::{PathBuf,Path};
+use serde::de::DeserializeOwned;
+use std::path::{PathBuf,Path,Component};
 use std::process;
 use std::fs;
+use std::env;
+use std::collections::{BTreeMap, HashSet};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::SystemTime;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use base64::prelude::*;
 
-mod gopro_hero_generic_1;
-mod sony_ilcem4_1;
-mod generic_single_file_items;
-mod helpers;
-mod gnss_tracker_generic;
+use media_interface::{helpers, fs_provider, json_schema, SourceMediaInterface, HandlerOptions, FileItem, PartsPolicy, PermissionErrorPolicy, UnknownFilePolicy, handler_factories, get_handler};
+use media_interface::helpers::{FileType, ItemType, get_extension_str, has_moov_box};
+use media_interface::error::MediaInterfaceError;
+use media_interface::manifest_driven_generic::{Manifest, ManifestItem, ManifestPart, ManifestFile, MANIFEST_DATA_TYPE};
+
+/// Largest preview file --embed-thumbnails will embed, in bytes
+const EMBEDDED_THUMBNAIL_MAX_SIZE: u64 = 64 * 1024;
+/// Videos in an MP4 style container, which --check-playable can look into
+const ISO_MEDIA_EXTENSIONS: [&str; 7] = ["MP4", "MOV", "M4V", "LRV", "LRF", "3GP", "3GPP"];
 
 /////////////////////////////////
 // Command line interface data //
@@ -38,7 +50,8 @@ mod gnss_tracker_generic;
 #[command(group(
     ArgGroup::new("action")
         .required(true)
-        .args(&["list_thumbnail", "list_high_quality", "get_related"])
+        .multiple(true)
+        .args(&["list_thumbnail", "list_high_quality", "get_related", "resolve_errata", "count_expected_vs_present", "summary_per_roll", "emit_relations_graph", "emit_errata_template", "dump_resolved_config", "export_manifest", "verify_manifest", "capabilities"])
 ))]
 struct Cli {
     /// Path to config json file. If none is supplied, a file named "interface_config.json" in the
@@ -47,79 +60,416 @@ struct Cli {
     config: Option<PathBuf>,
 
     /// Print a JSON object with a list of files and info representing items under the given
-    /// directory, prefering the lowest quality representation of the item
-    #[arg(short='l', long="list-thumbnail", value_name="dir path" )]
-    list_thumbnail: Option<PathBuf>,
+    /// directory, prefering the lowest quality representation of the item. Given more than one
+    /// directory, the items of each are listed one after the other
+    #[arg(short='l', long="list-thumbnail", value_name="dir path", num_args=1..)]
+    list_thumbnail: Vec<PathBuf>,
 
     /// Print a JSON object with a list of files and info representing items under the given
-    /// directory, prefering the highest quality representation of the item
-    #[arg(short='L', long="list-high-quality", value_name="dir path")]
-    list_high_quality: Option<PathBuf>,
+    /// directory, prefering the highest quality representation of the item. Given more than one
+    /// directory, the items of each are listed one after the other
+    #[arg(short='L', long="list-high-quality", value_name="dir path", num_args=1..)]
+    list_high_quality: Vec<PathBuf>,
 
     /// Given a file this will output a JSON object with a list of all files in the item that
     /// represent the file
     #[arg(short='g', long="get-related", num_args=1, value_name="file path")]
     get_related: Option<PathBuf>,
+
+    /// Check the known missing files of every source media against the filesystem and report
+    /// which entries are still missing, which files are present again and which entries point
+    /// to a directory that doesn't exist
+    #[arg(long="resolve-errata")]
+    resolve_errata: bool,
+
+    /// Given a card directory this will count, over all of its items, how many of the files
+    /// they should be made of are present, listed as known missing and missing without being
+    /// listed, along with the percentage present
+    #[arg(long="count-expected-vs-present", num_args=1, value_name="card path")]
+    count_expected_vs_present: Option<PathBuf>,
+
+    /// With --count-expected-vs-present, exit with status 2 if less than this percentage of the
+    /// expected files is present
+    #[arg(long="min-completeness", value_name="percent", requires="count_expected_vs_present")]
+    min_completeness: Option<f64>,
+
+    /// Given a card directory this will break its items down by the roll (folder) they are in,
+    /// with the number of items of every roll, by item type, and the total size of their files
+    #[arg(long="summary-per-roll", num_args=1, value_name="card path")]
+    summary_per_roll: Option<PathBuf>,
+
+    /// Given a card directory this will output every file of every item, as get related finds
+    /// them, each with the item_id of its item. With --format dot it's output as a Graphviz graph
+    /// linking every item to its files
+    #[arg(long="emit-relations-graph", num_args=1, value_name="card path")]
+    emit_relations_graph: Option<PathBuf>,
+
+    /// Given a card directory this will find the files its items should have that are missing
+    /// without being listed as known missing files, and output them along with the known missing
+    /// files of the card as an errata block for its per source media config file
+    #[arg(long="emit-errata-template", num_args=1, value_name="card path")]
+    emit_errata_template: Option<PathBuf>,
+
+    /// Output the configuration as it was resolved, for debugging configs: every source media
+    /// with the absolute directory its cards are in, its handler and options, and the absolute
+    /// paths its known missing files resolved to
+    #[arg(long="dump-resolved-config")]
+    dump_resolved_config: bool,
+
+    /// Write a manifest of every item of a card to the given file: the handler, and the files of
+    /// each item by part, relative to the card, with their size and modification time. The card
+    /// is the directory the manifest is written in unless --manifest-card is given. It can be
+    /// read by the Manifest-Driven-Generic-1 handler and checked with --verify-manifest
+    #[arg(long="export-manifest", value_name="manifest file")]
+    export_manifest: Option<PathBuf>,
+
+    /// Check a card against a manifest written by --export-manifest and report the files that
+    /// are missing, the ones that aren't in the manifest and the ones whose size or modification
+    /// time changed, exiting with status 2 if there are any. Doesn't need a config file
+    #[arg(long="verify-manifest", value_name="manifest file", conflicts_with_all=["list_thumbnail", "list_high_quality", "get_related", "resolve_errata", "count_expected_vs_present", "summary_per_roll", "emit_relations_graph", "emit_errata_template", "dump_resolved_config", "export_manifest", "capabilities"])]
+    verify_manifest: Option<PathBuf>,
+
+    /// The card --export-manifest and --verify-manifest work on, when it isn't the directory of
+    /// the manifest file
+    #[arg(long="manifest-card", value_name="card path")]
+    manifest_card: Option<PathBuf>,
+
+    /// Output the optional features this build was compiled with, the output versions it
+    /// produces and the handlers it provides. Doesn't need a config file
+    #[arg(long="capabilities", conflicts_with_all=["list_thumbnail", "list_high_quality", "get_related", "resolve_errata", "count_expected_vs_present", "summary_per_roll", "emit_relations_graph", "emit_errata_template", "dump_resolved_config", "export_manifest", "verify_manifest"])]
+    capabilities: bool,
+
+    /// For video items that have no preview file, point to where a thumbnail generated from the
+    /// first frame would be stored and flag whether it still needs to be generated
+    #[arg(long="first-frame-as-thumbnail")]
+    first_frame_as_thumbnail: bool,
+
+    /// Check the output against a JSON Schema, like schema/output.schema.json, before writing
+    /// it and fail if it doesn't conform. For keeping the schema of clients in sync. Only the
+    /// keywords the shipped schema uses are supported, schemas with others fail
+    #[arg(long="validate-output", value_name="schema file")]
+    validate_output: Option<PathBuf>,
+
+    /// Keep what list actions find in this JSON file and reuse it on later runs for cards none of
+    /// the directories of which were modified since
+    #[arg(long="cache", value_name="cache file")]
+    cache: Option<PathBuf>,
+
+    /// Output all paths relative to the source media directory (the "path" of its config entry)
+    /// instead of as absolute paths
+    #[arg(long="relative-paths")]
+    relative_paths: bool,
+
+    /// Output paths under the home directory starting with ~ instead of the home directory
+    #[arg(long="home-relative")]
+    home_relative: bool,
+
+    /// Include the POSIX permission bits, owner uid and group gid of every file. Only available
+    /// on Unix
+    #[arg(long="stat-owner")]
+    stat_owner: bool,
+
+    /// Embed the contents of preview image files (GoPro THM, Sony THMBNL JPG) in the output,
+    /// base64 encoded, on the preview files themselves and on the video items they are the
+    /// preview of, as with --thumbnail-path. Previews larger than 64KiB are not embedded
+    #[arg(long="embed-thumbnails")]
+    embed_thumbnails: bool,
+
+    /// With list actions, include the total size in bytes of all the files of every item, parts
+    /// and sidecar files included, on the file representing it
+    #[arg(long="item-total-size")]
+    item_total_size: bool,
+
+    /// With list actions, check that MP4/MOV videos are playable by looking for the index
+    /// (moov box) recordings that were cut short don't have. Only the box headers are read
+    #[arg(long="check-playable")]
+    check_playable: bool,
+
+    /// How list actions represent items made of multiple parts: by their first part only, or
+    /// with one entry for each part
+    #[arg(long="parts-policy", value_enum, default_value_t=PartsPolicy::First)]
+    parts_policy: PartsPolicy,
+
+    /// Count parts of GoPro clips that only have their low bitrate LRV file left as existing
+    /// parts. With the "all" parts policy, list_high_quality lists such parts through their LRV
+    #[arg(long="count-low-bitrate-parts")]
+    count_low_bitrate_parts: bool,
+
+    /// With list_high_quality, list GoPro clips that have no MP4 left on disk for any of their
+    /// parts through their low bitrate LRV instead of leaving them out, marked as degraded
+    #[arg(long="lrv-fallback")]
+    lrv_fallback: bool,
+
+    /// Most parts a recording may have, recordings that appear to have more are reported as an
+    /// error. Defaults to the most the device numbers its parts up to, 99 for GoPro cameras
+    #[arg(long="max-parts", value_name="count", value_parser=clap::value_parser!(u8).range(1..))]
+    max_parts: Option<u8>,
+
+    /// Format of the output. With csv the file list of list and get related actions is output as
+    /// CSV, with paths as one path per line and with dot as a Graphviz graph of the items and
+    /// their files. Errors are still output as JSON
+    #[arg(long="format", value_enum, default_value_t=OutputFormat::Json)]
+    format: OutputFormat,
+
+    /// With list actions, output every file of every item, as get related would, instead of only
+    /// the one representing the item
+    #[arg(long="include-related")]
+    include_related: bool,
+
+    /// With list actions, output every file of every item once, like --include-related, each
+    /// with the item_id of the item it belongs to, for clients that handle files one by one and
+    /// regroup them later
+    #[arg(long="flatten-parts", visible_alias="flatten")]
+    flatten_parts: bool,
+
+    /// Order the files of every get_related result by part, then primary file, previews,
+    /// sidecars and metadata, instead of the order the handler finds them in
+    #[arg(long="sort-related")]
+    sort_related: bool,
+
+    /// With list actions, add the files a client can fall back on one after the other when the
+    /// thumbnail of an item doesn't load, best first
+    #[arg(long="thumbnail-candidates")]
+    thumbnail_candidates: bool,
+
+    /// With list actions, add the preview of every video item (GoPro THM, Sony THMBNL JPG, DJI
+    /// LRF) to it, for galleries that don't go through get related for each item
+    #[arg(long="thumbnail-path")]
+    thumbnail_path: bool,
+
+    /// With list actions, leave out the entries that are the same as one listed before them, on the
+    /// same card or another one given before it: the same file going by its canonical path, for
+    /// overlapping scans and symlinked folders, or the same item_id, which with --flatten-parts
+    /// keeps only the first file of every item, or, in builds with the checksum feature, the same
+    /// SHA-256 checksum of the contents, which catches duplicate content stored under different
+    /// names
+    #[arg(long="dedup-by", value_enum, value_name="key")]
+    dedup_by: Option<DedupKey>,
+
+    /// With get related, output only the best file of the item: the one with the highest quality
+    /// rank, or the first part of it for videos made of multiple parts
+    #[arg(long="best-only")]
+    best_only: bool,
+
+    /// Comma separated extensions, most preferred first, that pick the file --best-only outputs
+    /// instead of the quality rank, like "jpg,dng". The quality rank is used if the item has none
+    /// of them
+    #[arg(long="prefer", value_name="extensions", value_delimiter=',', requires="best_only")]
+    prefer: Vec<String>,
+
+    /// What handlers do with files and folders they don't recognise: fail, or leave them out
+    #[arg(long="unknown-file-policy", value_enum, default_value_t=UnknownFilePolicy::Error)]
+    unknown_file_policy: UnknownFilePolicy,
+
+    /// What to do about directories of the card that can't be read for lack of permissions:
+    /// fail, or skip them with a warning
+    #[arg(long="permission-error-policy", value_enum, default_value_t=PermissionErrorPolicy::Error)]
+    permission_error_policy: PermissionErrorPolicy,
+
+    /// Don't skip hidden (dot prefixed) files and directories while scanning. Useful for media
+    /// stored in hidden folders or to see the AppleDouble files macOS leaves around
+    #[arg(long="include-hidden")]
+    include_hidden: bool,
+
+    /// Order the file list by the order the items were captured in, going by the numbers the
+    /// device gives its files even across the counter rolling over. Files the handler can't tell
+    /// the number of are put at the end in natural order
+    #[arg(long="capture-order")]
+    capture_order: bool,
+
+    /// Replace the leading FROM part of paths with TO, for media that was moved since the config
+    /// was written. Applied to the source media paths of the config, to known missing files and
+    /// to input paths. Can be given more than once, the first matching FROM is used
+    #[arg(long="base-path-substitution", visible_alias="subst", value_name="FROM=TO", value_parser=parse_path_substitution)]
+    base_path_substitution: Vec<(PathBuf, PathBuf)>,
+
+    /// Group the file list into capture sessions, starting a new session whenever more than
+    /// the given number of minutes pass between two consecutive items
+    #[arg(long="group-by-session", value_name="gap minutes")]
+    group_by_session: Option<u64>,
+
+    /// List the cards given to a list action each on a thread of its own, for cards on separate
+    /// devices. The output is the same as without it
+    #[arg(long="parallel-cards", conflicts_with="cache")]
+    parallel_cards: bool,
+
+    /// Most cards --parallel-cards lists at the same time. Defaults to the number of CPUs, which
+    /// suits local devices; cards on a slow network mount are better listed a few at a time
+    #[arg(long="scan-concurrency", value_name="threads", value_parser=clap::value_parser!(u64).range(1..), requires="parallel_cards")]
+    scan_concurrency: Option<u64>,
+
+    /// With list actions, only list the items modified after the marker file was. When the
+    /// marker doesn't exist yet everything is listed
+    #[arg(long="since-file", value_name="marker file")]
+    since_file: Option<PathBuf>,
+
+    /// Once listed, set the modification time of the --since-file marker to that of the newest
+    /// item listed, creating the marker if needed, so the next run picks up where this one ended
+    #[arg(long="update-since", requires="since_file")]
+    update_since: bool,
+}
+
+fn parse_path_substitution(arg: &str) -> Result<(PathBuf, PathBuf)> {
+    let (from, to) = arg.split_once('=').ok_or_else(|| anyhow!("expected FROM=TO, got {:?}", arg))?;
+    if from.is_empty() {
+        return Err(anyhow!("FROM can't be empty in {:?}", arg));
+    }
+    Ok((PathBuf::from(from), PathBuf::from(to)))
+}
+
+/// Apply the first --base-path-substitution whose FROM `path` starts with
+fn substitute_path(path: &Path, substitutions: &[(PathBuf, PathBuf)]) -> PathBuf {
+    for (from, to) in substitutions {
+        if let Ok(rest) = path.strip_prefix(from) {
+            return to.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Replace a leading ~ or $HOME component of a path from the config with the home directory. Left
+/// as is when HOME isn't set
+fn expand_home(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    match (components.next(), env::var_os("HOME")) {
+        (Some(Component::Normal(first)), Some(home)) if first == "~" || first == "$HOME" => PathBuf::from(home).join(components.as_path()),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Json,
+    Csv,
+    Paths,
+    Dot,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum DedupKey {
+    Path,
+    #[value(name="item_id", alias="item-id")]
+    ItemId,
+    #[cfg(feature = "checksum")]
+    Sha256,
+}
+
+/// What two entries of a file list need to share to be duplicates. Entries without an item_id are
+/// their own item
+fn dedup_key(item: &FileItem, key: DedupKey, fs: &dyn fs_provider::FsProvider) -> String {
+    match key {
+        DedupKey::Path => fs.canonicalize(Path::new(&item.file_path)).map(|path| path.to_string_lossy().into_owned()).unwrap_or_else(|_| item.file_path.clone()),
+        DedupKey::ItemId => item.item_id.clone().unwrap_or_else(|| item.file_path.clone()),
+        // Files that can't be read are only ever duplicates of themselves
+        #[cfg(feature = "checksum")]
+        DedupKey::Sha256 => media_interface::sha256::file_sha256(fs, Path::new(&item.file_path)).map(|hash| format!("sha256 {}", hash)).unwrap_or_else(|_| item.file_path.clone()),
+    }
 }
 
 //////////////////////
 // config file data //
 //////////////////////
+/// The versions of the config format this binary understands. Configs without a config_version
+/// are version 1
+const SUPPORTED_CONFIG_VERSIONS: std::ops::RangeInclusive<u32> = 1..=1;
+
+/// What every config file starts with, checked before the rest of the file is parsed so that a
+/// config written for another version of the program is reported as such
 #[derive(Deserialize)]
-struct MainConfig {
+struct ConfigHeader {
     data_type: String,
+    config_version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct MainConfig {
     source_media: Vec<SourceMediaEntry>,
+    exclude_dirs: Option<Vec<PathBuf>>,
+    /// Directory relative source media paths are resolved against instead of the config file's
+    /// directory. Itself relative to the config file's directory if not absolute
+    media_base: Option<PathBuf>,
 }
 #[derive(Deserialize)]
 struct PerSourceConfig {
-    data_type: String,
     errata: Option<Errata>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct Errata {
     known_missing_files: Option<Vec<PathBuf>>,
 }
 
+/// A .media_interface.json in a directory of a card, naming the handler for that directory and
+/// everything under it. Options left out are the ones of the source media
 #[derive(Deserialize)]
-struct SourceMediaEntry {
+struct DirectoryOverride {
     handler: String,
-    card_subdir: PathBuf,
-    path: PathBuf,
+    #[serde(default)]
+    options: OverrideOptions,
 }
 
-//////////////////
-// Handler data //
-//////////////////
-trait SourceMediaInterface {
-    fn list_thumbnail(&self, source_media_location: &Path, source_media_card: &Path, known_missing_file: Vec<PathBuf>) -> Result<Vec<FileItem>>;
-    fn list_high_quality(&self, source_media_location: &Path, source_media_card: &Path, known_missing_file: Vec<PathBuf>) -> Result<Vec<FileItem>>;
-    fn get_related(&self, source_media_location: &Path, source_media_file: &Path, known_missing_file: Vec<PathBuf>) -> Result<Vec<FileItem>>;
-    fn name(&self) -> &'static str;
+#[derive(Deserialize, Default)]
+struct OverrideOptions {
+    /// Relative to the directory of the override
+    exclude_dirs: Option<Vec<PathBuf>>,
+    ignore_extensions: Option<Vec<String>>,
+    recursive: Option<bool>,
 }
 
-fn get_handler(id: &str) -> Result<Box<dyn SourceMediaInterface>> {
-    let factories: Vec<fn() -> Box<dyn SourceMediaInterface>> = vec![
-        || Box::new(gopro_hero_generic_1::GoProInterface),
-        || Box::new(sony_ilcem4_1::SonyInterface),
-        || Box::new(generic_single_file_items::GenericSingleFileItem),
-        || Box::new(gnss_tracker_generic::GNSSTrackerGeneric),
-    ];
+#[derive(Deserialize)]
+struct SourceMediaEntry {
+    handler: String,
+    /// Directory under `path` the card directories are in. Cards are directly in `path` if not set
+    card_subdir: Option<PathBuf>,
+    path: PathBuf,
+    exclude_dirs: Option<Vec<PathBuf>>,
+    /// Extensions of files the handler should skip instead of trying to classify
+    ignore_extensions: Option<Vec<String>>,
+    /// Scan the subdirectories of cards too, like the YYYY/MM folders of phone exports
+    recursive: Option<bool>,
+    /// File in each card, relative to it, with tags and captions for the items of the card
+    tags_file: Option<PathBuf>,
+}
 
-    for factory in factories {
-        let instance = factory();
-        if instance.name() == id {
-            return Ok(instance);
-        }
+fn capabilities() -> CapabilitiesJson {
+    let mut features = Vec::new();
+    if cfg!(feature = "gpano") {
+        features.push("gpano");
+    }
+    if cfg!(feature = "motion-photo") {
+        features.push("motion-photo");
+    }
+    if cfg!(feature = "yaml") {
+        features.push("yaml");
+    }
+    if cfg!(feature = "toml") {
+        features.push("toml");
+    }
+    if cfg!(feature = "archive") {
+        features.push("archive");
+    }
+    if cfg!(feature = "media-probe") {
+        features.push("media-probe");
+    }
+    if cfg!(feature = "checksum") {
+        features.push("checksum");
     }
 
-    Err(anyhow::anyhow!("Unknown handler ID '{}'", id))
+    CapabilitiesJson{
+        features,
+        versions: vec![env!("CARGO_PKG_VERSION")],
+        handlers: handler_factories().into_iter().map(|factory| factory().name()).collect(),
+    }
 }
 
 struct HandlerMapEntry{
     name: String,
     location: PathBuf,
     root: PathBuf,
+    exclude_dirs: Vec<PathBuf>,
+    ignore_extensions: Vec<String>,
+    recursive: bool,
+    tags_file: Option<PathBuf>,
 }
 
 ////////////////////////////////
@@ -133,160 +483,1648 @@ struct OutputJson {
     #[serde(skip_serializing_if = "Option::is_none")]
     file_list: Option<Vec<FileItem>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    error_string: Option<String>
+    error_string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errata_report: Option<Vec<ErrataReportEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sessions: Option<SessionsJson>,
+    /// Set instead of the payload fields above when more than one action was requested, keyed
+    /// by the name of the action
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<BTreeMap<&'static str, ActionResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<CapabilitiesJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completeness: Option<CompletenessJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roll_summary: Option<BTreeMap<String, RollSummaryJson>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errata_template: Option<ErrataTemplateJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_config: Option<ResolvedConfigJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exported_manifest: Option<ExportedManifestJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest_report: Option<ManifestReportJson>,
+}
+
+#[derive(Serialize)]
+struct CompletenessJson {
+    expected: usize,
+    present: usize,
+    known_missing: usize,
+    unexpected_missing: usize,
+    percent: f64,
+}
+
+/// Sizes are in bytes and count every file of the items of the roll
+#[derive(Serialize)]
+struct RollSummaryJson {
+    items: usize,
+    item_types: BTreeMap<String, usize>,
+    total_size: u64,
+}
+
+/// Known missing files are relative to the directory of `config_file`, the per source media
+/// config file they belong in
+#[derive(Serialize)]
+struct ErrataTemplateJson {
+    config_file: String,
+    errata: Errata,
+}
+
+#[derive(Serialize)]
+struct ResolvedConfigJson {
+    config_file: String,
+    source_media: Vec<ResolvedSourceMediaJson>,
+}
+
+#[derive(Serialize)]
+struct ResolvedSourceMediaJson {
+    handler: String,
+    /// The directory the cards are in
+    location: String,
+    /// The directory the per source media config file is in
+    root: String,
+    per_source_config: String,
+    exclude_dirs: Vec<String>,
+    ignore_extensions: Vec<String>,
+    recursive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags_file: Option<String>,
+    known_missing_files: Vec<String>,
 }
 
 #[derive(Serialize)]
-struct FileItem {
-    file_path: String,
-    file_type: String,
-    item_type: String,
+struct ExportedManifestJson {
+    manifest_file: String,
+    card: String,
+    items: usize,
+    files: usize,
+}
+
+/// Paths are relative to the card
+#[derive(Serialize)]
+struct ManifestReportJson {
+    manifest_file: String,
+    card: String,
+    missing: Vec<String>,
+    extra: Vec<String>,
+    changed: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CapabilitiesJson {
+    features: Vec<&'static str>,
+    versions: Vec<&'static str>,
+    handlers: Vec<&'static str>,
+}
+
+/// The payload of one action out of many requested in the same invocation
+#[derive(Serialize)]
+struct ActionResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_list: Option<Vec<FileItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errata_report: Option<Vec<ErrataReportEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sessions: Option<SessionsJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completeness: Option<CompletenessJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roll_summary: Option<BTreeMap<String, RollSummaryJson>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    part_count: Option<u8>,
+    errata_template: Option<ErrataTemplateJson>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    part_num: Option<u8>,
+    resolved_config: Option<ResolvedConfigJson>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    metadata_file: Option<String>,
+    exported_manifest: Option<ExportedManifestJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest_report: Option<ManifestReportJson>,
+}
+
+#[derive(Serialize)]
+struct SessionsJson {
+    gap_minutes: u64,
+    sessions: Vec<SessionJson>,
+    ungrouped: Vec<FileItem>,
+}
+
+/// Capture times are seconds since the Unix epoch
+#[derive(Serialize)]
+struct SessionJson {
+    start: u64,
+    end: u64,
+    file_list: Vec<FileItem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ErrataStatus {
+    /// The file is still missing, the entry is valid
+    Missing,
+    /// The file exists now so the entry is obsolete
+    Present,
+    /// The directory the file should be in doesn't exist
+    Unresolved,
+}
+
+#[derive(Serialize)]
+struct ErrataReportEntry {
+    config_file: String,
+    entry: String,
+    resolved_path: String,
+    status: ErrataStatus,
 }
 
 //////////
 // Main //
 //////////
 
+/// Apply `rewrite` to every path a FileItem carries so that all of them stay consistent
+fn rewrite_item_paths<F>(items: &mut [FileItem], rewrite: F) where
+    F: Fn(&str) -> String,
+{
+    for item in items {
+        item.file_path = rewrite(&item.file_path);
+        if let Some(metadata_file) = &item.metadata_file {
+            item.metadata_file = Some(rewrite(metadata_file));
+        }
+        if let Some(generated_thumbnail) = &item.generated_thumbnail {
+            item.generated_thumbnail = Some(rewrite(generated_thumbnail));
+        }
+        if let Some(thumbnail_path) = &item.thumbnail_path {
+            item.thumbnail_path = Some(rewrite(thumbnail_path));
+        }
+        if let Some(item_id) = &item.item_id {
+            item.item_id = Some(rewrite(item_id));
+        }
+        if let Some(candidates) = &mut item.thumbnail_candidates {
+            for candidate in candidates {
+                *candidate = rewrite(candidate);
+            }
+        }
+    }
+}
+
 fn create_base_output_json() -> OutputJson {
     OutputJson{
         data_type: "source_media_interface_api",
         version: env!("CARGO_PKG_VERSION"),
         command_success: false,
         file_list: None,
-        error_string: Some("Uninitialised error message".to_string())
+        error_string: Some("Uninitialised error message".to_string()),
+        warnings: None,
+        errata_report: None,
+        sessions: None,
+        results: None,
+        capabilities: None,
+        completeness: None,
+        roll_summary: None,
+        errata_template: None,
+        resolved_config: None,
+        exported_manifest: None,
+        manifest_report: None,
+    }
+}
+
+/// Every output goes through here so that, whatever the format, it is only ever "\n" terminated
+/// lines with exactly one "\n" at the end of the output and never a BOM
+fn write_output(text: &str) {
+    let text = text.trim_start_matches('\u{feff}').trim_end_matches(['\n', '\r']);
+    println!("{}", text);
+}
+
+fn validate_output(output: &OutputJson, schema_path: &Path) -> Result<(), MediaInterfaceError> {
+    let data = fs::read_to_string(schema_path)
+        .map_err(MediaInterfaceError::io(format!("Failed to read schema file {:?}", schema_path)))?;
+    let schema: serde_json::Value = serde_json::from_str(&data)
+        .map_err(|e| MediaInterfaceError::ConfigParse{ file: schema_path.to_path_buf(), format: "JSON", detail: e.to_string() })?;
+    let value = serde_json::to_value(output).expect("the output is always serializable");
+    let errors = json_schema::validate(&value, &schema);
+    if !errors.is_empty() {
+        return Err(MediaInterfaceError::OutputSchemaMismatch{ schema: schema_path.to_path_buf(), errors });
     }
+    Ok(())
 }
 
 fn fail_main( error: String ) -> ! {
     let mut data = create_base_output_json();
     data.error_string=Some(error.clone());
     data.file_list=None;
-    println!("{}", serde_json::to_string(&data).unwrap_or_else(|_| "Failed to serialise json".to_string()));
+    write_output(&serde_json::to_string(&data).unwrap_or_else(|_| "Failed to serialise json".to_string()));
     eprintln!("{}", error);
     process::exit(1);
 }
 
-fn main() -> Result<()> {
-
-    let cli = Cli::parse();
-
-    //Get config file location
-    let config_file_path:PathBuf = match cli.config {
-        Some(p) => p,
-        None => {
-            let invoked_path = PathBuf::from(env::args().next().unwrap());
-
-            let absolute_invoked_path = if invoked_path.is_absolute() {
+/// The config file that sits next to the executable. The executable's location is taken from
+/// the OS, falling back to argv[0] if that isn't available
+fn default_config_path() -> Result<PathBuf> {
+    let executable = match env::current_exe() {
+        Ok(path) => path,
+        Err(_) => {
+            let invoked_path = PathBuf::from(env::args_os().next().ok_or_else(|| anyhow!("No argv[0] to locate the executable with"))?);
+            if invoked_path.is_absolute() {
                 invoked_path
             } else {
-                env::current_dir().unwrap().join(invoked_path)
-            };
-
-            absolute_invoked_path.parent().unwrap().join(PathBuf::from("interface_config.json"))
+                env::current_dir()?.join(invoked_path)
+            }
         }
     };
 
-    // Load config file
-    let data = std::fs::read_to_string(&config_file_path)
-        .unwrap_or_else(|e| fail_main(format!("Failed to read config file {:?}: {}", config_file_path, e)));
+    let dir = executable.parent().ok_or_else(|| anyhow!("Executable path {:?} has no parent directory", executable))?;
+    Ok(dir.join("interface_config.json"))
+}
+
+/// Parse a config file in the format its extension says: YAML for .yaml/.yml and TOML for .toml if
+/// support for them was compiled in, JSON for anything else
+fn parse_config<T: DeserializeOwned>(file: &Path, data: &str) -> Result<T, MediaInterfaceError> {
+    let extension = file.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    let (format, parsed) = match extension.as_deref() {
+        #[cfg(feature = "yaml")]
+        Some("yaml" | "yml") => ("YAML", serde_yaml::from_str(data).map_err(|e| e.to_string())),
+        #[cfg(not(feature = "yaml"))]
+        Some("yaml" | "yml") => ("YAML", Err("this build doesn't have the yaml feature".to_string())),
+        #[cfg(feature = "toml")]
+        Some("toml") => ("TOML", toml::from_str(data).map_err(|e| e.to_string())),
+        #[cfg(not(feature = "toml"))]
+        Some("toml") => ("TOML", Err("this build doesn't have the toml feature".to_string())),
+        _ => ("JSON", serde_json::from_str(data).map_err(|e| e.to_string())),
+    };
+    parsed.map_err(|detail| MediaInterfaceError::ConfigParse{ file: file.to_path_buf(), format, detail })
+}
 
-    let cfg: MainConfig = serde_json::from_str(&data)?;
+fn check_config_header(file: &Path, data: &str) -> Result<(), MediaInterfaceError> {
+    let header: ConfigHeader = parse_config(file, data)?;
+    if header.data_type != "source_media_config" {
+        return Err(MediaInterfaceError::InvalidDataType(header.data_type));
+    }
+    let version = header.config_version.unwrap_or(1);
+    if !SUPPORTED_CONFIG_VERSIONS.contains(&version) {
+        return Err(MediaInterfaceError::UnsupportedConfigVersion{ file: file.to_path_buf(), version, supported: SUPPORTED_CONFIG_VERSIONS });
+    }
+    Ok(())
+}
 
-    if cfg.data_type != "source_media_config" {
-        fail_main(format!("Invalid data type on the config file: {}", cfg.data_type));
+/// The directory paths in a config file are relative to. A bare file name has an empty parent,
+/// meaning the current directory
+fn config_dir(config_file_path: &Path) -> Result<&Path, MediaInterfaceError> {
+    match config_file_path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Ok(Path::new(".")),
+        Some(dir) => Ok(dir),
+        None => Err(MediaInterfaceError::ConfigWithoutParent(config_file_path.to_path_buf())),
     }
+}
+
+/// Read the main config file and resolve every source media entry in it
+fn load_handlers(config_file_path: &Path, substitutions: &[(PathBuf, PathBuf)]) -> Result<Vec<HandlerMapEntry>, MediaInterfaceError> {
+    // Before reading it, so that a root path is reported as such rather than as a directory
+    let config_dir = config_dir(config_file_path)?;
+    let data = std::fs::read_to_string(config_file_path)
+        .map_err(MediaInterfaceError::io(format!("Failed to read config file {:?}", config_file_path)))?;
+
+    check_config_header(config_file_path, &data)?;
+    let cfg: MainConfig = parse_config(config_file_path, &data)?;
 
     // Load handler data from config data
+    let media_base: PathBuf = match &cfg.media_base {
+        Some(media_base) => config_dir.join(expand_home(media_base)),
+        None => config_dir.to_path_buf(),
+    };
     let mut handlers: Vec<HandlerMapEntry> = Vec::new();
     for cam in cfg.source_media {
-        let source_root: PathBuf = config_file_path.parent().unwrap().join(&cam.path);
-        let path: PathBuf = source_root.join(&cam.card_subdir);
+        let source_root: PathBuf = substitute_path(&media_base.join(expand_home(&cam.path)), substitutions);
+        let path: PathBuf = match &cam.card_subdir {
+            Some(card_subdir) => source_root.join(expand_home(card_subdir)),
+            None => source_root.clone(),
+        };
         let absolute_path: PathBuf = fs::canonicalize(&path)
-            .unwrap_or_else(|e| fail_main(format!("Error reading source media dir {:?}: {}", &path, e)));
-        handlers.push(HandlerMapEntry{location:absolute_path,name:cam.handler,root:source_root});
+            .map_err(MediaInterfaceError::io(format!("Error reading source media dir {:?}", &path)))?;
+        let absolute_root: PathBuf = fs::canonicalize(&source_root)
+            .map_err(MediaInterfaceError::io(format!("Error reading source media dir {:?}", &source_root)))?;
+        if !absolute_path.starts_with(&absolute_root) {
+            return Err(MediaInterfaceError::CardSubdirOutsideSource{ card_subdir: cam.card_subdir.unwrap_or_default(), source_root });
+        }
+        let mut exclude_dirs = cfg.exclude_dirs.clone().unwrap_or_default();
+        exclude_dirs.extend(cam.exclude_dirs.unwrap_or_default());
+        let ignore_extensions = cam.ignore_extensions.unwrap_or_default();
+        let recursive = cam.recursive.unwrap_or(false);
+        handlers.push(HandlerMapEntry{location:absolute_path,name:cam.handler,root:source_root,exclude_dirs,ignore_extensions,recursive,tags_file:cam.tags_file});
+    }
+
+    Ok(handlers)
+}
+
+fn main() -> Result<()> {
+
+    let cli = Cli::parse();
+
+    if cli.capabilities {
+        let mut output = create_base_output_json();
+        output.command_success = true;
+        output.error_string = None;
+        output.capabilities = Some(capabilities());
+        if let Some(schema_path) = &cli.validate_output {
+            validate_output(&output, schema_path).unwrap_or_else(|e| fail_main(e.to_string()));
+        }
+        write_output(&serde_json::to_string(&output)?);
+        return Ok(());
     }
 
-    // execute the appropriate code of the appropriate handler
-    let output = if let Some(input_file) = cli.list_thumbnail.as_ref() {
+    if let Some(manifest_file) = &cli.verify_manifest {
+        let output = verify_manifest(manifest_file, &cli).unwrap_or_else(|e| fail_main(e.to_string()));
+        if let Some(schema_path) = &cli.validate_output {
+            validate_output(&output, schema_path).unwrap_or_else(|e| fail_main(e.to_string()));
+        }
+        write_output(&serde_json::to_string(&output)?);
+        let report = output.manifest_report.as_ref().expect("verify_manifest always reports");
+        if !report.missing.is_empty() || !report.extra.is_empty() || !report.changed.is_empty() {
+            process::exit(2);
+        }
+        return Ok(());
+    }
 
-        handle_action_with_input( input_file, handlers, true,
-            |handler, base, file, known_missing_files| handler.list_thumbnail(base, file, known_missing_files))
+    //Get config file location
+    let config_file_path:PathBuf = match cli.config.clone() {
+        Some(p) => p,
+        None => default_config_path()
+            .unwrap_or_else(|e| fail_main(format!("Failed to locate the config file next to the executable: {}", e))),
+    };
 
-    }else if let Some(input_file) = cli.list_high_quality.as_ref() {
+    let handlers = load_handlers(&config_file_path, &cli.base_path_substitution).unwrap_or_else(|e| fail_main(e.to_string()));
 
-        handle_action_with_input( input_file, handlers, true,
-            |handler, base, file, known_missing_files| handler.list_high_quality(base, file, known_missing_files))
+    // execute the appropriate code of the appropriate handler for every action requested
+    type Job<'a> = Box<dyn Fn() -> Result<OutputJson, MediaInterfaceError> + 'a>;
+    let mut jobs: Vec<(&'static str, Job)> = Vec::new();
 
-    }else if let Some(input_file) = cli.get_related.as_ref() {
+    if !cli.list_thumbnail.is_empty() {
+        jobs.push(("list_thumbnail", Box::new(|| list_cards("list_thumbnail", &cli.list_thumbnail, &handlers, &cli,
+            |handler, base, file, options| handler.list_thumbnail(base, file, options)))));
+    }
+    if !cli.list_high_quality.is_empty() {
+        jobs.push(("list_high_quality", Box::new(|| list_cards("list_high_quality", &cli.list_high_quality, &handlers, &cli,
+            |handler, base, file, options| handler.list_high_quality(base, file, options)))));
+    }
+    if let Some(input_file) = cli.get_related.as_ref() {
+        jobs.push(("get_related", Box::new(|| handle_action_with_input("get_related", input_file, &handlers, false, false, &cli,
+            |handler, base, file, options| handler.get_related(base, file, options).map(|mut items| {
+                helpers::link_metadata_file(&mut items);
+                if cli.sort_related {
+                    helpers::sort_related(&mut items);
+                }
+                items
+            })).map(|listing| arrange_files(listing.files, listing.warnings, &cli)))));
+    }
+    if cli.resolve_errata {
+        jobs.push(("resolve_errata", Box::new(|| resolve_errata(&handlers, &cli.base_path_substitution))));
+    }
+    if let Some(input_file) = cli.count_expected_vs_present.as_ref() {
+        jobs.push(("count_expected_vs_present", Box::new(|| count_expected_vs_present(input_file, &handlers, &cli))));
+    }
+    if let Some(input_file) = cli.summary_per_roll.as_ref() {
+        jobs.push(("summary_per_roll", Box::new(|| summary_per_roll(input_file, &handlers, &cli))));
+    }
+    if let Some(input_file) = cli.emit_relations_graph.as_ref() {
+        jobs.push(("emit_relations_graph", Box::new(|| emit_relations_graph(input_file, &handlers, &cli))));
+    }
+    if let Some(input_file) = cli.emit_errata_template.as_ref() {
+        jobs.push(("emit_errata_template", Box::new(|| emit_errata_template(input_file, &handlers, &cli))));
+    }
+    if let Some(manifest_file) = cli.export_manifest.as_ref() {
+        jobs.push(("export_manifest", Box::new(|| export_manifest(manifest_file, &handlers, &cli))));
+    }
+    if cli.dump_resolved_config {
+        jobs.push(("dump_resolved_config", Box::new(|| dump_resolved_config(&config_file_path, &handlers, &cli.base_path_substitution))));
+    }
 
-        handle_action_with_input( input_file, handlers, false,
-            |handler, base, file, known_missing_files| handler.get_related(base, file, known_missing_files))
+    let mut results: Vec<(&'static str, OutputJson)> = Vec::new();
+    for (action, job) in &jobs {
+        let result = job().unwrap_or_else(|e| fail_main(e.to_string()));
+        for warning in result.warnings.iter().flatten() {
+            eprintln!("warning: {}", warning);
+        }
+        results.push((action, result));
+    }
 
-    }else{
-        fail_main( "Internal error: no action selected".into())
+    let output = match results.len() {
+        0 => fail_main( "Internal error: no action selected".into()),
+        1 => results.remove(0).1,
+        _ => {
+            let mut output = create_base_output_json();
+            output.command_success = true;
+            output.error_string = None;
+            output.results = Some(results.into_iter().map(|(action, result)| (action, ActionResult{
+                file_list: result.file_list,
+                warnings: result.warnings,
+                errata_report: result.errata_report,
+                sessions: result.sessions,
+                completeness: result.completeness,
+                roll_summary: result.roll_summary,
+                errata_template: result.errata_template,
+                resolved_config: result.resolved_config,
+                exported_manifest: result.exported_manifest,
+                manifest_report: result.manifest_report,
+            })).collect());
+            output
+        }
     };
 
-    // Output response from handler as json
-    println!("{}", serde_json::to_string(&output)?);
+    if let Some(schema_path) = &cli.validate_output {
+        validate_output(&output, schema_path).unwrap_or_else(|e| fail_main(e.to_string()));
+    }
+
+    // Output response from handler
+    match cli.format {
+        OutputFormat::Json => write_output(&serde_json::to_string(&output)?),
+        OutputFormat::Csv => {
+            let file_list = output.file_list.as_ref()
+                .unwrap_or_else(|| fail_main("CSV output is only available for actions that output a plain file list".into()));
+            write_output(&file_list_to_csv(file_list));
+        }
+        OutputFormat::Paths => {
+            let file_list = output.file_list.as_ref()
+                .unwrap_or_else(|| fail_main("Paths output is only available for actions that output a plain file list".into()));
+            write_output(&file_list.iter().map(|item| item.file_path.as_str()).collect::<Vec<_>>().join("\n"));
+        }
+        OutputFormat::Dot => {
+            let file_list = output.file_list.as_ref()
+                .unwrap_or_else(|| fail_main("DOT output is only available for actions that output a plain file list".into()));
+            write_output(&file_list_to_dot(file_list));
+        }
+    }
+
+    if let Some(min_completeness) = cli.min_completeness {
+        let completeness = output.completeness.as_ref()
+            .or_else(|| output.results.as_ref().and_then(|results| results.get("count_expected_vs_present")).and_then(|result| result.completeness.as_ref()));
+        if completeness.is_some_and(|completeness| completeness.percent < min_completeness) {
+            process::exit(2);
+        }
+    }
 
     Ok(())
 }
 
-fn handle_action_with_input<F>(input_file: &Path, handlers: Vec<HandlerMapEntry>, arg_is_card: bool, action: F, ) -> OutputJson where
-    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, Vec<PathBuf>) -> Result<Vec<FileItem>>,
-{
-    let mut output = create_base_output_json();
-    let mut known_missing_files: Vec<PathBuf> = Vec::new();
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
 
-    let file = fs::canonicalize(input_file)
-        .unwrap_or_else(|e| fail_main(format!("error finding the absolute path of input file: {}", e)));
+/// One row per file with a fixed set of columns. Fields an item doesn't have are left empty
+fn file_list_to_csv(file_list: &[FileItem]) -> String {
+    let mut csv = String::from("file_path,file_type,item_type,part_count,part_num,metadata_file\n");
+    for item in file_list {
+        let row = [
+            csv_field(&item.file_path),
+            csv_field(&item.file_type),
+            csv_field(&item.item_type),
+            item.part_count.map(|count| count.to_string()).unwrap_or_default(),
+            item.part_num.map(|num| num.to_string()).unwrap_or_default(),
+            item.metadata_file.as_deref().map(csv_field).unwrap_or_default(),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
 
-    let handler_entry = handlers.iter()
-        .find(|entry| file.starts_with(&entry.location))
-        .unwrap_or_else(|| fail_main("Couldn't find handler responsible for a dir in the path of the input file".to_string()));
+fn dot_id(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
 
-    let handler = get_handler(&handler_entry.name)
-        .unwrap_or_else(|e| fail_main(format!("couldn't load handler {}: {}", handler_entry.name, e)));
+/// A node for every item, named after its item_id or the file representing it, with an edge to
+/// each of its files labelled by role and part
+fn file_list_to_dot(file_list: &[FileItem]) -> String {
+    let mut dot = String::from("digraph relations {\n");
+    let mut items = HashSet::new();
+    for file in file_list {
+        let item = file.item_id.as_deref().unwrap_or(&file.file_path);
+        let item_node = dot_id(&format!("item:{}", item));
+        let name = |path: &str| Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.to_string());
+        if items.insert(item) {
+            dot.push_str(&format!("\t{} [shape=box, label={}];\n", item_node, dot_id(&format!("{} {}", file.item_type, name(item)))));
+        }
+        let role = file.role.as_deref().unwrap_or(&file.file_type);
+        let label = match file.part_num {
+            Some(part_num) if role == "part" => format!("part {}", part_num),
+            Some(part_num) => format!("{} part {}", role, part_num),
+            None => role.to_string(),
+        };
+        dot.push_str(&format!("\t{} [label={}];\n", dot_id(&file.file_path), dot_id(&name(&file.file_path))));
+        dot.push_str(&format!("\t{} -> {} [label={}];\n", item_node, dot_id(&file.file_path), dot_id(&label)));
+    }
+    dot.push('}');
+    dot
+}
 
-    let per_source_config = handler_entry.root.join(PathBuf::from("interface_config.json"));
-    if per_source_config.exists() {
-        let data = std::fs::read_to_string(&per_source_config)
-            .unwrap_or_else(|e| fail_main(format!("Failed to read per source config file {:?}: {}", per_source_config, e)));
+fn per_source_config_path(handler_entry: &HandlerMapEntry) -> PathBuf {
+    handler_entry.root.join(PathBuf::from("interface_config.json"))
+}
 
-        let cfg: PerSourceConfig = serde_json::from_str(&data).unwrap_or_else(|e| fail_main(format!("Failed to parse JSON from per source config file {:?}: {}",per_source_config, e)));
+/// Resolve an errata entry to the path handlers compute for the same file. The file itself is
+/// missing so it can't be canonicalized; instead "." and ".." are resolved lexically and, if the
+/// directory it should be in exists, that is canonicalized. The directory not existing (yet) is
+/// fine, the entry then just can't match anything
+fn resolve_errata_path(base: &Path, entry: &Path) -> PathBuf {
+    let path = fs_provider::resolve_dots(&base.join(entry));
 
-        if cfg.data_type != "source_media_config" {
-            fail_main(format!("Invalid data type on the config file: {}", cfg.data_type));
-        }
+    if let (Some(dir), Some(name)) = (path.parent(), path.file_name())
+        && let Ok(dir) = fs::canonicalize(dir) {
+        return dir.join(name);
+    }
+    path
+}
+
+/// Read the known missing files of a per source config file, if it exists, returning each entry
+/// as written along with the absolute path it refers to
+fn read_errata(per_source_config: &Path, substitutions: &[(PathBuf, PathBuf)]) -> Result<Vec<(PathBuf, PathBuf)>, MediaInterfaceError> {
+    let mut entries = Vec::new();
+
+    if per_source_config.exists() {
+        let data = std::fs::read_to_string(per_source_config)
+            .map_err(MediaInterfaceError::io(format!("Failed to read per source config file {:?}", per_source_config)))?;
+
+        check_config_header(per_source_config, &data)?;
+        let cfg: PerSourceConfig = parse_config(per_source_config, &data)?;
 
         if let Some(errata) = &cfg.errata && let Some(known_missing_files_input) = &errata.known_missing_files {
+            let path: PathBuf = config_dir(per_source_config)?.to_path_buf();
+            let base: PathBuf = fs::canonicalize(&path)
+                .map_err(MediaInterfaceError::io(format!("Error reading errata missing file {:?}", &path)))?;
             for file_input in known_missing_files_input{
-                let path: PathBuf = per_source_config.parent().unwrap().to_path_buf();
-                let absolute_path: PathBuf = fs::canonicalize(&path)
-                    .unwrap_or_else(|e| fail_main(format!("Error reading errata missing file {:?}: {}", &path, e))).join(file_input);
-                known_missing_files.push(absolute_path);
+                entries.push((file_input.clone(), resolve_errata_path(&base, &substitute_path(&expand_home(file_input), substitutions))));
             }
         }
     }
 
-    if arg_is_card && file.parent().unwrap() != handler_entry.location {
-        fail_main("List path entered is not a card directory".to_string());
+    Ok(entries)
+}
+
+fn resolve_errata(handlers: &[HandlerMapEntry], substitutions: &[(PathBuf, PathBuf)]) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut report = Vec::new();
+
+    for handler_entry in handlers {
+        let per_source_config = per_source_config_path(handler_entry);
+        for (entry, absolute_path) in read_errata(&per_source_config, substitutions)? {
+            let status = if absolute_path.exists() {
+                ErrataStatus::Present
+            } else if absolute_path.parent().is_some_and(|dir| dir.is_dir()) {
+                ErrataStatus::Missing
+            } else {
+                ErrataStatus::Unresolved
+            };
+            report.push(ErrataReportEntry{
+                config_file: per_source_config.to_string_lossy().into_owned(),
+                entry: entry.to_string_lossy().into_owned(),
+                resolved_path: absolute_path.to_string_lossy().into_owned(),
+                status,
+            });
+        }
     }
 
-    output.file_list = Some(
-        action(handler.as_ref(), &handler_entry.location, &file, known_missing_files)
-            .unwrap_or_else(|e| fail_main(format!("handler {}: {}", handler.name(), e)))
-    );
+    output.errata_report = Some(report);
+    output.command_success = true;
+    output.error_string = None;
+
+    Ok(output)
+}
+
+fn dump_resolved_config(config_file_path: &Path, handlers: &[HandlerMapEntry], substitutions: &[(PathBuf, PathBuf)]) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut warnings = Vec::new();
+    let mut source_media = Vec::new();
+    let path_string = |path: &Path| path.to_string_lossy().into_owned();
+    let absolute = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    for handler_entry in handlers {
+        if let Err(e) = get_handler(&handler_entry.name) {
+            warnings.push(e.to_string());
+        }
+        let per_source_config = per_source_config_path(handler_entry);
+        let known_missing_files = read_errata(&per_source_config, substitutions)?.into_iter()
+            .map(|(_, absolute_path)| path_string(&absolute_path)).collect();
+        source_media.push(ResolvedSourceMediaJson{
+            handler: handler_entry.name.clone(),
+            location: path_string(&handler_entry.location),
+            root: path_string(&absolute(&handler_entry.root)),
+            per_source_config: path_string(&absolute(&handler_entry.root).join("interface_config.json")),
+            exclude_dirs: handler_entry.exclude_dirs.iter().map(|dir| path_string(dir)).collect(),
+            ignore_extensions: handler_entry.ignore_extensions.clone(),
+            recursive: handler_entry.recursive,
+            tags_file: handler_entry.tags_file.as_deref().map(path_string),
+            known_missing_files,
+        });
+    }
 
+    output.resolved_config = Some(ResolvedConfigJson{ config_file: path_string(&absolute(config_file_path)), source_media });
+    if !warnings.is_empty() {
+        output.warnings = Some(warnings);
+    }
     output.command_success = true;
     output.error_string = None;
 
-    output
+    Ok(output)
+}
+
+/// The capture time of a file, which is taken to be its modification time as cameras set it
+/// when recording
+fn capture_time(fs: &dyn fs_provider::FsProvider, file: &Path) -> Option<u64> {
+    let modified = fs.metadata(file).ok()?.modified?;
+    Some(modified.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// The modification time of the --since-file marker, or None when it doesn't exist yet
+fn since_marker_time(marker: &Path) -> Result<Option<SystemTime>, MediaInterfaceError> {
+    match fs::metadata(marker) {
+        Ok(metadata) => Ok(Some(metadata.modified()
+            .map_err(MediaInterfaceError::io(format!("Failed to read the modification time of {:?}", marker)))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(MediaInterfaceError::io(format!("Failed to read marker file {:?}", marker))(e)),
+    }
+}
+
+/// Set the modification time of the --since-file marker, creating it if it doesn't exist
+fn update_since_marker(marker: &Path, time: SystemTime) -> Result<(), MediaInterfaceError> {
+    fs::OpenOptions::new().create(true).truncate(false).write(true).open(marker)
+        .and_then(|file| file.set_modified(time))
+        .map_err(MediaInterfaceError::io(format!("Failed to update marker file {:?}", marker)))
+}
+
+/// Files numbered by the same counter of the same card are ordered by their number, starting
+/// after the largest gap between consecutive numbers when wrapping around, which is where the
+/// counter rolled over. The sequences of different counters are merged by capture time
+fn order_by_capture(files: Vec<ListedFile>) -> Vec<ListedFile> {
+    let mut counters: BTreeMap<(usize, &'static str), Vec<(helpers::CaptureSequence, ListedFile)>> = BTreeMap::new();
+    let mut unnumbered = Vec::new();
+    for mut file in files {
+        match file.capture_sequence.take() {
+            Some(sequence) => counters.entry((file.card, sequence.counter)).or_default().push((sequence, file)),
+            None => unnumbered.push(file),
+        }
+    }
+
+    let mut sequences: Vec<Vec<ListedFile>> = Vec::new();
+    for (_, mut items) in counters {
+        // Numbers are taken modulo the wrap, in case a handler gives one past it
+        items.sort_by_key(|(sequence, _)| (sequence.number % sequence.wrap, sequence.part));
+
+        let mut start = 0;
+        let mut largest_gap = 0;
+        for i in 0..items.len() {
+            let previous = &items[(i + items.len() - 1) % items.len()].0;
+            let current = &items[i].0;
+            let wrap = u64::from(current.wrap);
+            let gap = (u64::from(current.number) % wrap + wrap - u64::from(previous.number) % wrap) % wrap;
+            if gap > largest_gap {
+                largest_gap = gap;
+                start = i;
+            }
+        }
+        items.rotate_left(start);
+
+        sequences.push(items.into_iter().map(|(_, file)| file).rev().collect());
+    }
+
+    let mut ordered = Vec::new();
+    while let Some(next) = sequences.iter_mut().filter(|sequence| !sequence.is_empty())
+        .min_by_key(|sequence| sequence.last().map(|file| file.capture_time.unwrap_or(u64::MAX))) {
+        ordered.push(next.pop().unwrap());
+    }
+
+    unnumbered.sort_by(|a, b| helpers::natural_cmp(&a.item.file_path, &b.item.file_path));
+    ordered.extend(unnumbered);
+    ordered
+}
+
+/// Order the items by capture time and split them into sessions wherever the time between two
+/// consecutive items is more than `gap_minutes`. Items with the same capture time keep the order
+/// they were listed in and items without one end up in `ungrouped`
+fn group_by_session(files: Vec<ListedFile>, gap_minutes: u64) -> SessionsJson {
+    let mut timed = Vec::new();
+    let mut ungrouped = Vec::new();
+    for file in files {
+        match file.capture_time {
+            Some(time) => timed.push((time, file.item)),
+            None => ungrouped.push(file.item),
+        }
+    }
+    timed.sort_by_key(|(time, _)| *time);
+
+    let mut sessions: Vec<SessionJson> = Vec::new();
+    for (time, item) in timed {
+        match sessions.last_mut() {
+            Some(session) if time - session.end <= gap_minutes.saturating_mul(60) => {
+                session.end = time;
+                session.file_list.push(item);
+            }
+            _ => sessions.push(SessionJson{ start: time, end: time, file_list: vec![item] }),
+        }
+    }
+
+    SessionsJson{ gap_minutes, sessions, ungrouped }
+}
+
+/// What a tags file says about one item
+#[derive(Deserialize)]
+struct ItemNotes {
+    tags: Option<Vec<String>>,
+    caption: Option<String>,
+}
+
+/// Item notes keyed by the names of the files they are for, either just the file name or its
+/// path relative to the card
+fn read_item_notes(fs: &dyn fs_provider::FsProvider, card_root: &Path, tags_file: &Path) -> Result<BTreeMap<String, ItemNotes>, MediaInterfaceError> {
+    let file = card_root.join(tags_file);
+    if !fs.exists(&file) {
+        return Ok(BTreeMap::new());
+    }
+    let data = fs.read(&file).map_err(MediaInterfaceError::io(format!("Failed to read tags file {:?}", file)))?;
+    parse_config(&file, &String::from_utf8_lossy(&data))
+}
+
+/// A file a list or get related action listed, along with what ordering and grouping the files
+/// needs to know about it, worked out while its path is still the one on the card
+struct ListedFile {
+    item: FileItem,
+    /// Index of the card it was listed from among the cards given to the action
+    card: usize,
+    /// What it shares with its duplicates when listing with --dedup-by
+    dedup_key: Option<String>,
+    capture_sequence: Option<helpers::CaptureSequence>,
+    capture_time: Option<u64>,
+}
+
+/// What a list or get related action listed on one input path
+struct Listing {
+    files: Vec<ListedFile>,
+    warnings: Vec<String>,
+    /// The modification time of the newest item listed when listing with --since-file
+    newest_listed: Option<SystemTime>,
+}
+
+/// The output of a list or get related action out of the files it listed, without duplicates,
+/// in capture order or grouped into sessions when asked to
+fn arrange_files(mut files: Vec<ListedFile>, mut warnings: Vec<String>, cli: &Cli) -> OutputJson {
+    let mut seen = HashSet::new();
+    let listed = files.len();
+    files.retain(|file| file.dedup_key.as_ref().is_none_or(|key| seen.insert(key.clone())));
+    if files.len() < listed {
+        warnings.push(format!("Left out {} duplicate entries", listed - files.len()));
+    }
+
+    if cli.capture_order {
+        files = order_by_capture(files);
+    }
+
+    let mut output = create_base_output_json();
+    match cli.group_by_session {
+        Some(gap_minutes) => output.sessions = Some(group_by_session(files, gap_minutes)),
+        None => output.file_list = Some(files.into_iter().map(|file| file.item).collect()),
+    }
+    finish_output(output, warnings)
+}
+
+/// Everything an action needs to run a handler on an input path
+struct ActionContext<'a> {
+    file: PathBuf,
+    handler_entry: &'a HandlerMapEntry,
+    handler: Box<dyn SourceMediaInterface>,
+    options: HandlerOptions,
+    warnings: Vec<String>,
+}
+
+/// The absolute path of an input path and the filesystem to look at it through. With the archive
+/// feature a card can be a tar or zip archive, which is then looked into as the directory of the
+/// card
+fn open_input(input_file: &Path) -> Result<(PathBuf, Box<dyn fs_provider::FsProvider>), MediaInterfaceError> {
+    #[cfg(feature = "archive")]
+    if let Some(archive) = input_file.ancestors().find(|ancestor| ancestor.is_file() && ancestor.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tar") || ext.eq_ignore_ascii_case("zip"))) {
+        let absolute_archive = fs::canonicalize(archive)
+            .map_err(MediaInterfaceError::io("error finding the absolute path of input file".to_string()))?;
+        let archive_fs = fs_provider::ArchiveFs::open(&absolute_archive)
+            .map_err(MediaInterfaceError::io(format!("Failed to read archive {:?}", archive)))?;
+        let file = absolute_archive.join(input_file.strip_prefix(archive).expect("the archive is an ancestor of the input path"));
+        if !fs_provider::FsProvider::exists(&archive_fs, &file) {
+            return Err(MediaInterfaceError::io(format!("error finding input file {:?} in archive", file))(std::io::ErrorKind::NotFound.into()));
+        }
+        return Ok((file, Box::new(archive_fs)));
+    }
+
+    let file = fs::canonicalize(input_file)
+        .map_err(MediaInterfaceError::io("error finding the absolute path of input file".to_string()))?;
+    Ok((file, Box::new(fs_provider::RealFs)))
+}
+
+fn prepare_action<'a>(input_file: &Path, handlers: &'a [HandlerMapEntry], arg_is_card: bool, cli: &Cli) -> Result<ActionContext<'a>, MediaInterfaceError> {
+    let mut known_missing_files: Vec<PathBuf> = Vec::new();
+
+    let (file, fs) = open_input(&substitute_path(input_file, &cli.base_path_substitution))?;
+
+    let mut warnings: Vec<String> = Vec::new();
+
+    // With source media nested in one another the deepest one is responsible for the file
+    let matching: Vec<&HandlerMapEntry> = handlers.iter().filter(|entry| file.starts_with(&entry.location)).collect();
+    let handler_entry = *matching.iter()
+        .max_by_key(|entry| entry.location.components().count())
+        .ok_or_else(|| MediaInterfaceError::NoHandlerForPath(file.clone()))?;
+    if matching.len() > 1 {
+        warnings.push(format!("Input file is under {} source media directories, using the deepest one {:?} with handler {}",
+            matching.len(), handler_entry.location, handler_entry.name));
+    }
+
+    let mut handler = get_handler(&handler_entry.name)?;
+
+    for (_, absolute_path) in read_errata(&per_source_config_path(handler_entry), &cli.base_path_substitution)? {
+        known_missing_files.push(absolute_path);
+    }
+
+    if arg_is_card && file.parent() != Some(handler_entry.location.as_path()) {
+        return Err(MediaInterfaceError::NotACardDirectory(file));
+    }
+
+    let card_component = file.strip_prefix(&handler_entry.location).ok().and_then(|p| p.components().next())
+        .ok_or_else(|| MediaInterfaceError::NotInsideCard(file.clone()))?;
+    let card_root = handler_entry.location.join(card_component);
+    let mut options = HandlerOptions{
+        known_missing_files,
+        exclude_dirs: handler_entry.exclude_dirs.clone(),
+        ignore_extensions: handler_entry.ignore_extensions.clone(),
+        own_files: handler_entry.tags_file.iter().map(|tags_file| card_root.join(tags_file)).collect(),
+        recursive: handler_entry.recursive,
+        include_hidden: cli.include_hidden,
+        unknown_file_policy: cli.unknown_file_policy,
+        card_root,
+        first_frame_as_thumbnail: cli.first_frame_as_thumbnail,
+        fs,
+        parts_policy: cli.parts_policy,
+        count_low_bitrate_parts: cli.count_low_bitrate_parts,
+        lrv_fallback: cli.lrv_fallback,
+        max_parts: cli.max_parts,
+        permission_error_policy: cli.permission_error_policy,
+        scan_warnings: RefCell::new(Vec::new()),
+    };
+
+    // The deepest directory override above a file inside the card is responsible for it
+    let override_dir = file.parent().into_iter().flat_map(Path::ancestors)
+        .take_while(|ancestor| ancestor.starts_with(&options.card_root) && *ancestor != options.card_root)
+        .find(|ancestor| options.fs.exists(&ancestor.join(helpers::OVERRIDE_CONFIG)))
+        .map(Path::to_path_buf);
+    if let Some(dir) = override_dir {
+        let dir_override = read_directory_override(options.fs.as_ref(), &dir)?;
+        handler = get_handler(&dir_override.handler)?;
+        ScanScope::of_override(&dir, &dir_override.options, &options).swap(&mut options);
+    }
+
+    let required_subdirs = handler.required_subdirs();
+    if !required_subdirs.is_empty() && !required_subdirs.iter()
+        .any(|dir| helpers::find_dir(options.fs.as_ref(), &options.card_root, dir).is_some()) {
+        return Err(MediaInterfaceError::CardStructureMismatch{ handler: handler.name(), card: options.card_root, required_subdirs: required_subdirs.to_vec() });
+    }
+
+    Ok(ActionContext{ file, handler_entry, handler, options, warnings })
+}
+
+/// Every item of the card of `context`, including the ones of directories with an override,
+/// each along with its related files as the handler responsible for it gives them. With
+/// `add_missing` files missing without being known missing are added to the known missing files
+/// instead of failing the walk
+fn walk_items(context: &mut ActionContext, add_missing: bool) -> Result<Vec<(FileItem, Vec<FileItem>)>, MediaInterfaceError> {
+    let walk = |handler: &dyn SourceMediaInterface, location: &PathBuf, dir: &PathBuf, options: &mut HandlerOptions| {
+        let run = |options: &mut HandlerOptions, list: &dyn Fn(&HandlerOptions) -> Result<Vec<FileItem>>| match add_missing {
+            true => run_adding_missing_files(options, list),
+            false => list(options),
+        };
+        let mut walked = Vec::new();
+        for item in run(options, &|options| handler.list_high_quality(location, dir, options))? {
+            let related = run(options, &|options| handler.get_related(location, Path::new(&item.file_path), options))?;
+            walked.push((item, related));
+        }
+        Ok(walked)
+    };
+
+    let ActionContext{ file, handler_entry, handler, options, .. } = context;
+    let handler_error = |e: anyhow::Error| MediaInterfaceError::HandlerParse{ handler: handler.name(), detail: e.to_string() };
+    let mut walked = walk(handler.as_ref(), &handler_entry.location, file, options).map_err(handler_error)?;
+    let override_dirs = helpers::find_override_dirs(file, options).map_err(handler_error)?;
+    walked.extend(list_directory_overrides(override_dirs, &handler_entry.location, options, &walk, &mut Vec::new())?);
+    Ok(walked)
+}
+
+/// `output` of an action that succeeded along with its `warnings`
+fn finish_output(mut output: OutputJson, warnings: Vec<String>) -> OutputJson {
+    output.command_success = true;
+    output.error_string = None;
+    output.warnings = if warnings.is_empty() { None } else { Some(warnings) };
+    output
+}
+
+/// Go through every item of a card and count the files its related files should be made of:
+/// the ones present, the ones listed as known missing and the ones missing without being listed.
+/// Files missing without being listed are worked out one by one as for --emit-errata-template,
+/// any other error of the handler fails the action
+fn count_expected_vs_present(input_file: &Path, handlers: &[HandlerMapEntry], cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut context = prepare_action(input_file, handlers, true, cli)?;
+
+    // Files missing without being known missing are added after the listed ones
+    let listed_missing = context.options.known_missing_files.len();
+    let present: HashSet<String> = walk_items(&mut context, true)?.into_iter()
+        .flat_map(|(_, related)| related.into_iter().map(|related| related.file_path))
+        .collect();
+
+    let ActionContext{ file, options, mut warnings, .. } = context;
+    let known_missing = options.known_missing_files[..listed_missing].iter()
+        .filter(|missing| missing.starts_with(&file) && !options.fs.exists(missing))
+        .count();
+    let unexpected_missing = options.known_missing_files.len() - listed_missing;
+
+    let expected = present.len() + known_missing + unexpected_missing;
+    output.completeness = Some(CompletenessJson{
+        expected,
+        present: present.len(),
+        known_missing,
+        unexpected_missing,
+        percent: if expected == 0 { 100.0 } else { present.len() as f64 * 100.0 / expected as f64 },
+    });
+
+    warnings.extend(options.scan_warnings.take());
+    Ok(finish_output(output, warnings))
+}
+
+/// Items are put in the roll of the file representing them, files that aren't in a folder go under ""
+fn summary_per_roll(input_file: &Path, handlers: &[HandlerMapEntry], cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut context = prepare_action(input_file, handlers, true, cli)?;
+
+    let mut rolls = BTreeMap::new();
+    for (item, related) in walk_items(&mut context, false)? {
+        let roll = rolls.entry(item.roll.clone().unwrap_or_default())
+            .or_insert_with(|| RollSummaryJson{ items: 0, item_types: BTreeMap::new(), total_size: 0 });
+        roll.items += 1;
+        *roll.item_types.entry(item.item_type.clone()).or_insert(0) += 1;
+        for related_file in related {
+            roll.total_size += context.options.fs.metadata(Path::new(&related_file.file_path))
+                .map_err(MediaInterfaceError::io(format!("Failed to read metadata of {:?}", related_file.file_path)))?.len;
+        }
+    }
+    output.roll_summary = Some(rolls);
+
+    context.warnings.extend(context.options.scan_warnings.take());
+    Ok(finish_output(output, context.warnings))
+}
+
+/// The files of every item of the card in the order get related gives them, each with the item_id
+/// of the item, the file list_high_quality represents it by
+fn emit_relations_graph(input_file: &Path, handlers: &[HandlerMapEntry], cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut context = prepare_action(input_file, handlers, true, cli)?;
+
+    let mut file_list = Vec::new();
+    for (item, mut related) in walk_items(&mut context, false)? {
+        if cli.sort_related {
+            helpers::sort_related(&mut related);
+        }
+        for mut related_file in related {
+            related_file.item_id = Some(item.file_path.clone());
+            file_list.push(related_file);
+        }
+    }
+    output.file_list = Some(file_list);
+
+    context.warnings.extend(context.options.scan_warnings.take());
+    Ok(finish_output(output, context.warnings))
+}
+
+/// The single file of `items` --best-only outputs
+fn best_file(items: Vec<FileItem>, prefer: &[String]) -> Option<FileItem> {
+    let has_extension = |item: &FileItem, wanted: &str| get_extension_str(Path::new(&item.file_path)).is_ok_and(|ext| ext.eq_ignore_ascii_case(wanted));
+    if let Some(index) = prefer.iter().find_map(|wanted| items.iter().position(|item| has_extension(item, wanted))) {
+        return items.into_iter().nth(index);
+    }
+    // The first of the best ranked ones, so parts come out as the first part
+    let best_rank = items.iter().map(|item| item.quality_rank).max()?;
+    items.into_iter().find(|item| item.quality_rank == best_rank)
+}
+
+/// What --cache keeps for one list action on one card
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// The handler settings the items were listed with
+    options: String,
+    /// Modification time of every directory read while listing, in nanoseconds since the epoch
+    dir_mtimes: BTreeMap<PathBuf, u64>,
+    items: Vec<FileItem>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ListingCache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+fn mtime_nanos(modified: Option<SystemTime>) -> Option<u64> {
+    u64::try_from(modified?.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_nanos()).ok()
+}
+
+/// Everything about the options that can change what a handler lists
+fn cache_options_key(handler: &dyn SourceMediaInterface, options: &HandlerOptions) -> String {
+    let policy_name = |value: Option<clap::builder::PossibleValue>| value.map(|value| value.get_name().to_string()).unwrap_or_default();
+    format!("{} {:?} {:?} {:?} {:?} {} {} {} {} {} {:?} {} {} {}", handler.name(), options.known_missing_files, options.exclude_dirs,
+        options.ignore_extensions, options.own_files, options.recursive, options.include_hidden, options.first_frame_as_thumbnail,
+        options.count_low_bitrate_parts, options.lrv_fallback, options.max_parts, policy_name(options.unknown_file_policy.to_possible_value()), policy_name(options.parts_policy.to_possible_value()),
+        policy_name(options.permission_error_policy.to_possible_value()))
+}
+
+fn read_cache(cache_path: &Path, warnings: &mut Vec<String>) -> ListingCache {
+    let Ok(data) = fs::read_to_string(cache_path) else {
+        return ListingCache::default();
+    };
+    serde_json::from_str(&data).unwrap_or_else(|e| {
+        warnings.push(format!("Ignoring unreadable cache file {:?}: {}", cache_path, e));
+        ListingCache::default()
+    })
+}
+
+/// Written to a temporary file first and moved in place, so that other runs reading the cache at
+/// the same time never see it half written
+fn write_cache(cache_path: &Path, cache: &ListingCache) -> Result<(), MediaInterfaceError> {
+    let mut temporary_name = cache_path.as_os_str().to_owned();
+    temporary_name.push(format!(".tmp{}", process::id()));
+    let temporary_path = PathBuf::from(temporary_name);
+    let data = serde_json::to_string(cache).expect("cache entries are always serializable");
+    fs::write(&temporary_path, data)
+        .map_err(MediaInterfaceError::io(format!("Failed to write cache file {:?}", temporary_path)))?;
+    fs::rename(&temporary_path, cache_path)
+        .map_err(MediaInterfaceError::io(format!("Failed to replace cache file {:?}", cache_path)))
+}
+
+/// Run a list action through the cache at `cache_path`. The previous result is reused if none of
+/// the directories read for it have been modified since, otherwise the card is listed again
+fn cached_list<F>(cache_path: &Path, action_name: &str, context: &mut ActionContext, action: F) -> Result<Vec<FileItem>, MediaInterfaceError> where
+    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, &HandlerOptions) -> Result<Vec<FileItem>>,
+{
+    let ActionContext{ file, handler_entry, handler, options, warnings } = context;
+    let mut cache = read_cache(cache_path, warnings);
+    let key = format!("{} {}", action_name, file.display());
+    let options_key = cache_options_key(handler.as_ref(), options);
+
+    if let Some(entry) = cache.entries.remove(&key)
+        && entry.options == options_key
+        && entry.dir_mtimes.iter().all(|(dir, mtime)| mtime_nanos(options.fs.metadata(dir).ok().and_then(|metadata| metadata.modified)) == Some(*mtime)) {
+        return Ok(entry.items);
+    }
+
+    let listed_dirs = Rc::new(RefCell::new(BTreeMap::new()));
+    let fs = std::mem::replace(&mut options.fs, Box::new(fs_provider::RealFs));
+    options.fs = Box::new(fs_provider::RecordingFs::new(fs, listed_dirs.clone()));
+    let items = action(handler.as_ref(), &handler_entry.location, file, options)
+        .map_err(|e| MediaInterfaceError::HandlerParse{ handler: handler.name(), detail: e.to_string() })?;
+
+    // Directories without a known modification time can't be checked for changes
+    let dir_mtimes: Option<BTreeMap<PathBuf, u64>> = listed_dirs.borrow().iter()
+        .map(|(dir, modified)| Some((dir.clone(), mtime_nanos(*modified)?)))
+        .collect();
+    // A listing that skipped directories isn't kept, so their warnings come up again next time
+    if let Some(dir_mtimes) = dir_mtimes && options.scan_warnings.borrow().is_empty() {
+        cache.entries.insert(key, CacheEntry{ options: options_key, dir_mtimes, items: items.clone() });
+        write_cache(cache_path, &cache)?;
+    }
+
+    Ok(items)
+}
+
+/// Run `run` until it doesn't fail on a file missing without being a known missing file, adding
+/// every such file to the known missing files of `options` on the way
+fn run_adding_missing_files<T, F>(options: &mut HandlerOptions, run: F) -> Result<T> where
+    F: Fn(&HandlerOptions) -> Result<T>,
+{
+    loop {
+        let error = match run(options) {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+        match error.downcast_ref::<helpers::ExpectedFileMissing>() {
+            Some(helpers::ExpectedFileMissing(file)) if !options.known_missing_files.contains(file) => options.known_missing_files.push(file.clone()),
+            _ => return Err(error),
+        }
+    }
+}
+
+fn emit_errata_template(input_file: &Path, handlers: &[HandlerMapEntry], cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let mut context = prepare_action(input_file, handlers, true, cli)?;
+    walk_items(&mut context, true)?;
+    let ActionContext{ handler_entry, options, file, mut warnings, .. } = context;
+
+    let per_source_config = per_source_config_path(handler_entry);
+    let config_dir = fs::canonicalize(&handler_entry.root)
+        .map_err(MediaInterfaceError::io(format!("Error reading source media dir {:?}", &handler_entry.root)))?;
+    let mut known_missing_files: Vec<PathBuf> = options.known_missing_files.iter()
+        .filter(|missing| missing.starts_with(&file) && !options.fs.exists(missing))
+        .map(|missing| missing.strip_prefix(&config_dir).map(Path::to_path_buf).unwrap_or_else(|_| missing.clone()))
+        .collect();
+    known_missing_files.sort_by(|a, b| helpers::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    known_missing_files.dedup();
+
+    output.errata_template = Some(ErrataTemplateJson{
+        config_file: per_source_config.to_string_lossy().into_owned(),
+        errata: Errata{ known_missing_files: Some(known_missing_files) },
+    });
+
+    warnings.extend(options.scan_warnings.take());
+    Ok(finish_output(output, warnings))
+}
+
+/// The card a manifest file is for
+fn manifest_card(manifest_file: &Path, cli: &Cli) -> Result<PathBuf, MediaInterfaceError> {
+    match &cli.manifest_card {
+        Some(card) => Ok(card.clone()),
+        None => Ok(config_dir(manifest_file)?.to_path_buf()),
+    }
+}
+
+/// Size and modification time in seconds since the Unix epoch of a file, as kept in manifests
+fn manifest_file_stat(fs: &dyn fs_provider::FsProvider, file: &Path) -> Result<(u64, Option<u64>), MediaInterfaceError> {
+    let metadata = fs.metadata(file).map_err(MediaInterfaceError::io(format!("Failed to read metadata of {:?}", file)))?;
+    let modified = metadata.modified.and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok()).map(|since| since.as_secs());
+    Ok((metadata.len, modified))
+}
+
+fn export_manifest(manifest_file: &Path, handlers: &[HandlerMapEntry], cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let card = manifest_card(manifest_file, cli)?;
+    let mut context = prepare_action(&card, handlers, true, cli)?;
+
+    // A manifest written in the card, or an older one being replaced, isn't one of the device's files
+    let manifest_path = fs::canonicalize(config_dir(manifest_file)?)
+        .map_err(MediaInterfaceError::io(format!("Error reading manifest directory of {:?}", manifest_file)))?
+        .join(manifest_file.file_name().ok_or_else(|| MediaInterfaceError::NotACardDirectory(manifest_file.to_path_buf()))?);
+    context.options.own_files.push(manifest_path.clone());
+
+    let walked = walk_items(&mut context, false)?;
+    let ActionContext{ handler, options, mut warnings, .. } = context;
+    let relative_path = |path: &str| Path::new(path).strip_prefix(&options.card_root).map(Path::to_path_buf).unwrap_or_else(|_| PathBuf::from(path));
+
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    let mut file_count = 0;
+    for (listed_item, mut related) in walked {
+        if seen.contains(&listed_item.file_path) {
+            continue;
+        }
+        helpers::link_metadata_file(&mut related);
+
+        let mut parts: Vec<ManifestPart> = Vec::new();
+        for related_file in &related {
+            seen.insert(related_file.file_path.clone());
+            let part_index = related_file.part_num.unwrap_or(1).max(1) as usize - 1;
+            while parts.len() <= part_index {
+                parts.push(ManifestPart{ files: Vec::new() });
+            }
+            let (size, modified) = manifest_file_stat(options.fs.as_ref(), Path::new(&related_file.file_path))?;
+            parts[part_index].files.push(ManifestFile{
+                path: relative_path(&related_file.file_path),
+                file_type: related_file.file_type.to_string(),
+                size: Some(size),
+                modified,
+            });
+            file_count += 1;
+        }
+        // Parts that are known missing altogether have nothing to list
+        parts.retain(|part| !part.files.is_empty());
+
+        items.push(ManifestItem{
+            item_type: listed_item.item_type.to_string(),
+            parts,
+            metadata_file: related.iter().find_map(|related_file| related_file.metadata_file.as_deref()).map(relative_path),
+        });
+    }
+
+    let manifest = Manifest{ data_type: MANIFEST_DATA_TYPE.to_string(), handler: Some(handler.name().to_string()), items };
+    let data = serde_json::to_string_pretty(&manifest).expect("the manifest is always serializable");
+    fs::write(manifest_file, data + "\n").map_err(MediaInterfaceError::io(format!("Failed to write manifest file {:?}", manifest_file)))?;
+
+    output.exported_manifest = Some(ExportedManifestJson{
+        manifest_file: manifest_path.to_string_lossy().into_owned(),
+        card: options.card_root.to_string_lossy().into_owned(),
+        items: manifest.items.len(),
+        files: file_count,
+    });
+
+    warnings.extend(options.scan_warnings.take());
+    Ok(finish_output(output, warnings))
+}
+
+/// Every file under `dir`, skipping hidden ones unless `include_hidden`
+fn card_files(dir: &Path, include_hidden: bool) -> Result<Vec<PathBuf>, MediaInterfaceError> {
+    let mut files = Vec::new();
+    let walker = walkdir::WalkDir::new(dir).min_depth(1).into_iter()
+        .filter_entry(|entry| include_hidden || !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walker {
+        let entry = entry.map_err(std::io::Error::from).map_err(MediaInterfaceError::io(format!("Failed to read card directory {:?}", dir)))?;
+        if !entry.file_type().is_dir() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+fn verify_manifest(manifest_file: &Path, cli: &Cli) -> Result<OutputJson, MediaInterfaceError> {
+    let mut output = create_base_output_json();
+    let card = manifest_card(manifest_file, cli)?;
+    let fs = fs_provider::RealFs;
+
+    let data = fs::read_to_string(manifest_file)
+        .map_err(MediaInterfaceError::io(format!("Failed to read manifest file {:?}", manifest_file)))?;
+    let manifest: Manifest = serde_json::from_str(&data)
+        .map_err(|e| MediaInterfaceError::ConfigParse{ file: manifest_file.to_path_buf(), format: "JSON", detail: e.to_string() })?;
+    if manifest.data_type != MANIFEST_DATA_TYPE {
+        return Err(MediaInterfaceError::InvalidDataType(manifest.data_type));
+    }
+    manifest.check()
+        .map_err(|e| MediaInterfaceError::ConfigParse{ file: manifest_file.to_path_buf(), format: "JSON", detail: e.to_string() })?;
+
+    let mut missing = Vec::new();
+    let mut changed = Vec::new();
+    let mut listed = HashSet::new();
+    for file in manifest.items.iter().flat_map(|item| &item.parts).flat_map(|part| &part.files) {
+        listed.insert(file.path.clone());
+        let path = card.join(&file.path);
+        if !fs_provider::FsProvider::exists(&fs, &path) {
+            missing.push(file.path.to_string_lossy().into_owned());
+            continue;
+        }
+        let (size, modified) = manifest_file_stat(&fs, &path)?;
+        if file.size.is_some_and(|expected| expected != size) || (file.modified.is_some() && file.modified != modified) {
+            changed.push(file.path.to_string_lossy().into_owned());
+        }
+    }
+
+    let manifest_path = fs::canonicalize(manifest_file)
+        .map_err(MediaInterfaceError::io(format!("Failed to read manifest file {:?}", manifest_file)))?;
+    let mut extra = Vec::new();
+    for file in card_files(&card, cli.include_hidden)? {
+        let relative = file.strip_prefix(&card).unwrap_or(&file);
+        if !listed.contains(relative) && fs::canonicalize(&file).ok().as_ref() != Some(&manifest_path) {
+            extra.push(relative.to_string_lossy().into_owned());
+        }
+    }
+    extra.sort_by(|a, b| helpers::natural_cmp(a, b));
+
+    output.manifest_report = Some(ManifestReportJson{
+        manifest_file: manifest_file.to_string_lossy().into_owned(),
+        card: card.to_string_lossy().into_owned(),
+        missing,
+        extra,
+        changed,
+    });
+    output.command_success = true;
+    output.error_string = None;
+
+    Ok(output)
+}
+
+fn read_directory_override(fs: &dyn fs_provider::FsProvider, dir: &Path) -> Result<DirectoryOverride, MediaInterfaceError> {
+    let file = dir.join(helpers::OVERRIDE_CONFIG);
+    let data = fs.read(&file).map_err(MediaInterfaceError::io(format!("Failed to read directory override {:?}", file)))?;
+    parse_config(&file, &String::from_utf8_lossy(&data))
+}
+
+/// The part of the handler options a directory override changes
+struct ScanScope {
+    card_root: PathBuf,
+    exclude_dirs: Vec<PathBuf>,
+    ignore_extensions: Vec<String>,
+    recursive: bool,
+}
+
+impl ScanScope {
+    fn of_override(dir: &Path, override_options: &OverrideOptions, options: &HandlerOptions) -> ScanScope {
+        ScanScope{
+            card_root: dir.to_path_buf(),
+            exclude_dirs: override_options.exclude_dirs.clone().unwrap_or_else(|| options.exclude_dirs.clone()),
+            ignore_extensions: override_options.ignore_extensions.clone().unwrap_or_else(|| options.ignore_extensions.clone()),
+            recursive: override_options.recursive.unwrap_or(options.recursive),
+        }
+    }
+
+    /// Put this scope in `options`, keeping the one that was there. Swapping again restores it
+    fn swap(&mut self, options: &mut HandlerOptions) {
+        std::mem::swap(&mut self.card_root, &mut options.card_root);
+        std::mem::swap(&mut self.exclude_dirs, &mut options.exclude_dirs);
+        std::mem::swap(&mut self.ignore_extensions, &mut options.ignore_extensions);
+        std::mem::swap(&mut self.recursive, &mut options.recursive);
+    }
+}
+
+/// List every directory of `override_dirs` with the handler its override names, adding the
+/// handlers to `overrides` by directory
+fn list_directory_overrides<T, F>(override_dirs: Vec<PathBuf>, location: &PathBuf, options: &mut HandlerOptions, action: &F,
+    overrides: &mut Vec<(PathBuf, Box<dyn SourceMediaInterface>)>) -> Result<Vec<T>, MediaInterfaceError> where
+    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, &mut HandlerOptions) -> Result<Vec<T>>,
+{
+    let mut items = Vec::new();
+    for dir in override_dirs {
+        let dir_override = read_directory_override(options.fs.as_ref(), &dir)?;
+        let handler = get_handler(&dir_override.handler)?;
+        let mut scope = ScanScope::of_override(&dir, &dir_override.options, options);
+        scope.swap(options);
+        let listed = list_override_dir(handler.as_ref(), &dir, location, options, action, overrides);
+        scope.swap(options);
+        items.extend(listed?);
+        overrides.push((dir, handler));
+    }
+    Ok(items)
+}
+
+/// The items of a directory with an override, including the ones of the overrides further down
+fn list_override_dir<T, F>(handler: &dyn SourceMediaInterface, dir: &PathBuf, location: &PathBuf, options: &mut HandlerOptions, action: &F,
+    overrides: &mut Vec<(PathBuf, Box<dyn SourceMediaInterface>)>) -> Result<Vec<T>, MediaInterfaceError> where
+    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, &mut HandlerOptions) -> Result<Vec<T>>,
+{
+    let handler_error = |e: anyhow::Error| MediaInterfaceError::HandlerParse{ handler: handler.name(), detail: e.to_string() };
+    let mut items = action(handler, location, dir, options).map_err(handler_error)?;
+    let nested_dirs = helpers::find_override_dirs(dir, options).map_err(handler_error)?;
+    items.extend(list_directory_overrides(nested_dirs, location, options, action, overrides)?);
+    Ok(items)
+}
+
+/// Run a list action on every card of `cards`, merging what they list in the order they were
+/// given in before ordering and grouping the merged files. With --parallel-cards the cards are
+/// listed on up to --scan-concurrency threads, their warnings kept until the cards before them
+/// are done, so the output is the same either way. With more than one card every file is tagged with the handler
+/// that listed it
+fn list_cards<F>(action_name: &str, cards: &[PathBuf], handlers: &[HandlerMapEntry], cli: &Cli, action: F) -> Result<OutputJson, MediaInterfaceError> where
+    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, &HandlerOptions) -> Result<Vec<FileItem>> + Sync,
+{
+    let tag_handler = cards.len() > 1;
+    let list_card = |card: &PathBuf| handle_action_with_input(action_name, card, handlers, true, tag_handler, cli, &action);
+    let thread_count = match cli.scan_concurrency {
+        Some(scan_concurrency) => usize::try_from(scan_concurrency).unwrap_or(usize::MAX),
+        None => std::thread::available_parallelism().map_or(1, usize::from),
+    }.min(cards.len());
+    // Every thread takes the next card not taken yet until there are none left
+    let next_card = AtomicUsize::new(0);
+    let card_results: Vec<_> = match cli.parallel_cards {
+        true => std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..thread_count).map(|_| scope.spawn(|| {
+                let mut listed = Vec::new();
+                loop {
+                    let index = next_card.fetch_add(1, Ordering::Relaxed);
+                    let Some(card) = cards.get(index) else {
+                        return listed;
+                    };
+                    listed.push((index, list_card(card)));
+                }
+            })).collect();
+            let mut listed: Vec<_> = threads.into_iter()
+                .flat_map(|thread| thread.join().unwrap_or_else(|_| fail_main("Internal error: listing a card panicked".into())))
+                .collect();
+            listed.sort_by_key(|(index, _)| *index);
+            listed.into_iter().map(|(_, card_result)| card_result).collect()
+        }),
+        false => cards.iter().map(list_card).collect(),
+    };
+
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let mut newest_listed = None;
+    for (card, card_result) in card_results.into_iter().enumerate() {
+        let listing = card_result?;
+        warnings.extend(listing.warnings);
+        newest_listed = newest_listed.max(listing.newest_listed);
+        files.extend(listing.files.into_iter().map(|file| ListedFile{ card, ..file }));
+    }
+
+    // Only once every card was listed, so that a card failing doesn't leave the others out the
+    // next time
+    if cli.update_since && let (Some(marker), Some(newest)) = (&cli.since_file, newest_listed) {
+        update_since_marker(marker, newest)?;
+    }
+
+    Ok(arrange_files(files, warnings, cli))
+}
+
+/// Run `action` on `input_file`, returning what it listed
+fn handle_action_with_input<F>(action_name: &str, input_file: &Path, handlers: &[HandlerMapEntry], arg_is_card: bool, tag_handler: bool, cli: &Cli, action: F, ) -> Result<Listing, MediaInterfaceError> where
+    F: Fn(&dyn SourceMediaInterface, &PathBuf, &PathBuf, &HandlerOptions) -> Result<Vec<FileItem>>,
+{
+    let mut context = prepare_action(input_file, handlers, arg_is_card, cli)?;
+
+    // Worked out along with the listing, so that cached listings keep them
+    let with_thumbnail_paths = arg_is_card && (cli.thumbnail_path || cli.embed_thumbnails);
+    let action = |handler: &dyn SourceMediaInterface, location: &PathBuf, dir: &PathBuf, options: &HandlerOptions| {
+        let mut items = action(handler, location, dir, options)?;
+        if with_thumbnail_paths {
+            for item in items.iter_mut().filter(|item| item.item_type == ItemType::ItemVideo.as_str()) {
+                item.thumbnail_path = handler.thumbnail_path(location, Path::new(&item.file_path), options)
+                    .map(|path| path.to_string_lossy().into_owned());
+            }
+        }
+        Ok(items)
+    };
+    let cache_name = match with_thumbnail_paths {
+        true => format!("{} with thumbnail paths", action_name),
+        false => action_name.to_string(),
+    };
+
+    let override_dirs = match arg_is_card {
+        true => helpers::find_override_dirs(&context.file, &context.options)
+            .map_err(|e| MediaInterfaceError::HandlerParse{ handler: context.handler.name(), detail: e.to_string() })?,
+        false => Vec::new(),
+    };
+
+    // The cache only ever holds listings of cards without directory overrides, as it wouldn't
+    // notice one being added or changed
+    let mut file_list = match &cli.cache {
+        Some(cache_path) if arg_is_card && override_dirs.is_empty() => cached_list(cache_path, &cache_name, &mut context, action)?,
+        _ => action(context.handler.as_ref(), &context.handler_entry.location, &context.file, &context.options)
+            .map_err(|e| MediaInterfaceError::HandlerParse{ handler: context.handler.name(), detail: e.to_string() })?,
+    };
+    let mut overrides = Vec::new();
+    let override_action = |handler: &dyn SourceMediaInterface, location: &PathBuf, dir: &PathBuf, options: &mut HandlerOptions| action(handler, location, dir, options);
+    file_list.extend(list_directory_overrides(override_dirs, &context.handler_entry.location, &mut context.options, &override_action, &mut overrides)?);
+    let ActionContext{ file, handler_entry, handler, options, mut warnings } = context;
+    let handler_of = |path: &Path| overrides.iter()
+        .filter(|(dir, _)| path.starts_with(dir))
+        .max_by_key(|(dir, _)| dir.components().count())
+        .map_or(handler.as_ref(), |(_, handler)| handler.as_ref());
+
+    for (item, metadata_file) in helpers::drop_dead_metadata_files(&mut file_list, options.fs.as_ref(), &options.known_missing_files) {
+        warnings.push(format!("Metadata file {:?} of {:?} doesn't exist, leaving it out", metadata_file, item));
+    }
+
+    if !arg_is_card && cli.best_only {
+        file_list = best_file(file_list, &cli.prefer).into_iter().collect();
+    }
+
+    let mut newest_listed = None;
+    if arg_is_card && let Some(marker) = &cli.since_file {
+        let since = since_marker_time(marker)?;
+        let is_new = |modified: &Option<SystemTime>| since.is_none() || (modified.is_some() && *modified > since);
+        let listed: Vec<(FileItem, Option<SystemTime>)> = file_list.into_iter()
+            .map(|item| {
+                let modified = options.fs.metadata(Path::new(&item.file_path)).ok().and_then(|metadata| metadata.modified);
+                (item, modified)
+            })
+            .filter(|(_, modified)| is_new(modified))
+            .collect();
+        newest_listed = listed.iter().filter_map(|(_, modified)| *modified).max();
+        file_list = listed.into_iter().map(|(item, _)| item).collect();
+    }
+
+    if arg_is_card && cli.thumbnail_candidates {
+        for item in &mut file_list {
+            let candidates = handler_of(Path::new(&item.file_path)).thumbnail_candidates(&handler_entry.location, Path::new(&item.file_path), &options);
+            item.thumbnail_candidates = Some(candidates.iter().map(|path| path.to_string_lossy().into_owned()).collect());
+        }
+    }
+
+    if arg_is_card && (cli.include_related || cli.flatten_parts) {
+        let mut seen = HashSet::new();
+        let mut related_files = Vec::new();
+        for item in &file_list {
+            let item_handler = handler_of(Path::new(&item.file_path));
+            let mut related = item_handler.get_related(&handler_entry.location, Path::new(&item.file_path), &options)
+                .map_err(|e| MediaInterfaceError::HandlerParse{ handler: item_handler.name(), detail: e.to_string() })?;
+            if cli.sort_related {
+                helpers::sort_related(&mut related);
+            }
+            for mut related_file in related.into_iter().filter(|related_file| seen.insert(related_file.file_path.clone())) {
+                if cli.flatten_parts {
+                    related_file.item_id = Some(item.file_path.clone());
+                }
+                related_files.push(related_file);
+            }
+        }
+        file_list = related_files;
+    }
+
+    if let Some(tags_file) = &handler_entry.tags_file {
+        let notes = read_item_notes(options.fs.as_ref(), &options.card_root, tags_file)?;
+        for item in &mut file_list {
+            let path = Path::new(&item.file_path);
+            let relative_path = path.strip_prefix(&options.card_root).ok().map(|relative| relative.to_string_lossy());
+            let file_name = path.file_name().map(|name| name.to_string_lossy());
+            let item_notes = relative_path.and_then(|relative| notes.get(relative.as_ref()))
+                .or_else(|| file_name.and_then(|name| notes.get(name.as_ref())));
+            if let Some(item_notes) = item_notes {
+                item.tags = item_notes.tags.clone();
+                item.caption = item_notes.caption.clone();
+            }
+        }
+    }
+
+    if cli.stat_owner {
+        for item in &mut file_list {
+            let metadata = options.fs.metadata(Path::new(&item.file_path))
+                .map_err(MediaInterfaceError::io(format!("Failed to read metadata of {:?}", item.file_path)))?;
+            item.mode = metadata.mode;
+            item.uid = metadata.uid;
+            item.gid = metadata.gid;
+        }
+    }
+
+    if cli.embed_thumbnails {
+        for item in &mut file_list {
+            let preview = match &item.thumbnail_path {
+                Some(thumbnail_path) => thumbnail_path,
+                None if item.file_type == FileType::FileImagePreview.as_str() => &item.file_path,
+                None => continue,
+            };
+            let path = Path::new(preview);
+            item.thumbnail_base64 = match options.fs.metadata(path) {
+                Ok(metadata) if metadata.len <= EMBEDDED_THUMBNAIL_MAX_SIZE =>
+                    options.fs.read(path).ok().map(|data| BASE64_STANDARD.encode(data)),
+                _ => None,
+            };
+        }
+    }
+
+    if arg_is_card && cli.item_total_size {
+        for item in &mut file_list {
+            let item_handler = handler_of(Path::new(&item.file_path));
+            let related = item_handler.get_related(&handler_entry.location, Path::new(&item.file_path), &options)
+                .map_err(|e| MediaInterfaceError::HandlerParse{ handler: item_handler.name(), detail: e.to_string() })?;
+            let mut total = 0;
+            for related_file in related {
+                total += options.fs.metadata(Path::new(&related_file.file_path))
+                    .map_err(MediaInterfaceError::io(format!("Failed to read metadata of {:?}", related_file.file_path)))?.len;
+            }
+            item.item_total_size = Some(total);
+        }
+    }
+
+    #[cfg(feature = "media-probe")]
+    for item in file_list.iter_mut().filter(|item| matches!(item.container.as_deref(), Some("mp4" | "mov" | "3gp"))) {
+        if let Ok(Some(container)) = helpers::ftyp_container(options.fs.as_ref(), Path::new(&item.file_path)) {
+            item.container = Some(container.to_string());
+        }
+    }
+
+    if arg_is_card && cli.check_playable {
+        for item in &mut file_list {
+            if item.file_type != FileType::FileVideo.as_str() && item.file_type != FileType::FileVideoPreview.as_str() {
+                continue;
+            }
+            let path = Path::new(&item.file_path);
+            let is_iso_media = get_extension_str(path).is_ok_and(|ext| ISO_MEDIA_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)));
+            if is_iso_media {
+                item.playable = has_moov_box(options.fs.as_ref(), path).ok();
+            }
+        }
+    }
+
+    if arg_is_card && file_list.is_empty() {
+        match &cli.since_file {
+            Some(marker) => warnings.push(format!("No media modified since {:?} found under {:?}", marker, file)),
+            None => warnings.push(format!("No media found under {:?}", file)),
+        }
+    }
+
+    if tag_handler {
+        for item in &mut file_list {
+            item.handler = Some(handler_of(Path::new(&item.file_path)).name().to_string());
+        }
+    }
+
+    // Taken before the paths get rewritten
+    let with_capture_time = cli.capture_order || cli.group_by_session.is_some();
+    let listed: Vec<(Option<String>, Option<helpers::CaptureSequence>, Option<u64>)> = file_list.iter().map(|item| {
+        let path = Path::new(&item.file_path);
+        let item_dedup_key = cli.dedup_by.filter(|_| arg_is_card).map(|dedup_by| dedup_key(item, dedup_by, options.fs.as_ref()));
+        let capture_sequence = if cli.capture_order { handler_of(path).capture_sequence(path) } else { None };
+        let item_capture_time = if with_capture_time { capture_time(options.fs.as_ref(), path) } else { None };
+        (item_dedup_key, capture_sequence, item_capture_time)
+    }).collect();
+
+    if cli.relative_paths {
+        let root = fs::canonicalize(&handler_entry.root)
+            .map_err(MediaInterfaceError::io(format!("Error reading source media dir {:?}", &handler_entry.root)))?;
+        rewrite_item_paths(&mut file_list, |path| {
+            Path::new(path).strip_prefix(&root).map(|p| p.to_string_lossy().into_owned()).unwrap_or_else(|_| path.to_string())
+        });
+    }
+
+    if cli.home_relative && let Some(home) = env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        rewrite_item_paths(&mut file_list, |path| match Path::new(path).strip_prefix(&home) {
+            Ok(rest) => Path::new("~").join(rest).to_string_lossy().into_owned(),
+            Err(_) => path.to_string(),
+        });
+    }
+
+    let files = file_list.into_iter().zip(listed)
+        .map(|(item, (dedup_key, capture_sequence, capture_time))| ListedFile{ item, card: 0, dedup_key, capture_sequence, capture_time })
+        .collect();
+
+    warnings.extend(options.scan_warnings.take());
+    Ok(Listing{ files, warnings, newest_listed })
 }
 
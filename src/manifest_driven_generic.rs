@@ -0,0 +1,251 @@
+/* manifest_driven_generic.rs - Generic handler for devices that describe their items in a
+ * manifest.json file at the root of the card instead of relying on the file names
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::{SourceMediaInterface, HandlerOptions, PartsPolicy};
+use std::path::{Component, PathBuf, Path};
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+////////////////////////////////////////
+//          Manifest schema           //
+////////////////////////////////////////
+
+/// Also what --export-manifest writes, with the handler and the size and modification time of
+/// every file filled in, so that an exported manifest can be read back by this handler
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub data_type: String,
+    /// The handler the items were listed with when the manifest was exported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler: Option<String>,
+    pub items: Vec<ManifestItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestItem {
+    pub item_type: String,
+    /// The parts of the item in order, single file items have one part with one file
+    pub parts: Vec<ManifestPart>,
+    /// File holding the embedded metadata of the item, relative to the card directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestPart {
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Path of the file relative to the card directory
+    pub path: PathBuf,
+    pub file_type: String,
+    /// Size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Modification time in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+pub const MANIFEST_DATA_TYPE: &str = "source_media_manifest";
+
+/// Whether `path` stays inside the card directory it is relative to: not absolute and without ".."
+fn is_inside_card(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+impl Manifest {
+    /// Check the manifest only names files inside the card, as it comes from the card itself, and
+    /// that its items have no more parts than part numbers go up to
+    pub fn check(&self) -> Result<()> {
+        for item in &self.items {
+            if u8::try_from(item.parts.len()).is_err() {
+                return Err(anyhow!("Manifest item with {} parts, more than the {} an item may have", item.parts.len(), u8::MAX));
+            }
+            let paths = item.parts.iter().flat_map(|part| &part.files).map(|file| &file.path).chain(&item.metadata_file);
+            for path in paths {
+                if !is_inside_card(path) {
+                    return Err(anyhow!("Manifest path {:?} is not inside the card directory", path));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+fn read_manifest(card: &Path, options: &HandlerOptions) -> Result<Manifest> {
+    let manifest_path = card.join(MANIFEST_NAME);
+    if ! options.fs.exists(&manifest_path) {
+        return Err(anyhow!("No {} found in card directory {:?}", MANIFEST_NAME, card));
+    }
+
+    let data = options.fs.read(&manifest_path)?;
+    let manifest: Manifest = serde_json::from_slice(&data)
+        .map_err(|e| anyhow!("Failed to parse {:?}: {}", manifest_path, e))?;
+
+    if manifest.data_type != MANIFEST_DATA_TYPE {
+        return Err(anyhow!("Invalid data type on the manifest {:?}: {}", manifest_path, manifest.data_type));
+    }
+    manifest.check().map_err(|e| anyhow!("{:?}: {}", manifest_path, e))?;
+
+    Ok(manifest)
+}
+
+/// Items whose files carry part numbers in the output. Matches the other handlers where videos and
+/// audio are always multi-part items
+fn is_part_item(item_type: ItemType, item: &ManifestItem) -> bool {
+    item.parts.len() > 1 || matches!(item_type, ItemVideo | ItemAudio)
+}
+
+fn create_file(card: &Path, item: &ManifestItem, part_num: usize, file: &ManifestFile, metadata_file: Option<String>) -> Result<FileItem> {
+    let types = JsonFileInfoTypes{ file_type: FileType::parse(&file.file_type)?, item_type: ItemType::parse(&item.item_type)? };
+    let path = card.join(&file.path).to_string_lossy().into_owned();
+
+    if is_part_item(types.item_type, item) {
+        let part_count = u8::try_from(item.parts.len()).map_err(|_| anyhow!("Manifest item with {} parts", item.parts.len()))?;
+        let part_num = u8::try_from(part_num).map_err(|_| anyhow!("Manifest part number {} out of range", part_num))?;
+        Ok(create_part_file(path, types, part_count, part_num, metadata_file))
+    } else {
+        create_simple_file(path, types, metadata_file)
+    }
+}
+
+/// Pick the file representing the item out of its first part, or out of every part with the
+/// "all" parts policy, using `preference`, the file types in the order they are preferred
+fn list_items(card: &Path, options: &HandlerOptions, preference: &[FileType]) -> Result<Vec<FileItem>> {
+    let manifest = read_manifest(card, options)?;
+    let mut items = Vec::<FileItem>::new();
+
+    for item in &manifest.items {
+        if item.parts.is_empty() {
+            return Err(anyhow!("Manifest item without any parts"));
+        }
+        let parts = match options.parts_policy {
+            PartsPolicy::First => &item.parts[..1],
+            PartsPolicy::All => &item.parts[..],
+        };
+
+        for (part_index, part) in parts.iter().enumerate() {
+            let mut candidates = Vec::new();
+            for file in &part.files {
+                let file_type = FileType::parse(&file.file_type)?;
+                if let Some(rank) = preference.iter().position(|preferred| *preferred == file_type)
+                    && options.fs.exists(&card.join(&file.path)) {
+                    candidates.push((rank, file));
+                }
+            }
+
+            if let Some((_, file)) = candidates.into_iter().min_by_key(|(rank, _)| *rank) {
+                let metadata_file = item.metadata_file.as_ref().map(|path| card.join(path).to_string_lossy().into_owned());
+                items.push(create_file(card, item, part_index + 1, file, metadata_file)?);
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+pub struct ManifestDrivenGeneric;
+
+impl SourceMediaInterface for ManifestDrivenGeneric {
+    fn list_thumbnail(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        list_items(source_media_card, options, &[
+            FileImagePreview, FileImage, FileVideoPreview, FileVideo, FileImageRaw, FileVideoRaw, FileLivePhotoMotion, FileAudio, FileGNSSTrack
+        ])
+    }
+    fn list_high_quality(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        list_items(source_media_card, options, &[
+            FileVideoRaw, FileImageRaw, FileVideo, FileImage, FileAudio, FileGNSSTrack, FileLivePhotoMotion, FileVideoPreview, FileImagePreview
+        ])
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let card = &options.card_root;
+        let manifest = read_manifest(card, options)?;
+
+        let relative_path = source_media_file.strip_prefix(card)
+            .map_err(|_| anyhow!("File {:?} is not inside the card directory {:?}", source_media_file, card))?;
+
+        let item = manifest.items.iter()
+            .find(|item| item.parts.iter().any(|part| part.files.iter().any(|file| file.path == relative_path)))
+            .ok_or_else(|| anyhow!("File {:?} is not part of any item in the manifest", relative_path))?;
+
+        let mut items = Vec::<FileItem>::new();
+        for (part_index, part) in item.parts.iter().enumerate() {
+            for file in &part.files {
+                let path = card.join(&file.path);
+                if options.fs.exists(&path) {
+                    items.push(create_file(card, item, part_index + 1, file, None)?);
+                } else if ! options.known_missing_files.contains(&path) {
+                    return Err(ExpectedFileMissing(path).into());
+                }
+            }
+        }
+
+        check_related(options.fs.as_ref(), source_media_file, items)
+    }
+    fn name(&self) -> &'static str {
+        "Manifest-Driven-Generic-1"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::MemoryFs;
+
+    fn card_with_manifest(manifest: &str) -> HandlerOptions {
+        let mut fs = MemoryFs::with_files(&["/card/clip.mp4"]);
+        fs.add_file_with_contents(Path::new("/card/manifest.json"), manifest.as_bytes().to_vec(), None);
+        HandlerOptions::new(PathBuf::from("/card"), Box::new(fs))
+    }
+
+    #[test]
+    fn manifest_paths_outside_of_the_card_are_an_error() {
+        for path in ["/etc/passwd", "../../etc/passwd", "DCIM/../../etc/passwd"] {
+            let options = card_with_manifest(&serde_json::json!({"data_type": MANIFEST_DATA_TYPE, "items": [
+                {"item_type": "video", "parts": [{"files": [{"path": "clip.mp4", "file_type": "video"}, {"path": path, "file_type": "metadata"}]}]}
+            ]}).to_string());
+            let error = ManifestDrivenGeneric.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap_err();
+            assert!(error.to_string().contains("not inside the card directory"), "{}: {}", path, error);
+            assert!(ManifestDrivenGeneric.get_related(Path::new("/"), Path::new("/card/clip.mp4"), &options).is_err(), "{}", path);
+        }
+
+        let options = card_with_manifest(&serde_json::json!({"data_type": MANIFEST_DATA_TYPE, "items": [
+            {"item_type": "video", "parts": [{"files": [{"path": "clip.mp4", "file_type": "video"}]}], "metadata_file": "/etc/passwd"}
+        ]}).to_string());
+        assert!(ManifestDrivenGeneric.list_high_quality(Path::new("/"), Path::new("/card"), &options).is_err());
+    }
+
+    #[test]
+    fn items_with_more_parts_than_part_numbers_go_up_to_are_an_error() {
+        let parts: Vec<_> = (0..256).map(|_| serde_json::json!({"files": [{"path": "clip.mp4", "file_type": "video"}]})).collect();
+        let options = card_with_manifest(&serde_json::json!({"data_type": MANIFEST_DATA_TYPE, "items": [{"item_type": "video", "parts": parts}]}).to_string());
+        let error = ManifestDrivenGeneric.list_high_quality(Path::new("/"), Path::new("/card"), &options).unwrap_err();
+        assert!(error.to_string().contains("256 parts"), "{}", error);
+    }
+}
@@ -0,0 +1,149 @@
+/* olympus_generic_1.rs - Handler for Olympus / OM System cameras that write ORF raws, JPGs and
+ * MOV clips under DCIM/NNNOLYMP
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use crate::{SourceMediaInterface, HandlerOptions};
+use std::path::{PathBuf,Path};
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+pub struct OlympusInterface;
+
+fn filetype(ext: &str) -> Result<JsonFileInfoTypes> {
+    match ext {
+        "MOV" => Ok(JsonFileInfoTypes{ file_type:FileVideo,    item_type:ItemVideo }),
+
+        "JPG" => Ok(JsonFileInfoTypes{ file_type:FileImage,    item_type:ItemImage }),
+        "ORF" => Ok(JsonFileInfoTypes{ file_type:FileImageRaw, item_type:ItemImage }),
+        _ => Err(anyhow!("unknown file extension {:?} trying to determain file type", ext)),
+    }
+}
+
+/// The sequential number at the end of a PMDDNNNN file name, with the month written as 1-9, A-C
+fn get_media_number(file: &Path) -> Result<u32> {
+    let stem = osstr_to_str(file.file_stem().ok_or_else(|| anyhow!("File {:?} has no name", file))?)?;
+    match stem.len() {
+        8 if stem.is_char_boundary(4) => stem[4..].parse::<u32>().ok(),
+        _ => None,
+    }.ok_or_else(|| anyhow!("File name {:?} is not of the form PMDDNNNN", file))
+}
+
+/// An item listed through one of its files, or skipped when the file with the `preferred`
+/// extension of the same photo exists and stands in for it instead. Focus stacked composites are
+/// written as a JPG of their own, numbered after the frames and without an ORF, so they always end
+/// up listed by their JPG
+fn create_item_unless(path: &Path, path_str: &str, ext: &str, preferred: Option<&str>, options: &HandlerOptions) -> Result<Option<FileItem>> {
+    let file_info = filetype(ext)?;
+    if file_info.file_type == FileVideo {
+        return Ok(Some(create_part_file(path_str.to_string(), file_info, 1, 1, None)));
+    }
+    if preferred.is_some_and(|preferred| find_file(options.fs.as_ref(), &path.with_extension(preferred)).is_some()) {
+        return Ok(None);
+    }
+    Ok(Some(create_simple_file(path_str.to_string(), file_info, None)?))
+}
+
+impl SourceMediaInterface for OlympusInterface {
+    fn list_thumbnail(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        scan_dcim_rolls(source_media_card, options, |ext: &str, path: &PathBuf, path_str: &str| {
+            match ext {
+                "JPG" | "MOV" => create_item_unless(path, path_str, ext, None, options),
+                "ORF" => create_item_unless(path, path_str, ext, Some("JPG"), options),
+                _ => unknown_file(path_str, options),
+            }
+        })
+    }
+    fn list_high_quality(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        scan_dcim_rolls(source_media_card, options, |ext: &str, path: &PathBuf, path_str: &str| {
+            match ext {
+                "ORF" | "MOV" => create_item_unless(path, path_str, ext, None, options),
+                "JPG" => create_item_unless(path, path_str, ext, Some("ORF"), options),
+                _ => unknown_file(path_str, options),
+            }
+        })
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let fs = options.fs.as_ref();
+        let items = match get_extension_str(source_media_file)?.to_ascii_uppercase().as_str() {
+            "MOV" => Ok(vec![create_part_file(source_media_file.to_string_lossy().into_owned(), filetype("MOV")?, 1, 1, None)]),
+            "JPG" | "ORF" => Ok([
+                create_simple_file_if_exists(fs, &source_media_file.with_extension("ORF"), filetype("ORF")?, None)?,
+                create_simple_file_if_exists(fs, &source_media_file.with_extension("JPG"), filetype("JPG")?, None)?,
+            ].into_iter().flatten().collect()),
+            ext => Err(anyhow!("unexpected file extension '{}' in file '{:?}'", ext, source_media_file)),
+        }?;
+        check_related(fs, source_media_file, items)
+    }
+    fn name(&self) -> &'static str {
+        "Olympus-Generic-1"
+    }
+    fn required_subdirs(&self) -> &'static [&'static str] {
+        &["DCIM"]
+    }
+    fn capture_sequence(&self, file: &Path) -> Option<CaptureSequence> {
+        let number = get_media_number(file).ok()?;
+        Some(CaptureSequence{ counter: "media", number, wrap: 10000, part: 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card};
+
+    #[test]
+    fn orf_and_jpg_of_a_photo_are_one_item() {
+        let options = memory_card("/card", &["/card/DCIM/100OLYMP/PA140001.ORF", "/card/DCIM/100OLYMP/PA140001.JPG", "/card/DCIM/100OLYMP/PA140002.MOV"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = OlympusInterface.list_thumbnail(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140001.JPG", "/card/DCIM/100OLYMP/PA140002.MOV"]);
+        let items = OlympusInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140001.ORF", "/card/DCIM/100OLYMP/PA140002.MOV"]);
+        assert_eq!(items[0].file_type, "image-raw");
+        assert_eq!(items[1].file_type, "video");
+
+        for file in ["PA140001.ORF", "PA140001.JPG"] {
+            let items = OlympusInterface.get_related(location, &Path::new("/card/DCIM/100OLYMP").join(file), &options).unwrap();
+            assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140001.ORF", "/card/DCIM/100OLYMP/PA140001.JPG"], "{}", file);
+        }
+        let items = OlympusInterface.get_related(location, Path::new("/card/DCIM/100OLYMP/PA140002.MOV"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140002.MOV"]);
+    }
+
+    #[test]
+    fn focus_stacked_composite_is_an_item_of_its_own() {
+        let options = memory_card("/card", &["/card/DCIM/100OLYMP/PA140001.ORF", "/card/DCIM/100OLYMP/PA140001.JPG",
+                                             "/card/DCIM/100OLYMP/PA140002.ORF", "/card/DCIM/100OLYMP/PA140002.JPG",
+                                             "/card/DCIM/100OLYMP/PA140003.JPG"]);
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = OlympusInterface.list_thumbnail(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140001.JPG", "/card/DCIM/100OLYMP/PA140002.JPG", "/card/DCIM/100OLYMP/PA140003.JPG"]);
+        let items = OlympusInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140001.ORF", "/card/DCIM/100OLYMP/PA140002.ORF", "/card/DCIM/100OLYMP/PA140003.JPG"]);
+        assert_eq!(items[2].file_type, "image");
+
+        let items = OlympusInterface.get_related(location, Path::new("/card/DCIM/100OLYMP/PA140003.JPG"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100OLYMP/PA140003.JPG"]);
+    }
+}
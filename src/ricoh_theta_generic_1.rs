@@ -0,0 +1,103 @@
+/* ricoh_theta_generic_1.rs - Handler for Ricoh Theta 360 cameras that store equirectangular
+ * photos and videos under DCIM/100RICOH
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use crate::{SourceMediaInterface, HandlerOptions};
+use std::path::{PathBuf,Path};
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+pub struct RicohThetaInterface;
+
+/// How far into a JPG to look for the GPano XMP packet. It sits in an APP1 segment right at the
+/// start of the file
+#[cfg(feature = "gpano")]
+const GPANO_SEARCH_LEN: usize = 64 * 1024;
+
+/// Whether the photo carries GPano metadata, which marks it as a 360 photo. Without the gpano
+/// feature every photo from the camera is taken to be one
+#[cfg(feature = "gpano")]
+fn is_panorama_photo(file: &Path, options: &HandlerOptions) -> Result<bool> {
+    let head = options.fs.read_range(file, 0, GPANO_SEARCH_LEN)?;
+    Ok(head.windows(b"GPano:".len()).any(|window| window == b"GPano:"))
+}
+
+#[cfg(not(feature = "gpano"))]
+fn is_panorama_photo(_file: &Path, _options: &HandlerOptions) -> Result<bool> {
+    Ok(true)
+}
+
+fn create_file(path: &Path, options: &HandlerOptions) -> Result<FileItem> {
+    let path_str = path.to_string_lossy().into_owned();
+    match get_extension_str(path)?.to_ascii_uppercase().as_str() {
+        "JPG" => {
+            let item_type = if is_panorama_photo(path, options)? { ItemPanorama } else { ItemImage };
+            create_simple_file(path_str, JsonFileInfoTypes{ file_type:FileImage, item_type }, None)
+        }
+        "MP4" => Ok(create_part_file(path_str, JsonFileInfoTypes{ file_type:FileVideo, item_type:ItemPanorama }, 1, 1, None)),
+        ext => Err(anyhow!("unexpected file extension '{}' in file '{}'", ext, path_str)),
+    }
+}
+
+impl SourceMediaInterface for RicohThetaInterface {
+    fn list_thumbnail(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        scan_dcim_rolls(source_media_card, options, |ext: &str, path: &PathBuf, path_str: &str| {
+            match ext {
+                "JPG" | "MP4" => Ok(Some(create_file(path, options)?)),
+                _ => unknown_file(path_str, options),
+            }
+        })
+    }
+    fn list_high_quality(&self, source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        self.list_thumbnail(source_media_location, source_media_card, options)
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let file = find_file(options.fs.as_ref(), source_media_file).unwrap_or_else(|| source_media_file.to_path_buf());
+        check_related(options.fs.as_ref(), source_media_file, vec![create_file(&file, options)?])
+    }
+    fn name(&self) -> &'static str {
+        "Ricoh-Theta-Generic-1"
+    }
+    fn required_subdirs(&self) -> &'static [&'static str] {
+        &["DCIM"]
+    }
+}
+
+#[cfg(all(test, feature = "gpano"))]
+mod tests {
+    use super::*;
+    use crate::fs_provider::MemoryFs;
+
+    #[test]
+    fn only_the_start_of_a_photo_is_searched_for_gpano_metadata() {
+        let mut fs = MemoryFs::default();
+        let mut late = vec![0; GPANO_SEARCH_LEN];
+        late.extend_from_slice(b"GPano:ProjectionType");
+        fs.add_file_with_contents(Path::new("/card/DCIM/100RICOH/R0010001.JPG"), b"GPano:ProjectionType".to_vec(), None);
+        fs.add_file_with_contents(Path::new("/card/DCIM/100RICOH/R0010002.JPG"), late, None);
+        let options = HandlerOptions::new(PathBuf::from("/card"), Box::new(fs));
+
+        let item_type = |file: &str| create_file(Path::new(file), &options).unwrap().item_type;
+        assert_eq!(item_type("/card/DCIM/100RICOH/R0010001.JPG"), "panorama");
+        assert_eq!(item_type("/card/DCIM/100RICOH/R0010002.JPG"), "image");
+    }
+}
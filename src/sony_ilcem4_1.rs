@@ -18,37 +18,42 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
 
 use anyhow::{Result, anyhow, Context};
-use crate::SourceMediaInterface;
+use crate::{SourceMediaInterface, HandlerOptions, UnknownFilePolicy};
 use std::path::{PathBuf,Path};
 use crate::FileItem;
 use crate::helpers::*;
 use crate::helpers::ItemType::*;
 use crate::helpers::FileType::*;
-use std::fs;
+use crate::fs_provider::FsProvider;
 
-fn find_m4root(card: &Path) -> Result<Option<PathBuf>> {
-    let private_m4root = card.join("PRIVATE/M4ROOT");
-    let root_m4root = card.join("M4ROOT");
+// Folder names are matched ignoring case, cards copied through case folding filesystems can end
+// up with a dcim or private folder
 
-    match (private_m4root.exists(), root_m4root.exists()) {
-        (true, true) => Err(anyhow!(
+fn find_m4root(fs: &dyn FsProvider, card: &Path) -> Result<Option<PathBuf>> {
+    match (find_dir(fs, card, "PRIVATE/M4ROOT"), find_dir(fs, card, "M4ROOT")) {
+        (Some(_), Some(_)) => Err(anyhow!(
             "Ambiguous M4ROOT location on card '{}': found in both 'PRIVATE/M4ROOT' and card root 'M4ROOT'",
             card.display()
         )),
-        (true, false) => Ok(Some(private_m4root)),
-        (false, true) => Ok(Some(root_m4root)),
-        (false, false) => Ok(None),
+        (Some(private_m4root), None) => Ok(Some(private_m4root)),
+        (None, Some(root_m4root)) => Ok(Some(root_m4root)),
+        (None, None) => Ok(None),
     }
 }
 
+fn is_named(dir: &Path, name: &str) -> bool {
+    dir.file_name().and_then(|dir_name| dir_name.to_str()).is_some_and(|dir_name| dir_name.eq_ignore_ascii_case(name))
+}
+
 fn filetype(file: &Path, source_media_location: &Path) -> Result<JsonFileInfoTypes> {
-    let extension = get_extension_str(file)?;
+    let extension = get_extension_str(file)?.to_ascii_uppercase();
+    let extension = extension.as_str();
     let file_str = file.to_string_lossy();
     let parent_folder = file.parent().context("File has no parent directory")?;
     let grandparent_folder = parent_folder.parent().context("File has no grandparent directory")?;
     let grandparent_name = osstr_to_str(grandparent_folder.file_name().ok_or_else(|| anyhow!("Failed to get name of grandparent folder"))?)?;
 
-    if grandparent_name == "DCIM"{
+    if grandparent_name.eq_ignore_ascii_case("DCIM") {
         let parent_folder_name = osstr_to_str(parent_folder.file_name()
             .ok_or_else(|| anyhow!("failed to get name of parent folder"))?)?;
 
@@ -59,23 +64,24 @@ fn filetype(file: &Path, source_media_location: &Path) -> Result<JsonFileInfoTyp
         //those features so it's better for me to get an error instead of accidently matching
         //something but i would be open to removing this. The rest of the codebase should support
         //it otherwise but has not been tested.
-        if parent_folder_name.ends_with("MSDCF") && expected_source_media_location == source_media_location {
+        if parent_folder_name.to_ascii_uppercase().ends_with("MSDCF") && expected_source_media_location == source_media_location {
             return match extension{
                 "JPG" => Ok(JsonFileInfoTypes{ file_type:FileImage,    item_type:ItemImage }),
                 "HIF" => Ok(JsonFileInfoTypes{ file_type:FileImage,    item_type:ItemImage }),
                 "ARW" => Ok(JsonFileInfoTypes{ file_type:FileImageRaw, item_type:ItemImage }),
+                "ARQ" => Ok(JsonFileInfoTypes{ file_type:FileImageRaw, item_type:ItemImage }),
                 _ => Err(anyhow!("unexpected input file extension '{}' in file '{}'", extension, file_str))
             }
         }
 
     }
 
-    if grandparent_name == "M4ROOT" {
+    if grandparent_name.eq_ignore_ascii_case("M4ROOT") {
         let m4root_parent = grandparent_folder.parent().context("Traversing path backwards, expected to reach M4ROOT parent dir but failed")?;
         let m4root_parent_name = osstr_to_str(m4root_parent.file_name().ok_or_else(|| anyhow!("failed to get filename of M4ROOT parent dir"))?)?;
 
         // M4ROOT may live under PRIVATE/ for SD Cards or directly in the card root for CF Express Cards
-        let expected_source_media_location = if m4root_parent_name == "PRIVATE" {
+        let expected_source_media_location = if m4root_parent_name.eq_ignore_ascii_case("PRIVATE") {
             m4root_parent.parent().context("Traversing path backwards, expected to reach card dir but failed")?
                          .parent().context("Traversing path backwards, expected to reach source media dir but failed")?
         } else {
@@ -84,7 +90,7 @@ fn filetype(file: &Path, source_media_location: &Path) -> Result<JsonFileInfoTyp
 
         if expected_source_media_location == source_media_location {
             let m4root_subfolder_name = osstr_to_str(parent_folder.file_name().ok_or_else(|| anyhow!("failed to get filename of what's expected to be the M4ROOT folder"))?)?;
-            return match m4root_subfolder_name {
+            return match m4root_subfolder_name.to_ascii_uppercase().as_str() {
                 "CLIP" => {
                     match extension {
                         "MP4" => Ok(JsonFileInfoTypes{ file_type:FileVideo,    item_type:ItemVideo }),
@@ -112,6 +118,23 @@ fn filetype(file: &Path, source_media_location: &Path) -> Result<JsonFileInfoTyp
     Err(anyhow!("File path not in expected directory structure '{}'", file_str))
 }
 
+/// Subfolders of M4ROOT the handler knows. GENERAL holds camera management data and, like the
+/// files in M4ROOT itself (CUEUP.XML, MEDIAPRO.XML, STATUS.BIN), belongs to no item
+const M4ROOT_SUBFOLDERS: [&str; 4] = ["CLIP", "THMBNL", "SUB", "GENERAL"];
+
+fn is_m4root_management_file(file: &Path) -> bool {
+    let Some(parent) = file.parent() else {
+        return false;
+    };
+    is_named(parent, "M4ROOT") ||
+        parent.ancestors().any(|dir| is_named(dir, "GENERAL") && dir.parent().is_some_and(|dir| is_named(dir, "M4ROOT")))
+}
+
+fn is_in_unknown_m4root_subfolder(file: &Path) -> bool {
+    file.parent().and_then(Path::parent).is_some_and(|dir| is_named(dir, "M4ROOT")) &&
+        file.parent().is_some_and(|dir| !M4ROOT_SUBFOLDERS.iter().any(|subfolder| is_named(dir, subfolder)))
+}
+
 enum VideoFiles{
     Thumbnail,
     Video,
@@ -119,42 +142,77 @@ enum VideoFiles{
     VideoPreview,
 }
 
+/// The clip number of a file of a video group. Every file of the group is named C followed by the
+/// four digit clip number, then M01 for the metadata XML, T01 for the THMBNL thumbnail and S03 for
+/// the SUB proxy, with nothing after the number for the MP4 itself
 fn get_video_id( file:&Path, file_type:VideoFiles ) -> Result<String> {
     let input_filename = file.file_name().ok_or_else(|| anyhow!("Couldn't get filename of video file"))?.to_string_lossy();
-
-    Ok( match file_type {
-        VideoFiles::Thumbnail    => input_filename[1..=4].to_string(),
-        VideoFiles::Video        => input_filename[1..=4].to_string(),
-        VideoFiles::Metadata     => input_filename[1..=4].to_string(),
-        VideoFiles::VideoPreview => input_filename[1..=4].to_string(),
-    } )
+    let suffix = match file_type {
+        VideoFiles::Thumbnail    => "T01",
+        VideoFiles::Video        => "",
+        VideoFiles::Metadata     => "M01",
+        VideoFiles::VideoPreview => "S03",
+    };
+
+    input_filename.rsplit_once('.')
+        .and_then(|(stem, _)| stem.strip_prefix('C'))
+        .and_then(|rest| rest.strip_suffix(suffix))
+        .filter(|id| id.len() == 4 && id.bytes().all(|c| c.is_ascii_digit()))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Video file name {:?} is not of the form C####{}, with the clip number where expected", input_filename, suffix))
 }
 
-fn create_video_file( input_file:&Path, id:&String, file_type:VideoFiles ) -> Result<PathBuf> {
+fn create_video_file( fs: &dyn FsProvider, input_file:&Path, id:&String, file_type:VideoFiles ) -> Result<PathBuf> {
     let m4root = input_file.parent().context("Traversing path backwards, expected to reach m4root subfolder but failed")?
                            .parent().context("Traversing path backwards, expected to reach m4root dir but failed")?;
+    let subfolder = |name: &str| find_dir(fs, m4root, name).unwrap_or_else(|| m4root.join(name));
     Ok ( match file_type{
-        VideoFiles::Video        => m4root.join("CLIP")  .join(format!("C{}.MP4", id)),
-        VideoFiles::Metadata     => m4root.join("CLIP")  .join(format!("C{}M01.XML", id)),
-        VideoFiles::Thumbnail    => m4root.join("THMBNL").join(format!("C{}T01.JPG", id)),
-        VideoFiles::VideoPreview => m4root.join("SUB")   .join(format!("C{}S03.MP4", id)),
+        VideoFiles::Video        => subfolder("CLIP")  .join(format!("C{}.MP4", id)),
+        VideoFiles::Metadata     => subfolder("CLIP")  .join(format!("C{}M01.XML", id)),
+        VideoFiles::Thumbnail    => subfolder("THMBNL").join(format!("C{}T01.JPG", id)),
+        VideoFiles::VideoPreview => subfolder("SUB")   .join(format!("C{}S03.MP4", id)),
     } )
 }
 
+/// Run the file name parsers on an arbitrary file, for the fuzz target. They are expected to
+/// return an error for names they can't handle instead of panicking
+#[cfg(fuzzing)]
+pub fn fuzz_file_name_parsers(file: &Path) {
+    for file_type in [|| VideoFiles::Thumbnail, || VideoFiles::Video, || VideoFiles::Metadata, || VideoFiles::VideoPreview] {
+        if let Ok(id) = get_video_id(file, file_type()) {
+            let _ = create_video_file(&crate::fs_provider::MemoryFs::default(), file, &id, file_type());
+        }
+    }
+    let _ = filetype(file, Path::new("/"));
+    let _ = is_m4root_management_file(file);
+    let _ = is_in_unknown_m4root_subfolder(file);
+    let _ = SonyInterface.capture_sequence(file);
+}
+
 pub struct SonyInterface;
 
 impl SourceMediaInterface for SonyInterface {
     //TODO: handle case where the thumbnail is in the known missing files and the item needs to be represented by something else
 
-    fn list_thumbnail(&self,  source_media_location: &Path,  source_media_card: &Path, _known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
+    fn list_thumbnail(&self,  source_media_location: &Path,  source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
         let mut files = Vec::<FileItem>::new();
-        let dcim = source_media_card.join("DCIM/");
-        if dcim.exists(){
-            for imagedir in fs::read_dir(dcim)? {
-                let mut image_set = filter_dir(&imagedir?.path(),|_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+        if let Some(dcim) = find_dir(options.fs.as_ref(), source_media_card, "DCIM") {
+            for imagedir in options.fs.read_dir(&dcim)? {
+                if is_excluded_dir(&imagedir.path, options) || is_skipped_hidden(&imagedir.path, options) {
+                    continue;
+                }
+                let mut image_set = filter_dir(&imagedir.path, options, |_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+                    // Lowest quality first: JPG/HIF, then ARW, then the pixel shift ARQ composite
                     match ext {
                         Some("ARW") => {
-                            if ! path.with_extension("JPG").exists() && ! path.with_extension("HIF").exists() {
+                            if find_file(options.fs.as_ref(), &path.with_extension("JPG")).is_none() && find_file(options.fs.as_ref(), &path.with_extension("HIF")).is_none() {
+                                Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
+                            }else{
+                                Ok(None)
+                            }
+                        }
+                        Some("ARQ") => {
+                            if find_file(options.fs.as_ref(), &path.with_extension("JPG")).is_none() && find_file(options.fs.as_ref(), &path.with_extension("HIF")).is_none() && find_file(options.fs.as_ref(), &path.with_extension("ARW")).is_none() {
                                 Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
                             }else{
                                 Ok(None)
@@ -163,19 +221,19 @@ impl SourceMediaInterface for SonyInterface {
                         Some("JPG") | Some("HIF") => {
                             Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
                         }
-                        Some(_) | None => Err(anyhow!("Unexpected file {}", path_str)),
+                        Some(_) | None => unknown_file(path_str, options),
                     }
                 })?;
                  files.append(&mut image_set);
             }
         }
-        if let Some(m4root) = find_m4root(source_media_card)? {
-            let mut videos = filter_dir(m4root.join("THMBNL/").as_path(),|_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+        if let Some(m4root) = find_m4root(options.fs.as_ref(), source_media_card)? {
+            let mut videos = filter_dir(&find_dir(options.fs.as_ref(), &m4root, "THMBNL").unwrap_or_else(|| m4root.join("THMBNL")), options, |_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
                 match ext {
                     Some("JPG") => {
                         Ok(Some(create_part_file(path_str.to_string(), filetype(path, source_media_location)?, 1, 1, None)))
                     }
-                    Some(_) | None => Err(anyhow!("Unexpected file {}", path_str)),
+                    Some(_) | None => unknown_file(path_str, options),
                 }
             })?;
             files.append(&mut videos);
@@ -183,37 +241,47 @@ impl SourceMediaInterface for SonyInterface {
 
         Ok(files)
     }
-    fn list_high_quality(&self,  source_media_location: &Path, source_media_card: &Path, _known_missing_files: Vec<PathBuf> ) -> Result<Vec<FileItem>> {
+    fn list_high_quality(&self,  source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions ) -> Result<Vec<FileItem>> {
         let mut files = Vec::<FileItem>::new();
-        let dcim = source_media_card.join("DCIM/");
-        if dcim.exists(){
-            for imagedir in fs::read_dir(source_media_card.join(dcim))? {
-                 let mut image_set = filter_dir(&imagedir?.path(),|_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+        if let Some(dcim) = find_dir(options.fs.as_ref(), source_media_card, "DCIM") {
+            for imagedir in options.fs.read_dir(&dcim)? {
+                 if is_excluded_dir(&imagedir.path, options) || is_skipped_hidden(&imagedir.path, options) {
+                     continue;
+                 }
+                 let mut image_set = filter_dir(&imagedir.path, options, |_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+                    // Highest quality first: the pixel shift ARQ composite, then ARW, then JPG/HIF
                     match ext {
                         Some("JPG") | Some("HIF") => {
-                            if ! path.with_extension("ARW").exists(){
+                            if find_file(options.fs.as_ref(), &path.with_extension("ARW")).is_none() && find_file(options.fs.as_ref(), &path.with_extension("ARQ")).is_none() {
                                 Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
                             }else{
                                 Ok(None)
                             }
                         }
                         Some("ARW") => {
+                            if find_file(options.fs.as_ref(), &path.with_extension("ARQ")).is_none() {
+                                Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
+                            }else{
+                                Ok(None)
+                            }
+                        }
+                        Some("ARQ") => {
                             Ok(Some(create_simple_file(path_str.to_string(), filetype(path, source_media_location)?, None)?))
                         }
-                        Some(_) | None => Err(anyhow!("Unexpected file {}", path_str)),
+                        Some(_) | None => unknown_file(path_str, options),
                     }
                 })?;
                  files.append(&mut image_set);
             }
         }
-        if let Some(m4root) = find_m4root(source_media_card)? {
-            let mut videos = filter_dir(m4root.join("CLIP/").as_path(),|_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
+        if let Some(m4root) = find_m4root(options.fs.as_ref(), source_media_card)? {
+            let mut videos = filter_dir(&find_dir(options.fs.as_ref(), &m4root, "CLIP").unwrap_or_else(|| m4root.join("CLIP")), options, |_filename: &str, ext: Option<&str>, path:&PathBuf, path_str: &str|{
                 match ext {
                     Some("MP4") => {
                         Ok(Some(create_part_file(path_str.to_string(), filetype(path, source_media_location)?, 1, 1, None)))
                     }
                     Some("XML") => Ok(None),
-                    Some(_) | None => Err(anyhow!("Unexpected file {}", path_str)),
+                    Some(_) | None => unknown_file(path_str, options),
                 }
             })?;
             files.append(&mut videos);
@@ -221,23 +289,33 @@ impl SourceMediaInterface for SonyInterface {
 
         Ok(files)
     }
-    fn get_related(&self, source_media_location: &Path, source_media_file: &Path, known_missing_files: Vec<PathBuf>) -> Result<Vec<FileItem>>{
+    fn get_related(&self, source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>>{
         let mut items = Vec::<FileItem>::new();
 
+        // Management files belong to no item, so are left to the unknown file policy like files
+        // the handler doesn't know
+        if is_m4root_management_file(source_media_file) {
+            return Ok(unknown_file(&source_media_file.to_string_lossy(), options)?.into_iter().collect());
+        }
+        if is_in_unknown_m4root_subfolder(source_media_file) && options.unknown_file_policy == UnknownFilePolicy::Ignore {
+            return Ok(items);
+        }
+
         let input_file_types = filetype(source_media_file, source_media_location)?;
 
         match input_file_types.item_type{
             ItemImage => {
+                let arq_path = source_media_file.with_extension("ARQ");
                 let arw_path = source_media_file.with_extension("ARW");
                 let jpg_path = source_media_file.with_extension("JPG");
                 let hif_path = source_media_file.with_extension("HIF");
-                for i in [arw_path, jpg_path, hif_path] {
-                    if let Some(v) = create_simple_file_if_exists(&i, filetype(&i, source_media_location)?, None)? {
+                for i in [arq_path, arw_path, jpg_path, hif_path] {
+                    if let Some(v) = create_simple_file_if_exists(options.fs.as_ref(), &i, filetype(&i, source_media_location)?, None)? {
                         items.push(v);
                     }
                 }
 
-                Ok(items)
+                check_related(options.fs.as_ref(), source_media_file, items)
             }
             ItemVideo => {
                 let video_type = match input_file_types.file_type{
@@ -250,19 +328,31 @@ impl SourceMediaInterface for SonyInterface {
 
                 let video_id = get_video_id(source_media_file, video_type)?;
 
-                for i in [VideoFiles::Metadata, VideoFiles::Video, VideoFiles::Thumbnail] {
-                    let file = create_video_file(source_media_file, &video_id, i)?;
-                    if let Some(item) = create_part_file_that_exists(&file, filetype(&file, source_media_location)?, 1, 1, None, &known_missing_files)?{
-                        items.push(item);
+                // Every missing member of the group is reported at once, so a partial card can be
+                // sorted out in one go
+                let mut missing = Vec::new();
+                for (i, member) in [(VideoFiles::Metadata, "XML"), (VideoFiles::Video, "MP4"), (VideoFiles::Thumbnail, "THMBNL")] {
+                    let file = create_video_file(options.fs.as_ref(), source_media_file, &video_id, i)?;
+                    match create_part_file_that_exists(options.fs.as_ref(), &file, filetype(&file, source_media_location)?, 1, 1, None, &options.known_missing_files) {
+                        Ok(Some(item)) => items.push(item),
+                        Ok(None) => {},
+                        Err(error) if error.is::<ExpectedFileMissing>() => missing.push((member, file)),
+                        Err(error) => return Err(error),
                     }
                 }
+                if let Some((_, first_missing)) = missing.first() {
+                    let members = missing.iter().map(|(member, _)| *member).collect::<Vec<_>>().join(", ");
+                    let files = missing.iter().map(|(_, file)| format!("{:?}", file)).collect::<Vec<_>>().join(", ");
+                    return Err(anyhow::Error::new(ExpectedFileMissing(first_missing.clone()))
+                        .context(format!("video C{} missing: {} (expected {})", video_id, members, files)));
+                }
 
-                let proxy_file = create_video_file(source_media_file, &video_id, VideoFiles::VideoPreview)?;
-                if let Some(item) = create_part_file_if_exists(&proxy_file, filetype(&proxy_file, source_media_location)?, 1, 1, None) {
+                let proxy_file = create_video_file(options.fs.as_ref(), source_media_file, &video_id, VideoFiles::VideoPreview)?;
+                if let Some(item) = create_part_file_if_exists(options.fs.as_ref(), &proxy_file, filetype(&proxy_file, source_media_location)?, 1, 1, None) {
                     items.push(item);
                 }
 
-                Ok(items)
+                check_related(options.fs.as_ref(), source_media_file, items)
             }
             _ => {
                 Err(anyhow!("Internal error"))
@@ -272,4 +362,216 @@ impl SourceMediaInterface for SonyInterface {
     fn name(&self) -> &'static str {
         "Sony-ILCEM4-1"
     }
+    fn required_subdirs(&self) -> &'static [&'static str] {
+        &["DCIM", "PRIVATE/M4ROOT", "M4ROOT"]
+    }
+    // Photos (DCIM/100MSDCF/DSC01234.JPG) and videos (PRIVATE/M4ROOT/CLIP/C0001.MP4) are
+    // numbered by separate counters
+    fn capture_sequence(&self, file: &Path) -> Option<CaptureSequence> {
+        let grandparent = file.parent()?.parent()?;
+        if is_named(grandparent, "DCIM") {
+            let name = file.file_stem()?.to_str()?;
+            let number = name.get(name.len().checked_sub(4)?..)?.parse::<u32>().ok()?;
+            Some(CaptureSequence{ counter: "photo", number, wrap: 10000, part: 1 })
+        } else if is_named(grandparent, "M4ROOT") {
+            let number = [VideoFiles::Video, VideoFiles::Thumbnail, VideoFiles::VideoPreview, VideoFiles::Metadata].into_iter()
+                .find_map(|file_type| get_video_id(file, file_type).ok())?.parse::<u32>().ok()?;
+            Some(CaptureSequence{ counter: "video", number, wrap: 10000, part: 1 })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card};
+
+    #[test]
+    fn raw_and_jpg_pair_is_one_item() {
+        let options = memory_card("/media/card", &["/media/card/DCIM/100MSDCF/DSC00001.JPG", "/media/card/DCIM/100MSDCF/DSC00001.ARW", "/media/card/DCIM/100MSDCF/DSC00002.JPG"]);
+        let location = Path::new("/media");
+        let card = Path::new("/media/card");
+
+        let mut items = SonyInterface.list_thumbnail(location, card, &options).unwrap();
+        items.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.JPG", "/media/card/DCIM/100MSDCF/DSC00002.JPG"]);
+
+        let mut items = SonyInterface.list_high_quality(location, card, &options).unwrap();
+        items.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.ARW", "/media/card/DCIM/100MSDCF/DSC00002.JPG"]);
+
+        let items = SonyInterface.get_related(location, Path::new("/media/card/DCIM/100MSDCF/DSC00001.JPG"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.ARW", "/media/card/DCIM/100MSDCF/DSC00001.JPG"]);
+    }
+
+    #[test]
+    fn pixel_shift_composite_is_the_best_of_its_item() {
+        let options = memory_card("/media/card", &["/media/card/DCIM/100MSDCF/DSC00001.JPG", "/media/card/DCIM/100MSDCF/DSC00001.ARW", "/media/card/DCIM/100MSDCF/DSC00001.ARQ"]);
+        let location = Path::new("/media");
+        let card = Path::new("/media/card");
+
+        let items = SonyInterface.list_thumbnail(location, card, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.JPG"]);
+        let items = SonyInterface.list_high_quality(location, card, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.ARQ"]);
+
+        for file in ["DSC00001.JPG", "DSC00001.ARW", "DSC00001.ARQ"] {
+            let items = SonyInterface.get_related(location, &card.join("DCIM/100MSDCF").join(file), &options).unwrap();
+            assert_eq!(item_paths(&items), ["/media/card/DCIM/100MSDCF/DSC00001.ARQ", "/media/card/DCIM/100MSDCF/DSC00001.ARW", "/media/card/DCIM/100MSDCF/DSC00001.JPG"]);
+        }
+    }
+
+    #[test]
+    fn short_and_misnamed_video_files_are_an_error() {
+        for name in ["C01.MP4", "C0.XML", "CAB.MP4", "C", "Cé01.MP4", "C0001T1.JPG"] {
+            for file_type in [VideoFiles::Video, VideoFiles::Metadata, VideoFiles::Thumbnail, VideoFiles::VideoPreview] {
+                assert!(get_video_id(&Path::new("/media/card/PRIVATE/M4ROOT/CLIP").join(name), file_type).is_err(), "{}", name);
+            }
+        }
+        assert_eq!(get_video_id(Path::new("/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"), VideoFiles::Thumbnail).unwrap(), "0001");
+
+        let options = memory_card("/media/card", &["/media/card/PRIVATE/M4ROOT/CLIP/C01.MP4"]);
+        assert!(SonyInterface.get_related(Path::new("/media"), Path::new("/media/card/PRIVATE/M4ROOT/CLIP/C01.MP4"), &options).is_err());
+        // Listing doesn't need the clip number of videos, only to not panic on their names
+        let _ = SonyInterface.list_thumbnail(Path::new("/media"), Path::new("/media/card"), &options);
+        let _ = SonyInterface.list_high_quality(Path::new("/media"), Path::new("/media/card"), &options);
+    }
+
+    /// What an A7 IV writes to an SD card recording a single clip
+    const M4ROOT_TREE: [&str; 9] = [
+        "/media/card/PRIVATE/M4ROOT/MEDIAPRO.XML", "/media/card/PRIVATE/M4ROOT/CUEUP.XML", "/media/card/PRIVATE/M4ROOT/STATUS.BIN",
+        "/media/card/PRIVATE/M4ROOT/GENERAL/COMMON/DATA.BIN", "/media/card/PRIVATE/M4ROOT/GENERAL/SONY/SETTING.DAT",
+        "/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4", "/media/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML",
+        "/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4",
+    ];
+
+    #[test]
+    fn m4root_management_files_belong_to_no_item() {
+        let location = Path::new("/media");
+        let card_dir = Path::new("/media/card");
+        let options = memory_card("/media/card", &M4ROOT_TREE);
+
+        let items = SonyInterface.list_thumbnail(location, card_dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"]);
+        let items = SonyInterface.list_high_quality(location, card_dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"]);
+        let items = SonyInterface.get_related(location, Path::new("/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4"), &options).unwrap();
+        assert_eq!(items.iter().map(|item| item.file_type.as_str()).collect::<Vec<_>>(), ["metadata", "video", "image-preview", "video-preview"]);
+
+        // Files of a folder the handler doesn't know are left to the unknown file policy. The list
+        // actions only go through the folders items are in, so never come across them
+        let mut options = memory_card("/media/card", &[&M4ROOT_TREE[..], &["/media/card/PRIVATE/M4ROOT/TAKE/C0002.MP4"]].concat());
+        let items = SonyInterface.list_high_quality(location, card_dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"]);
+        assert!(SonyInterface.get_related(location, Path::new("/media/card/PRIVATE/M4ROOT/TAKE/C0002.MP4"), &options).is_err());
+        options.unknown_file_policy = UnknownFilePolicy::Ignore;
+        assert!(SonyInterface.get_related(location, Path::new("/media/card/PRIVATE/M4ROOT/TAKE/C0002.MP4"), &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn card_folders_are_found_whatever_their_case() {
+        let options = memory_card("/media/card", &["/media/card/dcim/100msdcf/DSC00001.JPG", "/media/card/dcim/100msdcf/DSC00001.ARW",
+                             "/media/card/private/m4root/clip/C0001.MP4", "/media/card/private/m4root/clip/C0001M01.XML",
+                             "/media/card/private/m4root/thmbnl/C0001T01.JPG"]);
+        let (location, dir) = (Path::new("/media"), Path::new("/media/card"));
+        // As the card structure check looks them up
+        assert_eq!(find_dir(options.fs.as_ref(), dir, "DCIM"), Some(PathBuf::from("/media/card/dcim")));
+        assert_eq!(find_dir(options.fs.as_ref(), dir, "PRIVATE/M4ROOT"), Some(PathBuf::from("/media/card/private/m4root")));
+
+        let items = SonyInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/media/card/dcim/100msdcf/DSC00001.ARW", "/media/card/private/m4root/clip/C0001.MP4"]);
+        let items = SonyInterface.get_related(location, Path::new("/media/card/private/m4root/clip/C0001.MP4"), &options).unwrap();
+        let mut related = item_paths(&items);
+        related.sort();
+        assert_eq!(related, ["/media/card/private/m4root/clip/C0001.MP4", "/media/card/private/m4root/clip/C0001M01.XML",
+                             "/media/card/private/m4root/thmbnl/C0001T01.JPG"]);
+        let items = SonyInterface.get_related(location, Path::new("/media/card/dcim/100msdcf/DSC00001.JPG"), &options).unwrap();
+        assert_eq!(items.len(), 2);
+
+        // Still only in the folders of the card structure
+        let options = memory_card("/media/card", &["/media/card/dcim/100other/DSC00001.JPG", "/media/card/private/clip/C0001.MP4"]);
+        assert!(SonyInterface.get_related(location, Path::new("/media/card/dcim/100other/DSC00001.JPG"), &options).is_err());
+        assert!(SonyInterface.get_related(location, Path::new("/media/card/private/clip/C0001.MP4"), &options).is_err());
+    }
+
+    #[test]
+    fn every_file_of_a_video_group_is_related_to_the_whole_group() {
+        let group = ["/media/card/PRIVATE/M4ROOT/CLIP/C0042.MP4", "/media/card/PRIVATE/M4ROOT/CLIP/C0042M01.XML",
+                     "/media/card/PRIVATE/M4ROOT/THMBNL/C0042T01.JPG", "/media/card/PRIVATE/M4ROOT/SUB/C0042S03.MP4"];
+        let options = memory_card("/media/card", &group);
+        for (file, file_type) in group.iter().zip([VideoFiles::Video, VideoFiles::Metadata, VideoFiles::Thumbnail, VideoFiles::VideoPreview]) {
+            assert_eq!(get_video_id(Path::new(file), file_type).unwrap(), "0042", "{}", file);
+            let items = SonyInterface.get_related(Path::new("/media"), Path::new(file), &options).unwrap();
+            let mut related = item_paths(&items);
+            related.sort();
+            let mut expected = group.to_vec();
+            expected.sort();
+            assert_eq!(related, expected, "{}", file);
+        }
+
+        // Each shape is only the clip number of the kind of file it's for
+        assert!(get_video_id(Path::new("/media/card/PRIVATE/M4ROOT/CLIP/C0042M01.XML"), VideoFiles::Video).is_err());
+        assert!(get_video_id(Path::new("/media/card/PRIVATE/M4ROOT/CLIP/C0042.XML"), VideoFiles::Metadata).is_err());
+        assert!(get_video_id(Path::new("/media/card/PRIVATE/M4ROOT/SUB/C0042T01.MP4"), VideoFiles::VideoPreview).is_err());
+    }
+
+    #[test]
+    fn items_are_in_the_roll_of_their_folder() {
+        let options = memory_card("/media/card", &["/media/card/DCIM/100MSDCF/DSC00001.JPG", "/media/card/DCIM/101MSDCF/DSC00002.JPG",
+                             "/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4", "/media/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML",
+                             "/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"]);
+        let items = SonyInterface.list_high_quality(Path::new("/media"), Path::new("/media/card"), &options).unwrap();
+        let rolls: Vec<_> = items.iter().map(|item| item.roll.as_deref()).collect();
+        assert_eq!(rolls, [Some("100MSDCF"), Some("101MSDCF"), Some("CLIP")]);
+
+        let items = SonyInterface.get_related(Path::new("/media"), Path::new("/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"), &options).unwrap();
+        let thumbnail = items.iter().find(|item| item.file_path.ends_with("C0001T01.JPG")).unwrap();
+        assert_eq!(thumbnail.roll.as_deref(), Some("THMBNL"));
+    }
+
+    #[test]
+    fn every_missing_member_of_a_video_group_is_reported() {
+        let members = [("XML", "/media/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML"), ("MP4", "/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"),
+                       ("THMBNL", "/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG")];
+        // Asked for through the proxy, which is there every time and never required
+        let error_without = |missing: &[usize], known_missing: &[usize]| {
+            let mut files: Vec<&str> = (0..3).filter(|i| !missing.contains(i)).map(|i| members[i].1).collect();
+            files.push("/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4");
+            let mut options = memory_card("/media/card", &files);
+            options.known_missing_files = known_missing.iter().map(|&i| PathBuf::from(members[i].1)).collect();
+            SonyInterface.get_related(Path::new("/media"), Path::new("/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4"), &options)
+                .err().map(|error| format!("{:#}", error))
+        };
+
+        for (i, (member, file)) in members.iter().enumerate() {
+            let error = error_without(&[i], &[]).unwrap();
+            assert!(error.contains(&format!("video C0001 missing: {} (expected {:?})", member, file)), "{}", error);
+            assert_eq!(error_without(&[i], &[i]), None, "{}", member);
+        }
+        let error = error_without(&[0, 1, 2], &[]).unwrap();
+        assert!(error.contains("video C0001 missing: XML, MP4, THMBNL"), "{}", error);
+        let error = error_without(&[1, 2], &[]).unwrap();
+        assert!(error.contains("video C0001 missing: MP4, THMBNL"), "{}", error);
+        let error = error_without(&[1, 2], &[2]).unwrap();
+        assert!(error.contains("video C0001 missing: MP4 ("), "{}", error);
+    }
+
+    #[test]
+    fn thumbnail_candidates_are_the_thumbnail_then_the_proxy_then_the_clip() {
+        let options = memory_card("/media/card", &["/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4", "/media/card/PRIVATE/M4ROOT/CLIP/C0001M01.XML",
+                             "/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4"]);
+        let candidates = SonyInterface.thumbnail_candidates(Path::new("/media"), Path::new("/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"), &options);
+        assert_eq!(candidates, ["/media/card/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "/media/card/PRIVATE/M4ROOT/SUB/C0001S03.MP4",
+                                "/media/card/PRIVATE/M4ROOT/CLIP/C0001.MP4"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn items_have_the_basename_and_lowercase_extension_of_their_file() {
+        let options = memory_card("/media/card", &["/media/card/DCIM/100MSDCF/DSC00001.ARW"]);
+        let items = SonyInterface.list_high_quality(Path::new("/media"), Path::new("/media/card"), &options).unwrap();
+        assert_eq!(items[0].basename.as_deref(), Some("DSC00001.ARW"));
+        assert_eq!(items[0].extension.as_deref(), Some("arw"));
+    }
 }
@@ -0,0 +1,150 @@
+/* stereo_pair_generic_1.rs - Handler for 3D cameras and rigs that write a stereo photo as a
+ * NAME_L.JPG / NAME_R.JPG pair or as a single MPO file
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+use anyhow::{Result, anyhow};
+use crate::{SourceMediaInterface, HandlerOptions, PartsPolicy};
+use std::path::{PathBuf,Path};
+use crate::helpers::*;
+use crate::FileItem;
+use crate::helpers::ItemType::*;
+use crate::helpers::FileType::*;
+
+pub struct StereoPairInterface;
+
+const IMAGE: JsonFileInfoTypes = JsonFileInfoTypes{ file_type:FileImage, item_type:ItemImage };
+
+/// The left and right files of the pair `file` is a side of, going by the _L or _R at the end of
+/// its name, and the part number of `file`: 1 for the left side and 2 for the right
+fn stereo_pair(file: &Path) -> Option<([PathBuf; 2], u8)> {
+    let stem = file.file_stem()?.to_str()?;
+    let ext = file.extension()?.to_str()?;
+    let (name, side) = stem.rsplit_once('_')?;
+    let (part_num, left, right) = match side {
+        "L" => (1, "L", "R"),
+        "R" => (2, "L", "R"),
+        "l" => (1, "l", "r"),
+        "r" => (2, "l", "r"),
+        _ => return None,
+    };
+    Some(([file.with_file_name(format!("{name}_{left}.{ext}")), file.with_file_name(format!("{name}_{right}.{ext}"))], part_num))
+}
+
+/// A side of a pair is only listed for the item when it's the left one, or the right one with the
+/// left one missing, unless every part is listed. The other side has to be there or be a known
+/// missing file
+fn list_pair_side(path_str: &str, pair: &[PathBuf; 2], part_num: u8, options: &HandlerOptions) -> Result<Option<FileItem>> {
+    let fs = options.fs.as_ref();
+    let other = &pair[2 - part_num as usize];
+    if find_file(fs, other).is_none() && !options.known_missing_files.contains(other) {
+        return Err(ExpectedFileMissing(other.clone()).into());
+    }
+    if part_num == 2 && options.parts_policy == PartsPolicy::First && find_file(fs, &pair[0]).is_some() {
+        return Ok(None);
+    }
+    Ok(Some(create_part_file(path_str.to_string(), IMAGE, 2, part_num, None)))
+}
+
+/// The items in `dir` and its subdirectories
+fn list_dir(dir: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+    let mut subdirs = Vec::new();
+    let mut items = filter_dir_and_subdirs(dir, options, &mut subdirs, |_filename: &str, ext: Option<&str>, path: &PathBuf, path_str: &str| {
+        match ext.map(str::to_ascii_uppercase).as_deref() {
+            Some("JPG" | "JPEG") => match stereo_pair(path) {
+                Some((pair, part_num)) => list_pair_side(path_str, &pair, part_num, options),
+                None => Ok(Some(create_simple_file(path_str.to_string(), IMAGE, None)?)),
+            },
+            // The multi picture format keeps both sides in the one file
+            Some("MPO") => Ok(Some(create_simple_file(path_str.to_string(), IMAGE, None)?)),
+            _ => unknown_file(path_str, options),
+        }
+    })?;
+
+    for subdir in subdirs {
+        items.extend(list_dir(&subdir, options)?);
+    }
+    Ok(items)
+}
+
+impl SourceMediaInterface for StereoPairInterface {
+    fn list_thumbnail(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        list_dir(source_media_card, options)
+    }
+    fn list_high_quality(&self, _source_media_location: &Path, source_media_card: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        list_dir(source_media_card, options)
+    }
+    fn get_related(&self, _source_media_location: &Path, source_media_file: &Path, options: &HandlerOptions) -> Result<Vec<FileItem>> {
+        let fs = options.fs.as_ref();
+        let items = match get_extension_str(source_media_file)?.to_ascii_uppercase().as_str() {
+            "JPG" | "JPEG" => match stereo_pair(source_media_file) {
+                Some(([left, right], _)) => Ok([
+                    create_part_file_that_exists(fs, &left, IMAGE, 2, 1, None, &options.known_missing_files)?,
+                    create_part_file_that_exists(fs, &right, IMAGE, 2, 2, None, &options.known_missing_files)?,
+                ].into_iter().flatten().collect()),
+                None => create_simple_file_if_exists(fs, source_media_file, IMAGE, None).map(|item| item.into_iter().collect()),
+            },
+            "MPO" => create_simple_file_if_exists(fs, source_media_file, IMAGE, None).map(|item| item.into_iter().collect()),
+            ext => Err(anyhow!("unexpected file extension '{}' in file '{:?}'", ext, source_media_file)),
+        }?;
+        check_related(fs, source_media_file, items)
+    }
+    fn name(&self) -> &'static str {
+        "Stereo-Pair-Generic-1"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_provider::{item_paths, memory_card};
+
+    #[test]
+    fn left_and_right_photos_are_the_parts_of_one_item() {
+        let mut options = memory_card("/card", &["/card/DCIM/100_3D/A_L.JPG", "/card/DCIM/100_3D/A_R.JPG", "/card/DCIM/100_3D/B.MPO", "/card/DCIM/100_3D/C.JPG",
+                                 "/card/DCIM/100_3D/D_r.jpg"]);
+        options.known_missing_files = vec![PathBuf::from("/card/DCIM/100_3D/D_l.jpg")];
+        let (location, dir) = (Path::new("/"), Path::new("/card"));
+
+        let items = StereoPairInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100_3D/A_L.JPG", "/card/DCIM/100_3D/B.MPO", "/card/DCIM/100_3D/C.JPG", "/card/DCIM/100_3D/D_r.jpg"]);
+        assert_eq!((items[0].part_num, items[0].part_count), (Some(1), Some(2)));
+        assert_eq!((items[3].part_num, items[3].part_count), (Some(2), Some(2)));
+        assert_eq!(items[1].part_count, None);
+
+        for side in ["/card/DCIM/100_3D/A_L.JPG", "/card/DCIM/100_3D/A_R.JPG"] {
+            let items = StereoPairInterface.get_related(location, Path::new(side), &options).unwrap();
+            assert_eq!(item_paths(&items), ["/card/DCIM/100_3D/A_L.JPG", "/card/DCIM/100_3D/A_R.JPG"], "{}", side);
+            assert!(items.iter().all(|item| item.item_type == "image"));
+        }
+        let items = StereoPairInterface.get_related(location, Path::new("/card/DCIM/100_3D/B.MPO"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100_3D/B.MPO"]);
+        let items = StereoPairInterface.get_related(location, Path::new("/card/DCIM/100_3D/D_r.jpg"), &options).unwrap();
+        assert_eq!(item_paths(&items), ["/card/DCIM/100_3D/D_r.jpg"]);
+
+        options.parts_policy = PartsPolicy::All;
+        let items = StereoPairInterface.list_high_quality(location, dir, &options).unwrap();
+        assert_eq!(item_paths(&items)[..2], ["/card/DCIM/100_3D/A_L.JPG", "/card/DCIM/100_3D/A_R.JPG"]);
+    }
+
+    #[test]
+    fn a_side_without_the_other_one_is_an_error() {
+        let options = memory_card("/card", &["/card/A_L.JPG"]);
+        assert!(StereoPairInterface.list_high_quality(Path::new("/"), Path::new("/card"), &options).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+/* archive.rs - Checks cards packed in tar and zip archives are seen as the directory they were made from
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+#![cfg(feature = "archive")]
+
+mod common;
+
+use common::*;
+use media_interface::fs_provider::{ArchiveFs, FsProvider};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const GOPRO_CARD: &[&str] = &["GX010001.MP4", "GX010001.THM", "GL010001.LRV", "GX020001.MP4", "GX020001.THM", "GL020001.LRV", "GOPR0002.JPG"];
+
+/// How a tar entry stores its name
+#[derive(Clone, Copy)]
+enum TarName {
+    /// In the name field, or split over the prefix and name fields of the ustar header
+    Ustar,
+    /// In a GNU long name entry before it
+    GnuLongName,
+    /// In a pax extended header before it
+    Pax,
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let text = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..text.len()].copy_from_slice(text.as_bytes());
+}
+
+fn tar_header(name: &[u8], prefix: &[u8], type_flag: u8, len: u64) -> Vec<u8> {
+    let mut header = vec![0u8; 512];
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 1000);
+    octal(&mut header[116..124], 1000);
+    octal(&mut header[124..136], len);
+    octal(&mut header[136..148], 1_700_000_000);
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+    octal(&mut header[148..155], checksum);
+    header
+}
+
+fn tar_data(archive: &mut Vec<u8>, data: &[u8]) {
+    archive.extend_from_slice(data);
+    archive.resize(archive.len().div_ceil(512) * 512, 0);
+}
+
+/// A tar archive of `files`, entries ending in / being directories
+fn tar(files: &[(&str, &[u8])], naming: TarName) -> Vec<u8> {
+    let mut archive = Vec::new();
+    for (name, contents) in files {
+        let type_flag = if name.ends_with('/') { b'5' } else { b'0' };
+        let bytes = name.as_bytes();
+        match naming {
+            TarName::Ustar if bytes.len() > 100 => {
+                let split = bytes.iter().rposition(|byte| *byte == b'/').unwrap();
+                archive.extend(tar_header(&bytes[split + 1..], &bytes[..split], type_flag, contents.len() as u64));
+            }
+            TarName::Ustar => archive.extend(tar_header(bytes, b"", type_flag, contents.len() as u64)),
+            TarName::GnuLongName => {
+                let long_name = [bytes, b"\0"].concat();
+                archive.extend(tar_header(b"././@LongLink", b"", b'L', long_name.len() as u64));
+                tar_data(&mut archive, &long_name);
+                archive.extend(tar_header(&bytes[..bytes.len().min(100)], b"", type_flag, contents.len() as u64));
+            }
+            TarName::Pax => {
+                let record_body = format!(" path={}\n", name);
+                // The length counts its own digits too
+                let mut len = record_body.len() + 1;
+                while format!("{}{}", len, record_body).len() != len {
+                    len += 1;
+                }
+                let record = format!("{}{}", len, record_body);
+                archive.extend(tar_header(b"PaxHeaders/entry", b"", b'x', record.len() as u64));
+                tar_data(&mut archive, record.as_bytes());
+                archive.extend(tar_header(b"placeholder", b"", type_flag, contents.len() as u64));
+            }
+        }
+        tar_data(&mut archive, contents);
+    }
+    archive.extend([0u8; 1024]);
+    archive
+}
+
+/// How a zip entry is stored
+#[derive(Clone, Copy, PartialEq)]
+enum ZipEntry {
+    Stored,
+    /// Stored, with its sizes and offset in a zip64 extra field
+    Zip64,
+}
+
+fn zip(files: &[(&str, &[u8])], storage: ZipEntry) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, contents) in files {
+        let offset = archive.len() as u64;
+        let method: u16 = 0;
+        let (len_field, offset_field, extra) = match storage {
+            ZipEntry::Zip64 => (0xffff_ffffu32, 0xffff_ffffu32, [&1u16.to_le_bytes()[..], &24u16.to_le_bytes(), &(contents.len() as u64).to_le_bytes(),
+                                                                   &(contents.len() as u64).to_le_bytes(), &offset.to_le_bytes()].concat()),
+            _ => (contents.len() as u32, offset as u32, Vec::new()),
+        };
+        // 2023-11-14 22:13:20, as MS-DOS date and time
+        let (time, date) = ((22u16 << 11) | (13 << 5) | 10, (43u16 << 9) | (11 << 5) | 14);
+        let common = [&method.to_le_bytes()[..], &time.to_le_bytes(), &date.to_le_bytes(), &0u32.to_le_bytes(), &len_field.to_le_bytes(), &len_field.to_le_bytes(),
+                      &(name.len() as u16).to_le_bytes(), &(extra.len() as u16).to_le_bytes()].concat();
+        archive.extend([&b"PK\x03\x04"[..], &20u16.to_le_bytes(), &0u16.to_le_bytes(), &common, name.as_bytes(), &extra, contents].concat());
+        directory.extend([&b"PK\x01\x02"[..], &0x0314u16.to_le_bytes(), &20u16.to_le_bytes(), &0u16.to_le_bytes(), &common,
+                          &0u16.to_le_bytes(), &0u16.to_le_bytes(), &0u16.to_le_bytes(), &(0o100644u32 << 16).to_le_bytes(), &offset_field.to_le_bytes(),
+                          name.as_bytes(), &extra].concat());
+    }
+    let directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&directory);
+    let count = (files.len() as u16).to_le_bytes();
+    archive.extend([&b"PK\x05\x06"[..], &0u16.to_le_bytes(), &0u16.to_le_bytes(), &count, &count, &(directory.len() as u32).to_le_bytes(),
+                    &directory_offset.to_le_bytes(), &0u16.to_le_bytes()].concat());
+    archive
+}
+
+fn open(dir: &TestDir, name: &str, data: &[u8]) -> std::io::Result<(PathBuf, ArchiveFs)> {
+    let path = dir.path.join(name);
+    std::fs::write(&path, data).unwrap();
+    ArchiveFs::open(&path).map(|archive| (path, archive))
+}
+
+/// Every file under the archive, relative to it
+fn files_of(archive_path: &Path, archive: &ArchiveFs) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![archive_path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in archive.read_dir(&dir).unwrap() {
+            match entry.is_dir {
+                true => dirs.push(entry.path),
+                false => files.push(entry.path.strip_prefix(archive_path).unwrap().to_string_lossy().into_owned()),
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn tar_names_are_read_from_every_kind_of_header() {
+    let dir = TestDir::new();
+    let long_dir = "DCIM/".to_string() + &"long folder name ".repeat(6);
+    let long_name = format!("{}/GOPR0001.JPG", long_dir.trim_end());
+    assert!(long_name.len() > 100);
+    for naming in [TarName::Ustar, TarName::GnuLongName, TarName::Pax] {
+        let (path, archive) = open(&dir, "card.tar", &tar(&[("GX010001.THM", b"thumbnail"), (&long_name, b"photo")], naming)).unwrap();
+        assert_eq!(files_of(&path, &archive), [long_name.clone(), "GX010001.THM".to_string()]);
+
+        let thumbnail = path.join("GX010001.THM");
+        assert_eq!(archive.read(&thumbnail).unwrap(), b"thumbnail");
+        assert_eq!(archive.read_range(&thumbnail, 2, 3).unwrap(), b"umb");
+        assert_eq!(archive.read(&path.join(&long_name)).unwrap(), b"photo");
+        let metadata = archive.metadata(&thumbnail).unwrap();
+        assert_eq!((metadata.len, metadata.mode, metadata.uid), (9, Some(0o644), Some(1000)));
+        assert_eq!(metadata.modified, Some(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)));
+    }
+}
+
+#[test]
+fn the_single_top_level_folder_of_an_archive_is_the_card() {
+    let dir = TestDir::new();
+    let files: &[(&str, &[u8])] = &[("CARD0001/", b""), ("CARD0001/GOPR0001.JPG", b"photo")];
+    let (path, archive) = open(&dir, "card.tar", &tar(files, TarName::Ustar)).unwrap();
+    assert_eq!(files_of(&path, &archive), ["GOPR0001.JPG"]);
+    let (path, archive) = open(&dir, "card.zip", &zip(files, ZipEntry::Stored)).unwrap();
+    assert_eq!(files_of(&path, &archive), ["GOPR0001.JPG"]);
+
+    // Paths outside of the archive are the real filesystem
+    std::fs::write(dir.path.join("beside.txt"), "beside").unwrap();
+    assert_eq!(archive.read(&dir.path.join("beside.txt")).unwrap(), b"beside");
+}
+
+#[test]
+fn malformed_tar_archives_are_an_error() {
+    let dir = TestDir::new();
+    let archive = tar(&[("GOPR0001.JPG", &[b'x'; 600])], TarName::Ustar);
+
+    let error = open(&dir, "truncated.tar", &archive[..900]).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", error);
+
+    let mut bad_size = archive.clone();
+    bad_size[124..136].copy_from_slice(b"12345678z00\0");
+    assert_eq!(open(&dir, "bad_size.tar", &bad_size).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    let escaping = tar(&[("../GOPR0001.JPG", b"photo")], TarName::Ustar);
+    assert_eq!(open(&dir, "escaping.tar", &escaping).err().unwrap().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn zip_entries_are_read_stored_and_with_zip64_fields() {
+    let dir = TestDir::new();
+    for storage in [ZipEntry::Stored, ZipEntry::Zip64] {
+        let (path, archive) = open(&dir, "card.zip", &zip(&[("GX010001.THM", b"thumbnail"), ("DCIM/GOPR0001.JPG", b"photo")], storage)).unwrap();
+        assert_eq!(files_of(&path, &archive), ["DCIM/GOPR0001.JPG", "GX010001.THM"]);
+        let thumbnail = path.join("GX010001.THM");
+        assert_eq!(archive.read(&thumbnail).unwrap(), b"thumbnail");
+        assert_eq!(archive.read_range(&thumbnail, 5, 100).unwrap(), b"nail");
+        assert_eq!(archive.read(&path.join("DCIM/GOPR0001.JPG")).unwrap(), b"photo");
+        let metadata = archive.metadata(&thumbnail).unwrap();
+        assert_eq!((metadata.len, metadata.mode), (9, Some(0o100644)));
+        assert_eq!(metadata.modified, Some(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)));
+    }
+}
+
+/// A zip archive of `files` deflated, as zip tools write them by default
+fn deflated_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, contents) in files {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn deflated_zip_entries_are_listed_and_read() {
+    let dir = TestDir::new();
+    let photo = b"photo ".repeat(1000);
+    let (path, archive) = open(&dir, "card.zip", &deflated_zip(&[("GX010001.THM", b"thumbnail"), ("DCIM/GOPR0001.JPG", &photo)])).unwrap();
+    assert_eq!(files_of(&path, &archive), ["DCIM/GOPR0001.JPG", "GX010001.THM"]);
+
+    let thumbnail = path.join("GX010001.THM");
+    assert_eq!(archive.read(&thumbnail).unwrap(), b"thumbnail");
+    assert_eq!(archive.read_range(&thumbnail, 5, 100).unwrap(), b"nail");
+    assert_eq!(archive.metadata(&thumbnail).unwrap().len, 9);
+    assert_eq!(archive.read(&path.join("DCIM/GOPR0001.JPG")).unwrap(), photo);
+    assert_eq!(archive.read_range(&path.join("DCIM/GOPR0001.JPG"), 3000, 5).unwrap(), b"photo");
+}
+
+#[test]
+fn malformed_zip_archives_are_an_error() {
+    let dir = TestDir::new();
+    let archive = zip(&[("GOPR0001.JPG", b"photo")], ZipEntry::Stored);
+
+    assert_eq!(open(&dir, "no_end.zip", &archive[..archive.len() - 22]).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    let mut bad_local_header = archive.clone();
+    bad_local_header[0] = b'X';
+    assert_eq!(open(&dir, "bad_local_header.zip", &bad_local_header).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    let mut directory_out_of_range = archive.clone();
+    let end = directory_out_of_range.len() - 22;
+    directory_out_of_range[end + 16..end + 20].copy_from_slice(&1000u32.to_le_bytes());
+    assert_eq!(open(&dir, "directory_out_of_range.zip", &directory_out_of_range).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    let escaping = zip(&[("../GOPR0001.JPG", b"photo")], ZipEntry::Stored);
+    assert_eq!(open(&dir, "escaping.zip", &escaping).err().unwrap().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn archived_cards_are_listed_in_place_like_the_card_they_were_made_from() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let config = config.to_str().unwrap();
+    let card = &cards[0];
+    let names_under = |output: &serde_json::Value, root: &Path| -> Vec<String> {
+        let mut names: Vec<String> = file_paths(output).iter()
+            .map(|path| Path::new(path).strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let (from_dir, success) = run(&["-c", config, "-L", card.to_str().unwrap(), "--include-related"]);
+    assert!(success, "{}", from_dir);
+    let expected = names_under(&from_dir, card);
+    assert_eq!(expected.len(), GOPRO_CARD.len());
+
+    let files: Vec<(String, &[u8])> = GOPRO_CARD.iter().map(|file| (format!("CARD0001/{}", file), &b""[..])).collect();
+    let files: Vec<(&str, &[u8])> = files.iter().map(|(name, contents)| (name.as_str(), *contents)).collect();
+    for (name, archive) in [("CARD0002.tar", tar(&files, TarName::Ustar)), ("CARD0003.zip", zip(&files, ZipEntry::Stored)), ("CARD0004.zip", deflated_zip(&files))] {
+        let archive_path = card.parent().unwrap().join(name);
+        std::fs::write(&archive_path, archive).unwrap();
+        let (from_archive, success) = run(&["-c", config, "-L", archive_path.to_str().unwrap(), "--include-related"]);
+        assert!(success, "{}", from_archive);
+        assert_eq!(names_under(&from_archive, &archive_path), expected, "{}", name);
+
+        let (related, success) = run(&["-c", config, "-g", archive_path.join("GX020001.THM").to_str().unwrap()]);
+        assert!(success, "{}", related);
+        assert_eq!(file_paths(&related).len(), 6, "{}", related);
+    }
+}
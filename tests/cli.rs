@@ -0,0 +1,794 @@
+/* cli.rs - Checks the actions of the executable on cards written to disk
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+const GOPRO_CARD: &[&str] = &["GX010001.MP4", "GX010001.THM", "GL010001.LRV", "GX010001.JPG", "GX020001.MP4", "GX020001.THM", "GL020001.LRV",
+                              "GOPR0002.JPG", "GOPR0002.GPR", "GOPR0003.JPG"];
+
+#[test]
+fn flatten_parts_lists_every_file_once() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let card = cards[0].to_str().unwrap();
+
+    for action in ["-l", "-L"] {
+        let (output, success) = run(&["-c", config.to_str().unwrap(), action, card, "--flatten-parts"]);
+        assert!(success, "{}", output);
+        let mut counts = BTreeMap::new();
+        for path in file_paths(&output) {
+            *counts.entry(path).or_insert(0) += 1;
+        }
+        let expected: BTreeMap<_, _> = GOPRO_CARD.iter().map(|file| (cards[0].join(file).to_string_lossy().into_owned(), 1)).collect();
+        assert_eq!(counts, expected, "{}", action);
+    }
+}
+
+const SONY_CARD: &[&str] = &["PRIVATE/M4ROOT/CLIP/C0001.MP4", "PRIVATE/M4ROOT/CLIP/C0001M01.XML", "PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"];
+
+/// The thumbnail_path of every item of a file list output, by file path
+fn thumbnail_paths(output: &serde_json::Value) -> BTreeMap<String, Option<String>> {
+    output["file_list"].as_array().unwrap().iter()
+        .map(|item| (item["file_path"].as_str().unwrap().to_string(), item["thumbnail_path"].as_str().map(str::to_string)))
+        .collect()
+}
+
+#[test]
+fn thumbnail_paths_point_at_the_preview_of_video_items() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD), ("Sony-ILCEM4-1", SONY_CARD)]);
+    let config = config.to_str().unwrap();
+    let path_of = |card: usize, file: &str| cards[card].join(file).to_string_lossy().into_owned();
+
+    let (output, success) = run(&["-c", config, "-l", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert!(thumbnail_paths(&output).values().all(Option::is_none), "thumbnail paths without --thumbnail-path: {}", output);
+
+    let (output, success) = run(&["-c", config, "-l", cards[0].to_str().unwrap(), "--thumbnail-path"]);
+    assert!(success, "{}", output);
+    let thumbnails = thumbnail_paths(&output);
+    assert_eq!(thumbnails[&path_of(0, "GX010001.THM")], Some(path_of(0, "GX010001.THM")));
+    assert_eq!(thumbnails[&path_of(0, "GOPR0002.JPG")], None);
+
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap(), "--thumbnail-path"]);
+    assert!(success, "{}", output);
+    assert_eq!(thumbnail_paths(&output)[&path_of(1, "PRIVATE/M4ROOT/CLIP/C0001.MP4")], Some(path_of(1, "PRIVATE/M4ROOT/THMBNL/C0001T01.JPG")));
+
+    // A known missing preview is never pointed at
+    std::fs::remove_file(cards[1].join("PRIVATE/M4ROOT/THMBNL/C0001T01.JPG")).unwrap();
+    let errata = serde_json::json!({"data_type": "source_media_config", "errata": {"known_missing_files": ["DATA/CARD0001/PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"]}});
+    std::fs::write(dir.path.join("media1/interface_config.json"), errata.to_string()).unwrap();
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap(), "--thumbnail-path"]);
+    assert!(success, "{}", output);
+    assert_eq!(thumbnail_paths(&output)[&path_of(1, "PRIVATE/M4ROOT/CLIP/C0001.MP4")], None);
+}
+
+#[test]
+fn cached_listings_keep_their_thumbnail_paths() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let cache = dir.path.join("cache.json");
+    let args = ["-c", config.to_str().unwrap(), "-l", cards[0].to_str().unwrap(), "--thumbnail-path", "--cache", cache.to_str().unwrap()];
+
+    let (first, success) = run(&args);
+    assert!(success, "{}", first);
+
+    // Take a file away behind the back of the cache, leaving the directory looking unmodified,
+    // so that anything read from the card instead of the cache shows
+    let modified = std::fs::metadata(&cards[0]).unwrap().modified().unwrap();
+    std::fs::remove_file(cards[0].join("GL010001.LRV")).unwrap();
+    std::fs::File::open(&cards[0]).unwrap().set_modified(modified).unwrap();
+
+    let (second, success) = run(&args);
+    assert!(success, "{}", second);
+    assert_eq!(first["file_list"], second["file_list"]);
+}
+
+#[test]
+fn embedded_thumbnails_are_the_preview_of_video_items() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    std::fs::write(cards[0].join("GX010001.THM"), b"THM").unwrap();
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--embed-thumbnails"]);
+    assert!(success, "{}", output);
+    let video = output["file_list"].as_array().unwrap().iter()
+        .find(|item| item["file_path"].as_str().unwrap().ends_with("GX010001.MP4"))
+        .unwrap_or_else(|| panic!("no GX010001.MP4 in {}", output));
+    assert_eq!(video["thumbnail_base64"], "VEhN");
+}
+
+#[test]
+fn session_gaps_too_long_to_count_in_seconds_make_one_session() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", &["GOPR0001.JPG", "GOPR0002.JPG"])]);
+    let day_later = std::time::SystemTime::now() + std::time::Duration::from_secs(24 * 60 * 60);
+    std::fs::File::options().write(true).open(cards[0].join("GOPR0002.JPG")).unwrap().set_modified(day_later).unwrap();
+
+    let card = cards[0].to_str().unwrap();
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", card, "--group-by-session", &u64::MAX.to_string()]);
+    assert!(success, "{}", output);
+    assert_eq!(output["sessions"]["sessions"].as_array().unwrap().len(), 1, "{}", output);
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", card, "--group-by-session", "60"]);
+    assert!(success, "{}", output);
+    assert_eq!(output["sessions"]["sessions"].as_array().unwrap().len(), 2, "{}", output);
+}
+
+#[test]
+fn capabilities_conflicts_with_every_other_action() {
+    let dir = TestDir::new();
+    let manifest = dir.path.join("manifest.json");
+    let manifest = manifest.to_str().unwrap();
+    let card = dir.path.to_str().unwrap();
+    for action in [&["-l", card][..], &["-L", card], &["-g", card], &["--export-manifest", manifest], &["--verify-manifest", manifest]] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface"))
+            .arg("--capabilities").args(action)
+            .output().unwrap();
+        assert!(!output.status.success(), "--capabilities with {:?}", action);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"), "--capabilities with {:?}: {}", action, String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn dedup_by_path_leaves_out_files_listed_through_a_symlink() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["folder/photo.jpg", "other.jpg"])]);
+    let mut config_json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&config).unwrap()).unwrap();
+    config_json["source_media"][0]["recursive"] = true.into();
+    std::fs::write(&config, config_json.to_string()).unwrap();
+    std::os::unix::fs::symlink("other.jpg", cards[0].join("link.jpg")).unwrap();
+
+    let args = ["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()];
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output).len(), 3, "{}", output);
+
+    let (output, success) = run(&[&args[..], &["--dedup-by", "path"]].concat());
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["link.jpg", "photo.jpg"]);
+}
+
+#[test]
+fn dedup_by_path_leaves_out_files_of_a_card_given_twice() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["a.jpg"])]);
+    let card = cards[0].to_str().unwrap();
+    let card_with_slash = format!("{}/", card);
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", card, "-L", &card_with_slash, "--dedup-by", "path"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["a.jpg"]);
+    assert!(output["warnings"].to_string().contains("Left out 1 duplicate"), "{}", output);
+}
+
+#[test]
+fn dedup_by_item_id_keeps_one_file_of_every_item() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--flatten-parts", "--dedup-by", "item_id"]);
+    assert!(success, "{}", output);
+    let item_ids: Vec<&str> = output["file_list"].as_array().unwrap().iter().map(|file| file["item_id"].as_str().unwrap()).collect();
+    assert_eq!(item_ids.len(), 3, "{}", output);
+    assert_eq!(item_ids.iter().collect::<std::collections::HashSet<_>>().len(), 3, "{}", output);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn dedup_by_sha256_leaves_out_the_same_content_under_other_names() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &[])]);
+    for (name, contents) in [("a.jpg", "one"), ("b.jpg", "two"), ("copy of a.jpg", "one")] {
+        std::fs::write(cards[0].join(name), contents).unwrap();
+    }
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--dedup-by", "sha256"]);
+    assert!(success, "{}", output);
+    let mut names = file_names(&output);
+    names.sort();
+    assert_eq!(names.len(), 2, "{}", output);
+    assert!(names.contains(&"b.jpg".to_string()), "{}", output);
+    assert!(output["warnings"].to_string().contains("Left out 1 duplicate"), "{}", output);
+}
+
+#[test]
+fn cards_listed_together_are_merged_in_order_and_tagged_with_their_handler() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD), ("Generic-Single-File-Items", &[]),
+                                             ("Sony-ILCEM4-1", SONY_CARD), ("Generic-Single-File-Items", &[])]);
+    let card_args: Vec<&str> = cards.iter().map(|card| card.to_str().unwrap()).collect();
+    let args = [&["-c", config.to_str().unwrap(), "-L"][..], &card_args].concat();
+
+    let (output, success, stderr) = run_with_stderr(&args);
+    assert!(success, "{}", output);
+    let handlers: Vec<(String, &str)> = output["file_list"].as_array().unwrap().iter()
+        .map(|file| (file["file_path"].as_str().unwrap().to_string(), file["handler"].as_str().unwrap()))
+        .collect();
+    let expected: Vec<(String, &str)> = ["GOPR0002.GPR", "GOPR0003.JPG", "GX010001.MP4"].iter()
+        .map(|file| (cards[0].join(file).to_string_lossy().into_owned(), "GoPro-Hero-Generic-1"))
+        .chain([(cards[2].join("PRIVATE/M4ROOT/CLIP/C0001.MP4").to_string_lossy().into_owned(), "Sony-ILCEM4-1")])
+        .collect();
+    let mut sorted_gopro = handlers[..3].to_vec();
+    sorted_gopro.sort();
+    assert_eq!([&sorted_gopro[..], &handlers[3..]].concat(), expected);
+    let no_media: Vec<&str> = stderr.lines().filter(|line| line.contains("No media found")).collect();
+    assert_eq!(no_media.len(), 2, "{}", stderr);
+    assert!(no_media[0].contains(card_args[1]) && no_media[1].contains(card_args[3]), "{}", stderr);
+
+    for _ in 0..5 {
+        let (parallel_output, success, parallel_stderr) = run_with_stderr(&[&args[..], &["--parallel-cards"]].concat());
+        assert!(success, "{}", parallel_output);
+        assert_eq!(parallel_output, output);
+        assert_eq!(parallel_stderr, stderr);
+    }
+    for scan_concurrency in ["1", "2"] {
+        let (bounded_output, success, bounded_stderr) = run_with_stderr(&[&args[..], &["--parallel-cards", "--scan-concurrency", scan_concurrency]].concat());
+        assert!(success, "{}", bounded_output);
+        assert_eq!(bounded_output, output);
+        assert_eq!(bounded_stderr, stderr);
+    }
+    let zero = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface")).args([&args[..], &["--parallel-cards", "--scan-concurrency", "0"]].concat()).output().unwrap();
+    assert!(!zero.status.success());
+
+    // Files are only tagged when there is more than one card to tell apart
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", card_args[0]]);
+    assert!(success, "{}", output);
+    assert!(output["file_list"].as_array().unwrap().iter().all(|file| file.get("handler").is_none()), "{}", output);
+}
+
+/// Set the modification time of `file` to `seconds` after the Unix epoch
+fn set_capture_time(file: &std::path::Path, seconds: u64) {
+    std::fs::File::options().write(true).open(file).unwrap().set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+}
+
+#[test]
+fn sessions_of_cards_listed_together_are_grouped_across_the_cards() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["a.jpg", "b.jpg"]), ("Generic-Single-File-Items", &["c.jpg"])]);
+    set_capture_time(&cards[0].join("a.jpg"), 10 * 60 * 60);
+    set_capture_time(&cards[0].join("b.jpg"), 12 * 60 * 60);
+    set_capture_time(&cards[1].join("c.jpg"), 10 * 60 * 60 + 5 * 60);
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "-L", cards[1].to_str().unwrap(), "--group-by-session", "30"]);
+    assert!(success, "{}", output);
+    let sessions: Vec<Vec<&str>> = output["sessions"]["sessions"].as_array().unwrap().iter()
+        .map(|session| session["file_list"].as_array().unwrap().iter()
+            .map(|file| std::path::Path::new(file["file_path"].as_str().unwrap()).file_name().unwrap().to_str().unwrap())
+            .collect())
+        .collect();
+    assert_eq!(sessions, [vec!["a.jpg", "c.jpg"], vec!["b.jpg"]], "{}", output);
+}
+
+#[test]
+fn capture_order_of_cards_listed_together_merges_the_cards_by_capture_time() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", &["GOPR0001.JPG", "GOPR0002.JPG"]), ("GoPro-Hero-Generic-1", &["GOPR0005.JPG"])]);
+    set_capture_time(&cards[0].join("GOPR0001.JPG"), 100);
+    set_capture_time(&cards[0].join("GOPR0002.JPG"), 300);
+    set_capture_time(&cards[1].join("GOPR0005.JPG"), 200);
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "-L", cards[1].to_str().unwrap(), "--capture-order"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["GOPR0001.JPG", "GOPR0005.JPG", "GOPR0002.JPG"]);
+}
+
+#[test]
+fn relative_paths_rewrite_file_paths_and_metadata_files() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let config = config.to_str().unwrap();
+
+    let (output, success) = run(&["-c", config, "-l", cards[0].to_str().unwrap(), "--relative-paths"]);
+    assert!(success, "{}", output);
+    let thm = output["file_list"].as_array().unwrap().iter().find(|file| file["file_path"] == "DATA/CARD0001/GX010001.THM")
+        .unwrap_or_else(|| panic!("no relative path to the THM in {}", output));
+    assert_eq!(thm["metadata_file"], "DATA/CARD0001/GX010001.MP4");
+    assert!(file_paths(&output).iter().all(|path| path.starts_with("DATA/CARD0001/")), "{}", output);
+}
+
+#[cfg(unix)]
+#[test]
+fn stat_owner_reports_the_mode_and_owner_of_files() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let photo = cards[0].join("photo.jpg");
+    std::fs::set_permissions(&photo, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let metadata = std::fs::metadata(&photo).unwrap();
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--stat-owner"]);
+    assert!(success, "{}", output);
+    let file = &output["file_list"][0];
+    assert_eq!(file["mode"].as_u64(), Some(metadata.mode().into()));
+    assert_eq!(file["mode"].as_u64().unwrap() & 0o777, 0o640);
+    assert_eq!(file["uid"].as_u64(), Some(metadata.uid().into()));
+    assert_eq!(file["gid"].as_u64(), Some(metadata.gid().into()));
+
+    let (output, _) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(output["file_list"][0].get("mode").is_none(), "{}", output);
+}
+
+#[cfg(unix)]
+#[test]
+fn default_config_is_found_next_to_the_executable_through_symlinks_and_path() {
+    use std::process::Command;
+
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let bin = dir.path.join("bin");
+    let links = dir.path.join("links");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::create_dir_all(&links).unwrap();
+    std::fs::copy(env!("CARGO_BIN_EXE_media-interface"), bin.join("media-interface")).unwrap();
+    std::os::unix::fs::symlink(bin.join("media-interface"), links.join("mi")).unwrap();
+    // Next to the executable, with the source media paths made absolute as it's in another directory
+    edit_config(&config, |config| config["source_media"][0]["path"] = dir.path.join("media0").to_string_lossy().into_owned().into());
+    std::fs::rename(&config, bin.join("interface_config.json")).unwrap();
+
+    let card = cards[0].to_str().unwrap();
+    let through_symlink = Command::new(links.join("mi")).args(["-L", card]).current_dir(&dir.path).output().unwrap();
+    let through_path = Command::new("media-interface").args(["-L", card]).env("PATH", &bin).current_dir(&dir.path).output().unwrap();
+    for output in [through_symlink, through_path] {
+        let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(output.status.success(), "{}", value);
+        assert_eq!(file_names(&value), ["photo.jpg"]);
+    }
+}
+
+#[test]
+fn output_ends_in_one_newline_without_a_bom_or_carriage_returns_in_every_format() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+    let missing = dir.path.join("missing");
+
+    let runs: [&[&str]; 7] = [
+        &["-c", config, "-L", card],
+        &["-c", config, "-L", card, "--format", "csv"],
+        &["-c", config, "-L", card, "--format", "paths"],
+        &["-c", config, "-L", card, "--format", "dot"],
+        &["-c", config, "-l", card, "-L", card],
+        &["-c", config, "-L", missing.to_str().unwrap()],
+        &["--capabilities"],
+    ];
+    for args in runs {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface")).args(args).output().unwrap();
+        let stdout = &output.stdout;
+        assert!(!stdout.starts_with("\u{feff}".as_bytes()), "{:?}", args);
+        assert!(!stdout.contains(&b'\r'), "{:?}", args);
+        assert!(stdout.ends_with(b"\n") && !stdout.ends_with(b"\n\n"), "{:?}: {:?}", args, String::from_utf8_lossy(stdout));
+    }
+}
+
+#[test]
+fn capture_order_follows_the_file_counter_across_its_rollover() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[
+        ("GoPro-Hero-Generic-1", &["GOPR0001.JPG", "GX010002.MP4", "GX010002.THM", "GL010002.LRV", "GOPR9998.JPG", "GOPR9999.JPG"]),
+        ("Sony-ILCEM4-1", &["DCIM/100MSDCF/DSC00001.JPG", "DCIM/100MSDCF/DSC09999.JPG", "DCIM/101MSDCF/DSC00002.JPG"]),
+        ("Sony-ILCEM4-1", &["dcim/100msdcf/DSC00001.JPG", "dcim/100msdcf/DSC09999.JPG", "dcim/101msdcf/DSC00002.JPG",
+                            "private/m4root/clip/C0001.MP4", "private/m4root/clip/C0001M01.XML", "private/m4root/thmbnl/C0001T01.JPG",
+                            "private/m4root/clip/C9999.MP4", "private/m4root/clip/C9999M01.XML", "private/m4root/thmbnl/C9999T01.JPG"]),
+    ]);
+    let config = config.to_str().unwrap();
+
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap(), "--capture-order"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["GOPR9998.JPG", "GOPR9999.JPG", "GOPR0001.JPG", "GX010002.MP4"]);
+    let (output, _) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert_eq!(file_names(&output), ["GOPR0001.JPG", "GOPR9998.JPG", "GOPR9999.JPG", "GX010002.MP4"]);
+
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap(), "--capture-order"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["DSC09999.JPG", "DSC00001.JPG", "DSC00002.JPG"]);
+
+    // The same goes for cards with their folders in lower case
+    let (output, success) = run(&["-c", config, "-L", cards[2].to_str().unwrap(), "--capture-order"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["DSC09999.JPG", "DSC00001.JPG", "DSC00002.JPG", "C9999.MP4", "C0001.MP4"]);
+}
+
+#[test]
+fn capture_order_puts_numbers_past_the_counter_at_the_end() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("DJI-Drone-Generic-1", &["DCIM/100MEDIA/DJI_0002.MP4", "DCIM/100MEDIA/DJI_20001.MP4",
+                                                                        "DCIM/100MEDIA/DJI_4294967295.MP4", "DCIM/100MEDIA/DJI_0001.MP4"])]);
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--capture-order"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["DJI_0001.MP4", "DJI_0002.MP4", "DJI_20001.MP4", "DJI_4294967295.MP4"]);
+}
+
+#[test]
+fn hidden_files_are_only_scanned_with_include_hidden() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "._photo.jpg"]), ("Generic-Single-File-Items", &["photo.jpg", ".DS_Store"])]);
+    let config = config.to_str().unwrap();
+
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg"]);
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap(), "--include-hidden"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["._photo.jpg", "photo.jpg"]);
+
+    // Scanned, hidden files go through the unknown file policy like any other
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap(), "--include-hidden"]);
+    assert!(!success, "{}", output);
+    let (output, success) = run(&["-c", config, "-L", cards[1].to_str().unwrap(), "--include-hidden", "--unknown-file-policy", "ignore"]);
+    assert!(success, "{}", output);
+}
+
+#[test]
+fn base_path_substitution_replaces_only_matching_prefixes() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    // Written for where the media used to be
+    edit_config(&config, |config| config["source_media"][0]["path"] = "/old/mount/media0".into());
+    let config = config.to_str().unwrap();
+    let subst = format!("/old/mount={}", dir.path.to_str().unwrap());
+    let photo = cards[0].join("photo.jpg").to_string_lossy().into_owned();
+
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(!success, "{}", output);
+
+    // Input paths are substituted too, and a FROM that doesn't match leaves paths unchanged
+    for card in ["/old/mount/media0/DATA/CARD0001", cards[0].to_str().unwrap()] {
+        let (output, success) = run(&["-c", config, "-L", card, "--subst", "/unrelated=/elsewhere", "--subst", &subst]);
+        assert!(success, "{}: {}", card, output);
+        assert_eq!(file_paths(&output), [photo.as_str()], "{}", card);
+    }
+    // Whole path components are replaced, not just the leading characters
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap(), "--subst", "/old/moun=/elsewhere", "--subst", &subst]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), [photo.as_str()]);
+}
+
+#[test]
+fn cards_missing_the_directories_of_their_handler_are_rejected() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Sony-ILCEM4-1", &["MISC/notes.txt"]), ("Sony-ILCEM4-1", &["PRIVATE/M4ROOT/CLIP/C0001.MP4"]),
+                                             ("Generic-Single-File-Items", &["photo.jpg"])]);
+    let config = config.to_str().unwrap();
+
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(!success, "{}", output);
+    assert!(output.to_string().contains("doesn't look like a Sony-ILCEM4-1 card (missing DCIM or PRIVATE/M4ROOT or M4ROOT)"), "{}", output);
+    let (output, success) = run(&["-c", config, "-g", cards[0].join("MISC/notes.txt").to_str().unwrap()]);
+    assert!(!success, "{}", output);
+    assert!(output.to_string().contains("doesn't look like a Sony-ILCEM4-1 card"), "{}", output);
+
+    // Any one of the directories is enough, and handlers without any take every card
+    for card in &cards[1..] {
+        let (output, success) = run(&["-c", config, "-L", card.to_str().unwrap()]);
+        assert!(success, "{}", output);
+    }
+}
+
+#[test]
+fn paths_format_lists_one_file_path_per_line() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+    let paths_of = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface")).args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stdout));
+        String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect::<Vec<_>>()
+    };
+
+    for args in [["-L", card, "--include-related"], ["-l", card, "--include-related"]] {
+        let (output, success) = run(&[&["-c", config], &args[..]].concat());
+        assert!(success, "{}", output);
+        assert_eq!(paths_of(&[&["-c", config, "--format", "paths"], &args[..]].concat()), file_paths(&output), "{:?}", args);
+    }
+    let (output, _) = run(&["-c", config, "-L", card]);
+    let high_quality = paths_of(&["-c", config, "-L", card, "--format", "paths"]);
+    assert_eq!(high_quality, file_paths(&output));
+    assert!(high_quality.len() < paths_of(&["-c", config, "-L", card, "--format", "paths", "--include-related"]).len());
+
+    // Errors are still JSON, with a non-zero exit status
+    let missing = dir.path.join("missing");
+    let (output, success) = run(&["-c", config, "-L", missing.to_str().unwrap(), "--format", "paths"]);
+    assert!(!success, "{}", output);
+    assert_eq!(output["command_success"], false);
+}
+
+#[test]
+fn check_playable_flags_videos_without_a_moov_box() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["interrupted.mp4", "photo.jpg", "whole.mp4"])]);
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+    let mp4_box = |box_type: &[u8], len: u32| [&(8 + len).to_be_bytes()[..], box_type, &vec![0; len as usize]].concat();
+    std::fs::write(cards[0].join("interrupted.mp4"), [mp4_box(b"ftyp", 16), mp4_box(b"mdat", 64)].concat()).unwrap();
+    std::fs::write(cards[0].join("whole.mp4"), [mp4_box(b"ftyp", 16), mp4_box(b"mdat", 64), mp4_box(b"moov", 8)].concat()).unwrap();
+
+    let playable = |output: &serde_json::Value| output["file_list"].as_array().unwrap().iter()
+        .map(|item| item.get("playable").cloned()).collect::<Vec<_>>();
+    let (output, success) = run(&["-c", config, "-L", card]);
+    assert!(success, "{}", output);
+    assert_eq!(playable(&output), [None, None, None]);
+    let (output, success) = run(&["-c", config, "-L", card, "--check-playable"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["interrupted.mp4", "photo.jpg", "whole.mp4"]);
+    assert_eq!(playable(&output), [Some(false.into()), None, Some(true.into())]);
+}
+
+#[test]
+fn cache_is_reused_until_a_directory_read_for_it_changes() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "2024/clip.mp4"])]);
+    edit_config(&config, |config| config["source_media"][0]["recursive"] = true.into());
+    let cache = dir.path.join("cache.json");
+    let args = ["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--cache", cache.to_str().unwrap()];
+    // Change a directory without changing its modification time, so that only a listing that
+    // reads the card again shows the change
+    let behind_the_back_of_the_cache = |dir: &std::path::Path, change: &dyn Fn()| {
+        let modified = std::fs::metadata(dir).unwrap().modified().unwrap();
+        change();
+        std::fs::File::open(dir).unwrap().set_modified(modified).unwrap();
+    };
+
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg", "clip.mp4"]);
+    assert!(cache.exists());
+
+    let subdir = cards[0].join("2024");
+    behind_the_back_of_the_cache(&subdir, &|| std::fs::write(subdir.join("later.jpg"), "").unwrap());
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg", "clip.mp4"]);
+
+    // Any directory of the card being modified lists it again, subdirectories included
+    let modified = std::fs::metadata(&subdir).unwrap().modified().unwrap();
+    std::fs::File::open(&subdir).unwrap().set_modified(modified + std::time::Duration::from_secs(1)).unwrap();
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg", "clip.mp4", "later.jpg"]);
+
+    // Listing with other options isn't answered from the cache
+    behind_the_back_of_the_cache(&cards[0], &|| std::fs::write(cards[0].join(".hidden.jpg"), "").unwrap());
+    let (output, success) = run(&[&args[..], &["--include-hidden"]].concat());
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), [".hidden.jpg", "photo.jpg", "clip.mp4", "later.jpg"]);
+}
+
+#[test]
+fn prefer_picks_the_best_only_file_by_extension_over_the_quality_ranking() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Sony-ILCEM4-1", &["DCIM/100MSDCF/DSC00001.JPG", "DCIM/100MSDCF/DSC00001.ARW"])]);
+    let config = config.to_str().unwrap();
+    let photo = cards[0].join("DCIM/100MSDCF/DSC00001.JPG");
+    let best = |prefer: &[&str]| {
+        let (output, success) = run(&[&["-c", config, "-g", photo.to_str().unwrap(), "--best-only"], prefer].concat());
+        assert!(success, "{:?}: {}", prefer, output);
+        file_names(&output)
+    };
+
+    assert_eq!(best(&[]), ["DSC00001.ARW"]);
+    assert_eq!(best(&["--prefer", "jpg"]), ["DSC00001.JPG"]);
+    // The first preferred extension the item has a file of wins, falling back to the ranking
+    assert_eq!(best(&["--prefer", "heic,JPG,arw"]), ["DSC00001.JPG"]);
+    assert_eq!(best(&["--prefer", "heic,dng"]), ["DSC00001.ARW"]);
+}
+
+#[test]
+fn sort_related_orders_files_by_part_then_role() {
+    let dir = TestDir::new();
+    let gopro: Vec<String> = ["01", "02", "03"].iter()
+        .flat_map(|part| [format!("GX{part}0001.THM"), format!("GL{part}0001.LRV"), format!("GX{part}0001.MP4")]).collect();
+    let gopro: Vec<&str> = gopro.iter().map(String::as_str).chain(["GX010001.WAV"]).collect();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", &gopro),
+                                             ("Sony-ILCEM4-1", &["PRIVATE/M4ROOT/CLIP/C0001.MP4", "PRIVATE/M4ROOT/CLIP/C0001M01.XML",
+                                                                 "PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "PRIVATE/M4ROOT/SUB/C0001S03.MP4"])]);
+    let config = config.to_str().unwrap();
+    let sorted = |file: std::path::PathBuf| {
+        let (output, success) = run(&["-c", config, "-g", file.to_str().unwrap(), "--sort-related"]);
+        assert!(success, "{}", output);
+        file_names(&output)
+    };
+
+    assert_eq!(sorted(cards[0].join("GX020001.THM")), ["GX010001.MP4", "GL010001.LRV", "GX010001.THM", "GX010001.WAV",
+                                                       "GX020001.MP4", "GL020001.LRV", "GX020001.THM",
+                                                       "GX030001.MP4", "GL030001.LRV", "GX030001.THM"]);
+    // The same whichever file of the group it's asked for
+    for file in ["PRIVATE/M4ROOT/CLIP/C0001M01.XML", "PRIVATE/M4ROOT/SUB/C0001S03.MP4"] {
+        assert_eq!(sorted(cards[1].join(file)), ["C0001.MP4", "C0001T01.JPG", "C0001S03.MP4", "C0001M01.XML"], "{}", file);
+    }
+}
+
+#[test]
+fn since_file_lists_what_is_newer_than_the_marker_and_update_since_moves_it() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["a.jpg", "b.jpg", "c.jpg"])]);
+    let marker = dir.path.join("last_import");
+    let args = ["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--since-file", marker.to_str().unwrap(), "--update-since"];
+    let start = SystemTime::now() - Duration::from_secs(3600);
+    let set_modified = |name: &str, minutes: u64| {
+        std::fs::File::options().write(true).open(cards[0].join(name)).unwrap()
+            .set_modified(start + Duration::from_secs(minutes * 60)).unwrap();
+    };
+    set_modified("a.jpg", 1);
+    set_modified("b.jpg", 2);
+    set_modified("c.jpg", 3);
+
+    // Everything on the first run, with the marker created at the newest file
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["a.jpg", "b.jpg", "c.jpg"]);
+    let marker_time = || std::fs::metadata(&marker).unwrap().modified().unwrap();
+    assert_eq!(marker_time(), start + Duration::from_secs(180));
+
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), Vec::<String>::new());
+    assert!(output["warnings"][0].as_str().unwrap().contains("No media modified since"), "{}", output);
+
+    // Only moved by --update-since
+    set_modified("a.jpg", 4);
+    let (output, success) = run(&args[..args.len() - 1]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["a.jpg"]);
+    assert_eq!(marker_time(), start + Duration::from_secs(180));
+    let (output, success) = run(&args);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["a.jpg"]);
+    assert_eq!(marker_time(), start + Duration::from_secs(240));
+}
+
+#[test]
+fn metadata_files_that_dont_exist_are_left_out_with_a_warning() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Manifest-Driven-Generic-1", &["clip.mp4", "clip.xml", "other.mp4"])]);
+    std::fs::write(cards[0].join("manifest.json"), r#"{"data_type": "source_media_manifest", "items": [
+        {"item_type": "video", "metadata_file": "clip.xml", "parts": [{"files": [{"path": "clip.mp4", "file_type": "video"}]}]},
+        {"item_type": "video", "metadata_file": "other.xml", "parts": [{"files": [{"path": "other.mp4", "file_type": "video"}]}]}
+    ]}"#).unwrap();
+    let config = config.to_str().unwrap();
+
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    let metadata_files: Vec<_> = output["file_list"].as_array().unwrap().iter().map(|item| item.get("metadata_file").cloned()).collect();
+    assert_eq!(metadata_files, [Some(cards[0].join("clip.xml").to_string_lossy().into()), None]);
+    let warnings = output["warnings"].to_string();
+    assert!(warnings.contains("other.xml") && warnings.contains("doesn't exist, leaving it out") && !warnings.contains("clip.xml"), "{}", warnings);
+
+    // Known missing metadata files are still referenced
+    let errata = serde_json::json!({"data_type": "source_media_config", "errata": {"known_missing_files": ["DATA/CARD0001/other.xml"]}});
+    std::fs::write(dir.path.join("media0/interface_config.json"), errata.to_string()).unwrap();
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(output["file_list"][1]["metadata_file"], cards[0].join("other.xml").to_string_lossy().as_ref(), "{}", output);
+    assert_eq!(output.get("warnings"), None);
+}
+
+#[test]
+fn summary_per_roll_counts_the_items_and_bytes_of_every_roll() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Sony-ILCEM4-1", &["DCIM/100MSDCF/DSC00001.JPG", "DCIM/100MSDCF/DSC00001.ARW", "DCIM/100MSDCF/DSC00002.JPG",
+                                                             "DCIM/101MSDCF/DSC00003.JPG", "PRIVATE/M4ROOT/CLIP/C0001.MP4", "PRIVATE/M4ROOT/CLIP/C0001M01.XML",
+                                                             "PRIVATE/M4ROOT/THMBNL/C0001T01.JPG"])]);
+    for (file, size) in [("DCIM/100MSDCF/DSC00001.JPG", 10), ("DCIM/100MSDCF/DSC00001.ARW", 100), ("DCIM/100MSDCF/DSC00002.JPG", 20),
+                         ("DCIM/101MSDCF/DSC00003.JPG", 30), ("PRIVATE/M4ROOT/CLIP/C0001.MP4", 1000), ("PRIVATE/M4ROOT/CLIP/C0001M01.XML", 5),
+                         ("PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", 1)] {
+        std::fs::write(cards[0].join(file), vec![0; size]).unwrap();
+    }
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "--summary-per-roll", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(output["roll_summary"], serde_json::json!({
+        "100MSDCF": {"items": 2, "item_types": {"image": 2}, "total_size": 130},
+        "101MSDCF": {"items": 1, "item_types": {"image": 1}, "total_size": 30},
+        "CLIP": {"items": 1, "item_types": {"video": 1}, "total_size": 1006},
+    }));
+}
+
+#[test]
+fn summary_per_roll_goes_through_directory_overrides() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["notes.jpg", "gopro/GX010001.MP4", "gopro/GX010001.THM", "gopro/GL010001.LRV"])]);
+    std::fs::write(cards[0].join("gopro/.media_interface.json"), r#"{"handler": "GoPro-Hero-Generic-1"}"#).unwrap();
+    for (file, size) in [("notes.jpg", 10), ("gopro/GX010001.MP4", 1000), ("gopro/GX010001.THM", 1), ("gopro/GL010001.LRV", 100)] {
+        std::fs::write(cards[0].join(file), vec![0; size]).unwrap();
+    }
+
+    // The generic handler of the card would fail on the THM and LRV files
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "--summary-per-roll", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(output["roll_summary"], serde_json::json!({
+        "CARD0001": {"items": 1, "item_types": {"image": 1}, "total_size": 10},
+        "gopro": {"items": 1, "item_types": {"video": 1}, "total_size": 1101},
+    }));
+}
+
+#[test]
+fn relations_graph_in_dot_connects_every_item_to_its_files() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", GOPRO_CARD)]);
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface"))
+        .args(["-c", config.to_str().unwrap(), "--emit-relations-graph", cards[0].to_str().unwrap(), "--format", "dot"]).output().unwrap();
+    assert!(output.status.success());
+    let dot = String::from_utf8(output.stdout).unwrap();
+    assert!(dot.starts_with("digraph relations {\n") && dot.ends_with("}\n"), "{}", dot);
+
+    let item = format!("\"item:{}\"", cards[0].join("GX010001.MP4").display());
+    let file = |name: &str| format!("\"{}\"", cards[0].join(name).display());
+    assert!(dot.contains(&format!("\t{} [shape=box, label=\"video GX010001.MP4\"];\n", item)), "{}", dot);
+    assert!(dot.contains(&format!("\t{} [label=\"GX020001.MP4\"];\n", file("GX020001.MP4"))), "{}", dot);
+    for (name, label) in [("GX010001.MP4", "primary part 1"), ("GX020001.MP4", "part 2"), ("GX010001.THM", "preview part 1"), ("GL020001.LRV", "preview part 2")] {
+        assert!(dot.contains(&format!("\t{} -> {} [label=\"{}\"];\n", item, file(name), label)), "{} {}: {}", name, label, dot);
+    }
+    assert!(dot.contains(&format!("\t\"item:{}\" -> {} [label=\"primary\"];\n", cards[0].join("GOPR0002.GPR").display(), file("GOPR0002.JPG"))), "{}", dot);
+}
+
+#[test]
+fn card_walking_actions_go_through_directory_overrides() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["notes.jpg", "gopro/GX010001.MP4", "gopro/GX010001.THM", "gopro/GL010001.LRV"])]);
+    std::fs::write(cards[0].join("gopro/.media_interface.json"), r#"{"handler": "GoPro-Hero-Generic-1"}"#).unwrap();
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+
+    // The generic handler of the card would fail on the THM and LRV files
+    let (output, success) = run(&["-c", config, "--emit-relations-graph", card]);
+    assert!(success, "{}", output);
+    let item_ids: Vec<_> = output["file_list"].as_array().unwrap().iter().map(|file| file["item_id"].as_str().unwrap()).collect();
+    let clip = cards[0].join("gopro/GX010001.MP4").to_string_lossy().into_owned();
+    assert_eq!(item_ids, [cards[0].join("notes.jpg").to_string_lossy().as_ref(), &clip, &clip, &clip], "{}", output);
+
+    let manifest = dir.path.join("manifest.json");
+    let (output, success) = run(&["-c", config, "--export-manifest", manifest.to_str().unwrap(), "--manifest-card", card]);
+    assert!(success, "{}", output);
+    assert_eq!((&output["exported_manifest"]["items"], &output["exported_manifest"]["files"]), (&2.into(), &4.into()), "{}", output);
+
+    std::fs::remove_file(cards[0].join("gopro/GL010001.LRV")).unwrap();
+    let (output, success) = run(&["-c", config, "--count-expected-vs-present", card]);
+    assert!(success, "{}", output);
+    assert_eq!((&output["completeness"]["present"], &output["completeness"]["unexpected_missing"]), (&3.into(), &1.into()), "{}", output);
+
+    let (output, success) = run(&["-c", config, "--emit-errata-template", card]);
+    assert!(success, "{}", output);
+    assert_eq!(output["errata_template"]["errata"]["known_missing_files"], serde_json::json!(["DATA/CARD0001/gopro/GL010001.LRV"]), "{}", output);
+}
+
+#[test]
+fn count_expected_vs_present_counts_files_missing_without_being_listed_one_by_one() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", &["GX010001.MP4", "GX010001.THM", "GOPR0002.JPG"])]);
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+
+    // The clip is only missing its LRV, its MP4 and THM are still there
+    let (output, success) = run(&["-c", config, "--count-expected-vs-present", card]);
+    assert!(success, "{}", output);
+    let completeness = &output["completeness"];
+    assert_eq!((&completeness["expected"], &completeness["present"], &completeness["known_missing"], &completeness["unexpected_missing"]),
+               (&4.into(), &3.into(), &0.into(), &1.into()), "{}", output);
+    assert_eq!(completeness["percent"], 75.0);
+
+    // Errors other than missing files fail the action instead of being counted as missing
+    std::fs::write(cards[0].join("notes.txt"), "").unwrap();
+    let (output, success) = run(&["-c", config, "--count-expected-vs-present", card]);
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("notes.txt"), "{}", output);
+}
@@ -0,0 +1,221 @@
+/* common/mod.rs - Cards of every handler shared by the tests
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+// Every test crate including this module only uses some of it
+#![allow(dead_code)]
+
+use media_interface::fs_provider::{FsEntry, FsMetadata, FsProvider, MemoryFs};
+use media_interface::HandlerOptions;
+use serde_json::Value;
+use std::cell::Cell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const LOCATION: &str = "/media";
+pub const CARD: &str = "/media/card";
+
+/// The manifest of the card of the manifest driven handler
+pub const MANIFEST: &str = r#"{"data_type": "source_media_manifest", "items": [
+    {"item_type": "video", "parts": [{"files": [{"path": "clip.mp4", "file_type": "video"}, {"path": "clip.jpg", "file_type": "image-preview"}]}]},
+    {"item_type": "image", "parts": [{"files": [{"path": "photo.jpg", "file_type": "image"}]}]}
+]}"#;
+
+pub struct Fixture {
+    pub handler: &'static str,
+    /// The files of the card, relative to it
+    pub files: &'static [&'static str],
+    /// A file named like one of the device's that isn't on the card
+    pub missing: &'static str,
+}
+
+impl Fixture {
+    /// The card with empty files, but for the manifest of the manifest driven handler
+    pub fn fs(&self) -> MemoryFs {
+        let mut fs = MemoryFs::default();
+        for file in self.files {
+            fs.add_file(&Path::new(CARD).join(file), 0, None);
+        }
+        if self.files.contains(&"manifest.json") {
+            fs.add_file_with_contents(&Path::new(CARD).join("manifest.json"), MANIFEST.as_bytes().to_vec(), None);
+        }
+        fs
+    }
+}
+
+/// A card of every handler, with the files of a few items on it
+pub const FIXTURES: &[Fixture] = &[
+    Fixture{ handler: "GoPro-Hero-Generic-1", files: &["GX010001.MP4", "GX010001.THM", "GL010001.LRV", "GX010001.JPG", "GX020001.MP4", "GX020001.THM", "GL020001.LRV",
+                                                      "GOPR0002.JPG", "GOPR0002.GPR", "GOPR0003.JPG"], missing: "GX010009.MP4" },
+    Fixture{ handler: "Sony-ILCEM4-1", files: &["DCIM/100MSDCF/DSC00001.JPG", "DCIM/100MSDCF/DSC00001.ARW", "DCIM/100MSDCF/DSC00002.HIF",
+                                               "PRIVATE/M4ROOT/CLIP/C0001.MP4", "PRIVATE/M4ROOT/CLIP/C0001M01.XML", "PRIVATE/M4ROOT/THMBNL/C0001T01.JPG",
+                                               "PRIVATE/M4ROOT/SUB/C0001S03.MP4"], missing: "DCIM/100MSDCF/DSC00009.JPG" },
+    Fixture{ handler: "Generic-Single-File-Items", files: &["photo.jpg", "clip.mp4", "memo.wav"], missing: "other.jpg" },
+    Fixture{ handler: "GNSS-Tracker-Generic", files: &["track.gpx", "track.kml", "other.txt"], missing: "missing.gpx" },
+    Fixture{ handler: "Manifest-Driven-Generic-1", files: &["manifest.json", "clip.mp4", "clip.jpg", "photo.jpg"], missing: "missing.jpg" },
+    Fixture{ handler: "Ricoh-Theta-Generic-1", files: &["DCIM/100RICOH/R0010001.JPG", "DCIM/100RICOH/R0010002.MP4"], missing: "DCIM/100RICOH/R0010009.JPG" },
+    Fixture{ handler: "Android-Motion-Photo-Generic-1", files: &["PXL_0001.jpg", "PXL_0002.mp4"], missing: "PXL_0009.jpg" },
+    Fixture{ handler: "DJI-Drone-Generic-1", files: &["DCIM/100MEDIA/DJI_0001.MP4", "DCIM/100MEDIA/DJI_0001.LRF", "DCIM/100MEDIA/DJI_0001.SRT",
+                                                     "DCIM/100MEDIA/DJI_0002.JPG", "DCIM/100MEDIA/DJI_0002.DNG"], missing: "DCIM/100MEDIA/DJI_0009.JPG" },
+    Fixture{ handler: "Olympus-Generic-1", files: &["DCIM/100OLYMP/PA140001.ORF", "DCIM/100OLYMP/PA140001.JPG", "DCIM/100OLYMP/PA140002.MOV"],
+             missing: "DCIM/100OLYMP/PA140009.ORF" },
+    Fixture{ handler: "Stereo-Pair-Generic-1", files: &["A_L.JPG", "A_R.JPG", "B.MPO"], missing: "C.MPO" },
+];
+
+pub fn options_for(fs: impl FsProvider + 'static) -> HandlerOptions {
+    HandlerOptions::new(PathBuf::from(CARD), Box::new(fs))
+}
+
+/// A directory of its own under the temporary directory, removed along with everything in it
+/// once dropped
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new() -> TestDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!("media-interface-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)));
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir{ path }
+    }
+
+    /// A config with a source media for each of `cards`, the handler and the files of a card of
+    /// it. Each source media is named after its index, with its card in DATA/CARD0001. Returns
+    /// the config file and the card directories
+    pub fn source_media(&self, cards: &[(&str, &[&str])]) -> (PathBuf, Vec<PathBuf>) {
+        let mut source_media = Vec::new();
+        let mut card_dirs = Vec::new();
+        for (index, (handler, files)) in cards.iter().enumerate() {
+            let card = self.path.join(format!("media{index}/DATA/CARD0001"));
+            for file in *files {
+                let file = card.join(file);
+                std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+                std::fs::write(&file, "").unwrap();
+            }
+            std::fs::create_dir_all(&card).unwrap();
+            source_media.push(serde_json::json!({"path": format!("media{index}"), "card_subdir": "DATA", "handler": handler}));
+            card_dirs.push(card);
+        }
+        let config = self.path.join("config.json");
+        std::fs::write(&config, serde_json::json!({"data_type": "source_media_config", "source_media": source_media}).to_string()).unwrap();
+        (config, card_dirs)
+    }
+}
+
+/// Change the config written by TestDir::source_media
+pub fn edit_config(config: &Path, edit: impl FnOnce(&mut Value)) {
+    let mut value: Value = serde_json::from_str(&std::fs::read_to_string(config).unwrap()).unwrap();
+    edit(&mut value);
+    std::fs::write(config, value.to_string()).unwrap();
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Run the executable with `args`, returning its output parsed as JSON and whether it succeeded
+pub fn run(args: &[&str]) -> (Value, bool) {
+    let (value, success, _) = run_with_stderr(args);
+    (value, success)
+}
+
+/// Like run, also returning what was written to stderr
+pub fn run_with_stderr(args: &[&str]) -> (Value, bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_media-interface")).args(args).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let value = serde_json::from_str(&stdout).unwrap_or_else(|error| panic!("output of {:?} is not JSON ({}): {}", args, error, stdout));
+    (value, output.status.success(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+/// The paths of the files of a file list output
+pub fn file_paths(output: &Value) -> Vec<String> {
+    output["file_list"].as_array().unwrap_or_else(|| panic!("no file list in {}", output)).iter()
+        .map(|file| file["file_path"].as_str().unwrap().to_string())
+        .collect()
+}
+
+/// The names of the files of a file list output
+pub fn file_names(output: &Value) -> Vec<String> {
+    file_paths(output).iter().map(|path| Path::new(path).file_name().unwrap().to_string_lossy().into_owned()).collect()
+}
+
+/// How many times each kind of filesystem operation was asked of a CountingFs, the ones that
+/// each cost a system call on a real filesystem
+#[derive(Default)]
+pub struct FsCalls {
+    pub read_dir: Cell<usize>,
+    /// Metadata and existence checks
+    pub metadata: Cell<usize>,
+    pub read: Cell<usize>,
+}
+
+impl FsCalls {
+    pub fn total(&self) -> usize {
+        self.read_dir.get() + self.metadata.get() + self.read.get()
+    }
+}
+
+/// Passes everything through to another provider, counting the calls
+pub struct CountingFs<F: FsProvider> {
+    pub inner: F,
+    pub calls: Rc<FsCalls>,
+}
+
+impl<F: FsProvider> CountingFs<F> {
+    pub fn new(inner: F) -> (CountingFs<F>, Rc<FsCalls>) {
+        let calls = Rc::new(FsCalls::default());
+        (CountingFs{ inner, calls: calls.clone() }, calls)
+    }
+}
+
+fn count(counter: &Cell<usize>) {
+    counter.set(counter.get() + 1);
+}
+
+impl<F: FsProvider> FsProvider for CountingFs<F> {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        count(&self.calls.read_dir);
+        self.inner.read_dir(dir)
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        count(&self.calls.metadata);
+        self.inner.metadata(path)
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        count(&self.calls.read);
+        self.inner.read(path)
+    }
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        count(&self.calls.read);
+        self.inner.read_range(path, offset, len)
+    }
+    fn exists(&self, path: &Path) -> bool {
+        count(&self.calls.metadata);
+        self.inner.exists(path)
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        count(&self.calls.metadata);
+        self.inner.canonicalize(path)
+    }
+}
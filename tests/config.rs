@@ -0,0 +1,450 @@
+/* config.rs - Checks how the options of config files change what is listed
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[test]
+fn excluded_dirs_are_not_scanned() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "scratch/notes.xyz", "2024/03/clip.mp4", "2024/old/notes.xyz"])]);
+    edit_config(&config, |config| config["source_media"][0]["recursive"] = true.into());
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+
+    let (_, success) = run(&["-c", config, "-L", card]);
+    assert!(!success, "the unrecognised files are scanned without excluding their folders");
+
+    // By name anywhere under the card, and by path relative to it
+    edit_config(Path::new(config), |config| config["source_media"][0]["exclude_dirs"] = serde_json::json!(["scratch", "2024/old"]));
+    let (output, success) = run(&["-c", config, "-L", card]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg", "clip.mp4"]);
+}
+
+#[test]
+fn card_subdirs_outside_of_the_source_media_are_rejected() {
+    let dir = TestDir::new();
+    let (config, _) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"]), ("Generic-Single-File-Items", &["photo.jpg"])]);
+
+    for card_subdir in ["../media1/DATA", "DATA/../../media1"] {
+        edit_config(&config, |config| config["source_media"][0]["card_subdir"] = card_subdir.into());
+        let (output, success) = run(&["-c", config.to_str().unwrap(), "--dump-resolved-config"]);
+        assert!(!success, "{}: {}", card_subdir, output);
+        assert!(output["error_string"].as_str().unwrap().contains("resolves outside"), "{}: {}", card_subdir, output);
+    }
+
+    // Going up is fine while it stays within the source media
+    edit_config(&config, |config| config["source_media"][0]["card_subdir"] = "DATA/CARD0001/..".into());
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "--dump-resolved-config"]);
+    assert!(success, "{}", output);
+}
+
+/// The directory the cards of every source media were resolved to
+fn resolved_locations(config: &Path) -> Vec<String> {
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "--dump-resolved-config"]);
+    assert!(success, "{}", output);
+    output["resolved_config"]["source_media"].as_array().unwrap().iter().map(|entry| entry["location"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn relative_source_media_paths_are_resolved_against_the_media_base() {
+    let dir = TestDir::new();
+    let (config, _) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let card_dir = |base: &Path| std::fs::canonicalize(base.join("media0/DATA")).unwrap().to_string_lossy().into_owned();
+    assert_eq!(resolved_locations(&config), [card_dir(&dir.path)]);
+
+    // The source media moved under base/, relative to the config's directory or absolute
+    let base = dir.path.join("base");
+    std::fs::create_dir_all(&base).unwrap();
+    std::fs::rename(dir.path.join("media0"), base.join("media0")).unwrap();
+    for media_base in ["base".to_string(), base.to_string_lossy().into_owned()] {
+        edit_config(&config, |config| config["media_base"] = media_base.clone().into());
+        assert_eq!(resolved_locations(&config), [card_dir(&base)], "{}", media_base);
+    }
+
+    // Absolute source media paths are used as they are
+    edit_config(&config, |config| {
+        config["media_base"] = "elsewhere".into();
+        config["source_media"][0]["path"] = base.join("media0").to_string_lossy().into_owned().into();
+    });
+    assert_eq!(resolved_locations(&config), [card_dir(&base)]);
+}
+
+#[test]
+fn files_with_ignored_extensions_are_skipped() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Sony-ILCEM4-1", &["DCIM/100MSDCF/DSC00001.JPG", "DCIM/100MSDCF/DSC00001.cpi"])]);
+    let (config_arg, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+
+    let (output, success) = run(&["-c", config_arg, "-L", card]);
+    assert!(!success, "{}", output);
+
+    // Matched ignoring case
+    edit_config(&config, |config| config["source_media"][0]["ignore_extensions"] = serde_json::json!(["CPI"]));
+    let (output, success) = run(&["-c", config_arg, "-L", card]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["DSC00001.JPG"]);
+}
+
+/// Write the per source media config of the source media at `root` with `known_missing_files`
+fn write_errata(root: &Path, known_missing_files: &[&str]) {
+    let errata = serde_json::json!({"data_type": "source_media_config", "errata": {"known_missing_files": known_missing_files}});
+    std::fs::write(root.join("interface_config.json"), errata.to_string()).unwrap();
+}
+
+#[test]
+fn known_missing_files_match_the_paths_handlers_expect() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("GoPro-Hero-Generic-1", &["GX010001.MP4", "GX010001.THM"])]);
+    let (config, mp4) = (config.to_str().unwrap(), cards[0].join("GX010001.MP4"));
+    let root = dir.path.join("media0");
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("DATA"), root.join("LINK")).unwrap();
+
+    let (output, success) = run(&["-c", config, "-g", mp4.to_str().unwrap()]);
+    assert!(!success, "the LRV is missing without an errata entry: {}", output);
+
+    let mut entries = vec!["DATA/CARD0001/GL010001.LRV", "./DATA/../DATA/CARD0001/GL010001.LRV"];
+    if cfg!(unix) {
+        entries.push("LINK/CARD0001/GL010001.LRV");
+    }
+    for entry in entries {
+        // Along with an entry in a directory that doesn't exist, which can't match anything
+        write_errata(&root, &[entry, "DATA/CARD0002/GL010001.LRV"]);
+        let (output, success) = run(&["-c", config, "-g", mp4.to_str().unwrap()]);
+        assert!(success, "{}: {}", entry, output);
+        assert_eq!(file_names(&output), ["GX010001.MP4", "GX010001.THM"], "{}", entry);
+
+        let (output, success) = run(&["-c", config, "--dump-resolved-config"]);
+        assert!(success, "{}", output);
+        let expected = std::fs::canonicalize(&cards[0]).unwrap().join("GL010001.LRV");
+        assert_eq!(output["resolved_config"]["source_media"][0]["known_missing_files"][0], expected.to_str().unwrap(), "{}", entry);
+    }
+}
+
+#[test]
+fn the_deepest_of_nested_source_media_handles_the_input() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Sony-ILCEM4-1", &["DCIM/100MSDCF/DSC00001.JPG"])]);
+    // The outer one first, so it would win going by the order of the config
+    edit_config(&config, |config| {
+        let nested = config["source_media"][0].clone();
+        config["source_media"] = serde_json::json!([{"path": ".", "card_subdir": "media0", "handler": "Generic-Single-File-Items"}, nested]);
+    });
+
+    let (output, success, stderr) = run_with_stderr(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["DSC00001.JPG"]);
+    let warnings = output["warnings"].as_array().unwrap_or_else(|| panic!("no warning about the nested source media: {}", output));
+    assert!(warnings.iter().any(|warning| warning.as_str().unwrap().contains("using the deepest one") && warning.as_str().unwrap().contains("Sony-ILCEM4-1")), "{}", output);
+    assert!(stderr.contains("warning"), "{}", stderr);
+}
+
+#[test]
+fn cards_are_directly_in_the_source_media_without_card_subdir() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "clip.mp4"])]);
+    edit_config(&config, |config| {
+        config["source_media"][0]["path"] = "media0/DATA".into();
+        config["source_media"][0].as_object_mut().unwrap().remove("card_subdir");
+    });
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["clip.mp4", "photo.jpg"]);
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-g", cards[0].join("photo.jpg").to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg"]);
+
+    // Only directories in the source media are cards
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", dir.path.join("media0").to_str().unwrap()]);
+    assert!(!success, "{}", output);
+}
+
+#[test]
+fn recursive_source_media_list_files_in_year_and_month_folders() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "2024/03/clip.mp4", "2024/03/IMG_0001.jpg"])]);
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+    let nested = cards[0].join("2024/03/IMG_0001.jpg").to_string_lossy().into_owned();
+
+    // Only the top level by default, where the year folder is an unrecognised file
+    let (output, success) = run(&["-c", config, "-L", card]);
+    assert!(!success, "{}", output);
+    let (output, success) = run(&["-c", config, "-L", card, "--unknown-file-policy", "ignore"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output), ["photo.jpg"]);
+
+    edit_config(Path::new(config), |config| config["source_media"][0]["recursive"] = true.into());
+    let (output, success) = run(&["-c", config, "-L", card]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), [cards[0].join("photo.jpg").to_string_lossy().into_owned(),
+                                     cards[0].join("2024/03/IMG_0001.jpg").to_string_lossy().into_owned(),
+                                     cards[0].join("2024/03/clip.mp4").to_string_lossy().into_owned()]);
+    let (output, success) = run(&["-c", config, "-g", &nested]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), [nested.as_str()]);
+}
+
+#[test]
+#[cfg(unix)]
+fn recursive_listing_doesnt_follow_symlinks_to_directories() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "sub/clip.mp4"])]);
+    edit_config(&config, |config| config["source_media"][0]["recursive"] = true.into());
+    std::os::unix::fs::symlink("..", cards[0].join("sub/loop")).unwrap();
+    std::os::unix::fs::symlink(dir.path.join("media0"), cards[0].join("outside")).unwrap();
+    let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+
+    // The links are files like any other, unrecognised ones
+    let (output, success) = run(&["-c", config, "-L", card]);
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("Unexpected file"), "{}", output);
+    let (output, success) = run(&["-c", config, "-L", card, "--unknown-file-policy", "ignore"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), [cards[0].join("photo.jpg").to_string_lossy().into_owned(), cards[0].join("sub/clip.mp4").to_string_lossy().into_owned()]);
+}
+
+/// Write `contents` to `config.<extension>` in `dir`, returning its path
+#[cfg(all(feature = "yaml", feature = "toml"))]
+fn write_config_as(dir: &TestDir, extension: &str, contents: &str) -> String {
+    let config = dir.path.join(format!("config.{extension}"));
+    std::fs::write(&config, contents).unwrap();
+    config.to_string_lossy().into_owned()
+}
+
+#[test]
+#[cfg(all(feature = "yaml", feature = "toml"))]
+fn configs_in_every_format_are_read_the_same() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "2024/03/clip.mp4", "scratch/notes.xyz"])]);
+    edit_config(&config, |config| {
+        config["source_media"][0]["recursive"] = true.into();
+        config["source_media"][0]["exclude_dirs"] = serde_json::json!(["scratch"]);
+    });
+    let yaml = write_config_as(&dir, "yaml", "# Hand written, with comments\n\
+        data_type: source_media_config\n\
+        source_media:\n\
+        \x20 - path: media0\n\
+        \x20   card_subdir: DATA\n\
+        \x20   handler: Generic-Single-File-Items\n\
+        \x20   recursive: true\n\
+        \x20   exclude_dirs: [scratch]\n");
+    let yml = write_config_as(&dir, "yml", &std::fs::read_to_string(&yaml).unwrap());
+    let toml = write_config_as(&dir, "toml", "# Hand written, with comments\n\
+        data_type = \"source_media_config\"\n\
+        [[source_media]]\n\
+        path = \"media0\"\n\
+        card_subdir = \"DATA\"\n\
+        handler = \"Generic-Single-File-Items\"\n\
+        recursive = true\n\
+        exclude_dirs = [\"scratch\"]\n");
+
+    let card = cards[0].to_str().unwrap();
+    let (expected, success) = run(&["-c", config.to_str().unwrap(), "-L", card]);
+    assert!(success, "{}", expected);
+    assert_eq!(file_names(&expected), ["photo.jpg", "clip.mp4"]);
+    for config in [&yaml, &yml, &toml] {
+        let (output, success) = run(&["-c", config, "-L", card]);
+        assert!(success, "{}: {}", config, output);
+        assert_eq!(output, expected, "{}", config);
+    }
+
+    // The data type is checked whatever the format
+    let yaml = write_config_as(&dir, "yaml", "data_type: source_media_manifest\nsource_media: []\n");
+    let toml = write_config_as(&dir, "toml", "data_type = \"source_media_manifest\"\nsource_media = []\n");
+    for config in [&yaml, &toml] {
+        let (output, success) = run(&["-c", config, "-L", card]);
+        assert!(!success, "{}: {}", config, output);
+        assert!(output["error_string"].as_str().unwrap().contains("source_media_manifest"), "{}: {}", config, output);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "yaml"))]
+fn yaml_configs_without_the_yaml_feature_are_an_error() {
+    let dir = TestDir::new();
+    let (_, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let config = dir.path.join("config.yaml");
+    std::fs::write(&config, "data_type: source_media_config\n").unwrap();
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("yaml feature"), "{}", output);
+}
+
+#[test]
+fn configs_without_a_directory_part_are_relative_to_the_current_dir() {
+    let dir = TestDir::new();
+    let (_, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let run_in_dir = |config: &str| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface")).current_dir(&dir.path)
+            .args(["-c", config, "-L", "media0/DATA/CARD0001"]).output().unwrap();
+        (serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap(), output.status.success())
+    };
+
+    for config in ["config.json", "./config.json", "media0/../config.json"] {
+        let (output, success) = run_in_dir(config);
+        assert!(success, "{}: {}", config, output);
+        assert_eq!(file_paths(&output), [cards[0].join("photo.jpg").to_string_lossy().into_owned()], "{}", config);
+    }
+    let (output, success) = run_in_dir("/");
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("has no parent directory"), "{}", output);
+}
+
+#[test]
+fn tags_files_add_tags_and_captions_to_matching_items() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "2024/03/clip.mp4", "2024/03/photo.jpg", "untagged.jpg"])]);
+    edit_config(&config, |config| {
+        config["source_media"][0]["recursive"] = true.into();
+        config["source_media"][0]["tags_file"] = "tags.json".into();
+    });
+    // By file name for every photo.jpg, by relative path for the clip, and one for a file that isn't there
+    std::fs::write(cards[0].join("tags.json"), r#"{
+        "photo.jpg": {"tags": ["family", "beach"]},
+        "2024/03/clip.mp4": {"caption": "Sunset"},
+        "2024/03/photo.jpg": {"tags": ["sunset"], "caption": "Later on"},
+        "gone.jpg": {"tags": ["lost"]}
+    }"#).unwrap();
+
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    let notes: Vec<_> = output["file_list"].as_array().unwrap().iter()
+        .map(|item| (item["basename"].as_str().unwrap(), item.get("tags").cloned(), item.get("caption").cloned())).collect();
+    assert_eq!(notes, [
+        ("photo.jpg", Some(serde_json::json!(["family", "beach"])), None),
+        ("untagged.jpg", None, None),
+        ("clip.mp4", None, Some("Sunset".into())),
+        ("photo.jpg", Some(serde_json::json!(["sunset"])), Some("Later on".into())),
+    ]);
+}
+
+#[test]
+fn home_is_expanded_in_config_paths_and_abbreviated_with_home_relative() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"]), ("Generic-Single-File-Items", &["clip.mp4"])]);
+    edit_config(&config, |config| {
+        config["source_media"][0]["path"] = "~/media0".into();
+        config["source_media"][1]["path"] = "$HOME/media1".into();
+        config["source_media"][1]["card_subdir"] = "~/media1/DATA".into();
+    });
+    let run_with_home = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_media-interface")).env("HOME", &dir.path)
+            .args([&["-c", config.to_str().unwrap()], args].concat()).output().unwrap();
+        (serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap(), output.status.success())
+    };
+
+    let (output, success) = run_with_home(&["-L", cards[0].to_str().unwrap(), "-L", cards[1].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), [cards[0].join("photo.jpg").to_string_lossy().into_owned(), cards[1].join("clip.mp4").to_string_lossy().into_owned()]);
+    let (output, success) = run_with_home(&["-L", cards[0].to_str().unwrap(), "-L", cards[1].to_str().unwrap(), "--home-relative"]);
+    assert!(success, "{}", output);
+    assert_eq!(file_paths(&output), ["~/media0/DATA/CARD0001/photo.jpg", "~/media1/DATA/CARD0001/clip.mp4"]);
+
+    // Only whole leading components are expanded
+    edit_config(&config, |config| config["source_media"][0]["path"] = "~media0".into());
+    let (output, success) = run_with_home(&["-L", cards[0].to_str().unwrap()]);
+    assert!(!success, "{}", output);
+}
+
+#[test]
+fn dump_resolved_config_outputs_every_source_media_as_resolved() {
+    let dir = TestDir::new();
+    let (config, _) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"]), ("No-Such-Handler", &[])]);
+    edit_config(&config, |config| {
+        config["source_media"][0]["recursive"] = true.into();
+        config["source_media"][0]["exclude_dirs"] = serde_json::json!(["scratch"]);
+    });
+    write_errata(&dir.path.join("media0"), &["DATA/CARD0001/gone.jpg"]);
+    // Through a path that canonicalizing resolves
+    let config = dir.path.join("media0/../config.json");
+
+    // Without any list or get action
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "--dump-resolved-config"]);
+    assert!(success, "{}", output);
+    let path_of = |relative: &str| dir.path.join(relative).to_string_lossy().into_owned();
+    assert_eq!(output["resolved_config"]["config_file"], path_of("config.json"));
+    assert_eq!(output["resolved_config"]["source_media"], serde_json::json!([
+        {"handler": "Generic-Single-File-Items", "location": path_of("media0/DATA"), "root": path_of("media0"),
+         "per_source_config": path_of("media0/interface_config.json"), "exclude_dirs": ["scratch"], "ignore_extensions": [],
+         "recursive": true, "known_missing_files": [path_of("media0/DATA/CARD0001/gone.jpg")]},
+        {"handler": "No-Such-Handler", "location": path_of("media1/DATA"), "root": path_of("media1"),
+         "per_source_config": path_of("media1/interface_config.json"), "exclude_dirs": [], "ignore_extensions": [],
+         "recursive": false, "known_missing_files": []},
+    ]));
+    assert_eq!(output["warnings"], serde_json::json!(["Unknown handler ID 'No-Such-Handler'"]));
+}
+
+#[test]
+fn config_versions_outside_of_the_supported_range_are_rejected() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let run_with_version = |version: Option<u32>| {
+        edit_config(&config, |config| match version {
+            Some(version) => config["config_version"] = version.into(),
+            None => { config.as_object_mut().unwrap().remove("config_version"); }
+        });
+        run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()])
+    };
+
+    for version in [None, Some(1)] {
+        let (output, success) = run_with_version(version);
+        assert!(success, "{:?}: {}", version, output);
+    }
+    for version in [0, 2] {
+        let (output, success) = run_with_version(Some(version));
+        assert!(!success, "{}: {}", version, output);
+        let error = output["error_string"].as_str().unwrap();
+        assert!(error.contains(&format!("Config version {} of", version)) && error.contains("not supported by this binary (supports 1..=1)"), "{}", error);
+    }
+
+    // Per source media config files too
+    run_with_version(None);
+    std::fs::write(dir.path.join("media0/interface_config.json"), r#"{"data_type": "source_media_config", "config_version": 2}"#).unwrap();
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()]);
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("interface_config.json\" not supported"), "{}", output);
+}
+
+#[test]
+fn directory_overrides_pick_the_handler_of_their_subtree() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["notes.jpg", "gopro/GX010001.MP4", "gopro/GX010001.THM", "gopro/GL010001.LRV",
+                                                                          "tracker/track.gpx", "tracker/track.kml"])]);
+    std::fs::write(cards[0].join("gopro/.media_interface.json"), r#"{"handler": "GoPro-Hero-Generic-1"}"#).unwrap();
+    std::fs::write(cards[0].join("tracker/.media_interface.json"), r#"{"handler": "GNSS-Tracker-Generic"}"#).unwrap();
+    let config = config.to_str().unwrap();
+
+    // The generic handler of the card would fail on the THM, LRV and GPX files
+    let (output, success) = run(&["-c", config, "-L", cards[0].to_str().unwrap()]);
+    assert!(success, "{}", output);
+    let items: BTreeMap<_, _> = output["file_list"].as_array().unwrap().iter()
+        .map(|item| (item["basename"].as_str().unwrap().to_string(), item["item_type"].as_str().unwrap().to_string())).collect();
+    assert_eq!(items.keys().collect::<Vec<_>>(), ["GX010001.MP4", "notes.jpg", "track.gpx"]);
+    assert_eq!(items["GX010001.MP4"], "video");
+
+    // Files inside an override are asked about through its handler
+    let (output, success) = run(&["-c", config, "-g", cards[0].join("gopro/GX010001.MP4").to_str().unwrap()]);
+    assert!(success, "{}", output);
+    assert_eq!(file_names(&output).len(), 3, "{}", output);
+}
@@ -0,0 +1,139 @@
+/* get_related.rs - Checks every handler's get_related keeps to the contract of the trait
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::fs_provider::MemoryFs;
+use media_interface::{get_handler, handler_factories, UnknownFilePolicy};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[test]
+fn every_handler_has_a_fixture() {
+    for factory in handler_factories() {
+        let name = factory().name();
+        assert!(FIXTURES.iter().any(|fixture| fixture.handler == name), "no fixture for {}", name);
+    }
+}
+
+#[test]
+fn related_files_include_the_queried_file() {
+    for fixture in FIXTURES {
+        let handler_name = fixture.handler;
+        let handler = get_handler(handler_name).unwrap();
+        let options = options_for(fixture.fs());
+        let (location, card) = (Path::new(LOCATION), Path::new(CARD));
+
+        let mut listed = handler.list_thumbnail(location, card, &options).unwrap();
+        listed.extend(handler.list_high_quality(location, card, &options).unwrap());
+        assert!(!listed.is_empty(), "{} listed nothing", handler_name);
+
+        for item in listed {
+            let related = handler.get_related(location, Path::new(&item.file_path), &options)
+                .unwrap_or_else(|error| panic!("{} get_related of {}: {:#}", handler_name, item.file_path, error));
+            for file in &related {
+                let again = handler.get_related(location, Path::new(&file.file_path), &options)
+                    .unwrap_or_else(|error| panic!("{} get_related of {}: {:#}", handler_name, file.file_path, error));
+                assert!(again.iter().any(|other| other.file_path == file.file_path), "{} left {} out of its own related files", handler_name, file.file_path);
+            }
+        }
+    }
+}
+
+#[test]
+fn related_files_of_a_missing_file_are_an_error() {
+    for fixture in FIXTURES {
+        let handler = get_handler(fixture.handler).unwrap();
+        let file = Path::new(CARD).join(fixture.missing);
+        assert!(handler.get_related(Path::new(LOCATION), &file, &options_for(fixture.fs())).is_err(), "{} get_related of missing {:?}", fixture.handler, file);
+    }
+}
+
+#[test]
+fn gopro_clips_missing_files_are_an_error_instead_of_empty() {
+    let handler = get_handler("GoPro-Hero-Generic-1").unwrap();
+    let card = Path::new(CARD);
+
+    // The MP4 picked up a trailing space and the LRV is gone
+    let options = options_for(MemoryFs::with_files(&["/media/card/GX010001.mp4 ", "/media/card/GX010001.THM"]));
+    assert!(handler.get_related(Path::new(LOCATION), &card.join("GX010001.THM"), &options).is_err());
+    assert!(handler.get_related(Path::new(LOCATION), &card.join("GX010001.mp4 "), &options).is_err());
+
+    // Only the second part is left, so counting the parts from the first one finds none
+    let options = options_for(MemoryFs::with_files(&["/media/card/GX020001.MP4", "/media/card/GX020001.THM", "/media/card/GL020001.LRV"]));
+    assert!(handler.get_related(Path::new(LOCATION), &card.join("GX020001.MP4"), &options).is_err());
+}
+
+#[test]
+fn sony_management_files_follow_the_unknown_file_policy() {
+    let handler = get_handler("Sony-ILCEM4-1").unwrap();
+    let mut options = options_for(MemoryFs::with_files(&["/media/card/PRIVATE/M4ROOT/CUEUP.XML"]));
+    let file = Path::new("/media/card/PRIVATE/M4ROOT/CUEUP.XML");
+    assert!(handler.get_related(Path::new(LOCATION), file, &options).is_err());
+    options.unknown_file_policy = UnknownFilePolicy::Ignore;
+    assert!(handler.get_related(Path::new(LOCATION), file, &options).unwrap().is_empty());
+}
+
+/// What every file of the fixture of a handler is to its item
+const ROLES: &[(&str, &[(&str, &str)])] = &[
+    ("GoPro-Hero-Generic-1", &[("GL010001.LRV", "preview"), ("GL020001.LRV", "preview"), ("GOPR0002.GPR", "primary"), ("GOPR0002.JPG", "primary"),
+                               ("GOPR0003.JPG", "primary"), ("GX010001.JPG", "preview"), ("GX010001.MP4", "primary"), ("GX010001.THM", "preview"),
+                               ("GX020001.MP4", "part"), ("GX020001.THM", "preview")]),
+    ("Sony-ILCEM4-1", &[("DCIM/100MSDCF/DSC00001.ARW", "primary"), ("DCIM/100MSDCF/DSC00001.JPG", "primary"), ("DCIM/100MSDCF/DSC00002.HIF", "primary"),
+                        ("PRIVATE/M4ROOT/CLIP/C0001.MP4", "primary"), ("PRIVATE/M4ROOT/CLIP/C0001M01.XML", "metadata"),
+                        ("PRIVATE/M4ROOT/SUB/C0001S03.MP4", "preview"), ("PRIVATE/M4ROOT/THMBNL/C0001T01.JPG", "preview")]),
+    ("Generic-Single-File-Items", &[("clip.mp4", "primary"), ("memo.wav", "primary"), ("photo.jpg", "primary")]),
+    ("GNSS-Tracker-Generic", &[("other.txt", "primary"), ("track.gpx", "primary"), ("track.kml", "primary")]),
+    ("Manifest-Driven-Generic-1", &[("clip.jpg", "preview"), ("clip.mp4", "primary"), ("photo.jpg", "primary")]),
+    ("Ricoh-Theta-Generic-1", &[("DCIM/100RICOH/R0010001.JPG", "primary"), ("DCIM/100RICOH/R0010002.MP4", "primary")]),
+    ("Android-Motion-Photo-Generic-1", &[("PXL_0001.jpg", "primary"), ("PXL_0002.mp4", "primary")]),
+    ("DJI-Drone-Generic-1", &[("DCIM/100MEDIA/DJI_0001.LRF", "preview"), ("DCIM/100MEDIA/DJI_0001.MP4", "primary"), ("DCIM/100MEDIA/DJI_0001.SRT", "metadata"),
+                              ("DCIM/100MEDIA/DJI_0002.DNG", "primary"), ("DCIM/100MEDIA/DJI_0002.JPG", "primary")]),
+    ("Olympus-Generic-1", &[("DCIM/100OLYMP/PA140001.JPG", "primary"), ("DCIM/100OLYMP/PA140001.ORF", "primary"), ("DCIM/100OLYMP/PA140002.MOV", "primary")]),
+    ("Stereo-Pair-Generic-1", &[("A_L.JPG", "primary"), ("A_R.JPG", "part"), ("B.MPO", "primary")]),
+];
+
+/// The role of every file related to a listed item, by its path relative to the card
+fn related_roles(handler_name: &str, fs: MemoryFs) -> BTreeMap<String, String> {
+    let handler = get_handler(handler_name).unwrap();
+    let options = options_for(fs);
+    let mut roles = BTreeMap::new();
+    for item in handler.list_high_quality(Path::new(LOCATION), Path::new(CARD), &options).unwrap() {
+        for file in handler.get_related(Path::new(LOCATION), Path::new(&item.file_path), &options).unwrap() {
+            let relative = Path::new(&file.file_path).strip_prefix(CARD).unwrap().to_string_lossy().into_owned();
+            roles.insert(relative, file.role.expect("every file has a role"));
+        }
+    }
+    roles
+}
+
+#[test]
+fn related_files_have_the_role_of_their_place_in_the_item() {
+    for fixture in FIXTURES {
+        let (_, expected) = ROLES.iter().find(|(handler, _)| *handler == fixture.handler).unwrap();
+        let expected: BTreeMap<String, String> = expected.iter().map(|(file, role)| (file.to_string(), role.to_string())).collect();
+        assert_eq!(related_roles(fixture.handler, fixture.fs()), expected, "{}", fixture.handler);
+    }
+
+    // The audio of a video comes along with it
+    let roles = related_roles("GoPro-Hero-Generic-1", MemoryFs::with_files(&["/media/card/GX010001.MP4", "/media/card/GX010001.THM",
+                                                                          "/media/card/GL010001.LRV", "/media/card/GX010001.WAV"]));
+    assert_eq!(roles["GX010001.WAV"], "sidecar");
+}
@@ -0,0 +1,95 @@
+/* gopro_directory_listing.rs - Checks the GoPro handler working off one listing of the card matches checking files one by one
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::fs_provider::MemoryFs;
+use media_interface::{get_handler, FileItem};
+use serde_json::Value;
+use std::path::Path;
+
+/// What the handler output for a card back when it checked whether each file it could be made
+/// of exists one by one, with paths relative to the card. Fields added to the output since are
+/// not in it
+const BEFORE_DIRECTORY_LISTING: &str = include_str!("data/gopro_before_directory_listing.json");
+
+fn recorded() -> Value {
+    serde_json::from_str(BEFORE_DIRECTORY_LISTING).unwrap()
+}
+
+fn card_fs(recorded: &Value) -> MemoryFs {
+    let files: Vec<String> = recorded["files"].as_array().unwrap().iter().map(|file| format!("{}/{}", CARD, file.as_str().unwrap())).collect();
+    MemoryFs::with_files(&files.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+/// Every field of the recorded items has the same value in `items`, in the same order
+fn assert_matches(items: &[FileItem], recorded: &Value, what: &str) {
+    let recorded = recorded.as_array().unwrap();
+    assert_eq!(items.len(), recorded.len(), "{}: {:?}", what, items.iter().map(|item| &item.file_path).collect::<Vec<_>>());
+    for (item, recorded_item) in items.iter().zip(recorded) {
+        let item = serde_json::to_value(item).unwrap();
+        for (field, value) in recorded_item.as_object().unwrap() {
+            let value = match field.as_str() {
+                "file_path" | "metadata_file" => Value::String(format!("{}/{}", CARD, value.as_str().unwrap())),
+                _ => value.clone(),
+            };
+            assert_eq!(item[field], value, "{}: {} of {}", what, field, item["file_path"]);
+        }
+    }
+}
+
+#[test]
+fn output_is_the_same_as_checking_files_one_by_one() {
+    let recorded = recorded();
+    let handler = get_handler("GoPro-Hero-Generic-1").unwrap();
+    let options = options_for(card_fs(&recorded));
+    let (location, card) = (Path::new(LOCATION), Path::new(CARD));
+
+    // Listed in the order the directory is read in, which is up to the filesystem
+    let by_path = |mut items: Vec<FileItem>| {
+        items.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        items
+    };
+    let recorded_by_path = |action: &str| {
+        let mut items = recorded[action].as_array().unwrap().clone();
+        items.sort_by(|a, b| a["file_path"].as_str().cmp(&b["file_path"].as_str()));
+        Value::Array(items)
+    };
+    assert_matches(&by_path(handler.list_thumbnail(location, card, &options).unwrap()), &recorded_by_path("list_thumbnail"), "list_thumbnail");
+    assert_matches(&by_path(handler.list_high_quality(location, card, &options).unwrap()), &recorded_by_path("list_high_quality"), "list_high_quality");
+    for (file, related) in recorded["get_related"].as_object().unwrap() {
+        let items = handler.get_related(location, &card.join(file), &options).unwrap();
+        assert_matches(&items, related, &format!("get_related of {}", file));
+    }
+}
+
+#[test]
+fn related_files_are_found_with_one_directory_listing() {
+    let recorded = recorded();
+    let handler = get_handler("GoPro-Hero-Generic-1").unwrap();
+    let (fs, calls) = CountingFs::new(card_fs(&recorded));
+    let options = options_for(fs);
+
+    // A clip of 3 parts has 6 kinds of files each could be made of, 18 to check one by one
+    let related = handler.get_related(Path::new(LOCATION), &Path::new(CARD).join("GX020010.THM"), &options).unwrap();
+    assert_eq!(related.len(), 12);
+    assert_eq!(calls.read_dir.get(), 1);
+    assert!(calls.total() <= 4, "{} read_dir, {} metadata and {} read calls", calls.read_dir.get(), calls.metadata.get(), calls.read.get());
+}
@@ -0,0 +1,63 @@
+/* list_iter.rs - Checks the streaming list methods against the list actions
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::fs_provider::MemoryFs;
+use media_interface::{get_handler, FileItem};
+use std::path::Path;
+
+fn paths(items: &[FileItem]) -> Vec<&str> {
+    items.iter().map(|item| item.file_path.as_str()).collect()
+}
+
+#[test]
+fn iterators_yield_the_items_of_the_list_actions() {
+    for fixture in FIXTURES {
+        let handler = get_handler(fixture.handler).unwrap();
+        let options = options_for(fixture.fs());
+        let (location, card) = (Path::new(LOCATION), Path::new(CARD));
+
+        let streamed: Vec<FileItem> = handler.list_thumbnail_iter(location, card, &options).collect::<Result<_, _>>().unwrap();
+        assert_eq!(paths(&streamed), paths(&handler.list_thumbnail(location, card, &options).unwrap()), "{}", fixture.handler);
+        let streamed: Vec<FileItem> = handler.list_high_quality_iter(location, card, &options).collect::<Result<_, _>>().unwrap();
+        assert_eq!(paths(&streamed), paths(&handler.list_high_quality(location, card, &options).unwrap()), "{}", fixture.handler);
+    }
+}
+
+#[test]
+fn streamed_items_come_before_an_error_later_on_the_card() {
+    let handler = get_handler("Android-Motion-Photo-Generic-1").unwrap();
+    let (fs, calls) = CountingFs::new(MemoryFs::with_files(&["/media/card/PXL_0001.jpg", "/media/card/PXL_0002.jpg", "/media/card/PXL_0003.mp4", "/media/card/notes.txt"]));
+    let options = options_for(fs);
+    let (location, card) = (Path::new(LOCATION), Path::new(CARD));
+
+    assert!(handler.list_thumbnail(location, card, &options).is_err());
+
+    calls.read.set(0);
+    let mut items = handler.list_thumbnail_iter(location, card, &options);
+    assert_eq!(items.next().unwrap().unwrap().file_path, "/media/card/PXL_0001.jpg");
+    // Only the first photo has been read to check it for motion so far
+    #[cfg(feature = "motion-photo")]
+    assert_eq!(calls.read.get(), 1);
+    assert_eq!(items.next().unwrap().unwrap().file_path, "/media/card/PXL_0002.jpg");
+    assert_eq!(items.next().unwrap().unwrap().file_path, "/media/card/PXL_0003.mp4");
+    assert!(items.next().unwrap().is_err());
+}
@@ -0,0 +1,101 @@
+/* output_schema.rs - Checks the output of every action against the shipped output schema
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::json_schema::validate;
+use serde_json::Value;
+
+const SCHEMA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/schema/output.schema.json");
+
+fn schema() -> Value {
+    serde_json::from_str(&std::fs::read_to_string(SCHEMA).unwrap()).unwrap()
+}
+
+#[test]
+fn default_output_of_every_handler_conforms_to_the_schema() {
+    let schema = schema();
+    for fixture in FIXTURES {
+        let dir = TestDir::new();
+        let (config, cards) = dir.source_media(&[(fixture.handler, fixture.files)]);
+        if fixture.files.contains(&"manifest.json") {
+            std::fs::write(cards[0].join("manifest.json"), MANIFEST).unwrap();
+        }
+        let (config, card) = (config.to_str().unwrap(), cards[0].to_str().unwrap());
+        let missing_file = cards[0].join(fixture.missing);
+        let (listed, _) = run(&["-c", config, "-L", card]);
+        let first_file = file_paths(&listed).remove(0);
+
+        let runs: [(&[&str], bool); 4] = [
+            (&["-c", config, "-l", card], true),
+            (&["-c", config, "-L", card, "--include-related"], true),
+            (&["-c", config, "-g", &first_file], true),
+            (&["-c", config, "-g", missing_file.to_str().unwrap()], false),
+        ];
+        for (args, succeeds) in runs {
+            let (output, success) = run(args);
+            assert_eq!(success, succeeds, "{} {:?}: {}", fixture.handler, args, output);
+            assert_eq!(validate(&output, &schema), Vec::<String>::new(), "{} {:?}: {}", fixture.handler, args, output);
+        }
+    }
+    let (output, success) = run(&["--capabilities"]);
+    assert!(success, "{}", output);
+    assert_eq!(validate(&output, &schema), Vec::<String>::new(), "{}", output);
+}
+
+#[test]
+fn validate_output_fails_on_output_the_schema_doesnt_allow() {
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let args = ["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--validate-output"];
+
+    let (output, success) = run(&[&args[..], &[SCHEMA]].concat());
+    assert!(success, "{}", output);
+
+    // Without the fields the default output always has
+    let mut strict = schema();
+    strict["$defs"]["file_item"]["additionalProperties"] = false.into();
+    strict["$defs"]["file_item"]["properties"] = serde_json::json!({"file_path": {"type": "string"}});
+    let strict_path = dir.path.join("strict.schema.json");
+    std::fs::write(&strict_path, strict.to_string()).unwrap();
+    let (output, success) = run(&[&args[..], &[strict_path.to_str().unwrap()]].concat());
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains("/file_list/0/file_type"), "{}", output);
+}
+
+#[test]
+fn schemas_with_keywords_the_checker_doesnt_implement_never_pass() {
+    let value = serde_json::json!({"file_list": ["not an item"]});
+    let anyof_schema = serde_json::json!({"type": "object", "properties": {"file_list": {"type": "array", "items": {"anyOf": [{"type": "object"}], "minItems": 1}}}});
+    assert_eq!(validate(&value, &anyof_schema), [
+        r#"unsupported keyword "anyOf" at /properties/file_list/items"#,
+        r#"unsupported keyword "minItems" at /properties/file_list/items"#,
+    ]);
+
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg"])]);
+    let mut patterned = schema();
+    patterned["$defs"]["file_item"]["properties"]["file_path"]["pattern"] = "^/".into();
+    let patterned_path = dir.path.join("patterned.schema.json");
+    std::fs::write(&patterned_path, patterned.to_string()).unwrap();
+    let (output, success) = run(&["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap(), "--validate-output", patterned_path.to_str().unwrap()]);
+    assert!(!success, "{}", output);
+    assert!(output["error_string"].as_str().unwrap().contains(r#"unsupported keyword "pattern" at /$defs/file_item/properties/file_path"#), "{}", output);
+}
@@ -0,0 +1,98 @@
+/* permissions.rs - Checks scans going past directories that can't be read
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::fs_provider::{FsEntry, FsMetadata, FsProvider, MemoryFs};
+use media_interface::{get_handler, PermissionErrorPolicy};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Refuses to list one directory, like one without read permission for the user running the scan
+struct DenyingFs {
+    inner: MemoryFs,
+    denied: PathBuf,
+}
+
+impl FsProvider for DenyingFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<FsEntry>> {
+        if dir == self.denied {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        }
+        self.inner.read_dir(dir)
+    }
+    fn metadata(&self, path: &Path) -> io::Result<FsMetadata> {
+        self.inner.metadata(path)
+    }
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+    fn read_range(&self, path: &Path, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.inner.read_range(path, offset, len)
+    }
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+}
+
+#[test]
+fn unreadable_directories_are_skipped_with_a_warning_by_skip_warn() {
+    let handler = get_handler("Generic-Single-File-Items").unwrap();
+    let fs = DenyingFs{ inner: MemoryFs::with_files(&["/media/card/photo.jpg", "/media/card/locked/clip.mp4"]), denied: PathBuf::from("/media/card/locked") };
+    let mut options = options_for(fs);
+    options.recursive = true;
+    let (location, card) = (Path::new(LOCATION), Path::new(CARD));
+
+    assert!(handler.list_high_quality(location, card, &options).is_err());
+    assert!(options.scan_warnings.borrow().is_empty());
+
+    options.permission_error_policy = PermissionErrorPolicy::SkipWarn;
+    let items = handler.list_high_quality(location, card, &options).unwrap();
+    assert_eq!(items.iter().map(|item| item.file_path.as_str()).collect::<Vec<_>>(), ["/media/card/photo.jpg"]);
+    let warnings = options.scan_warnings.take();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("\"/media/card/locked\""), "{}", warnings[0]);
+}
+
+#[test]
+#[cfg(unix)]
+fn chmod_0_directories_are_skipped_with_a_warning_in_the_output() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[("Generic-Single-File-Items", &["photo.jpg", "locked/clip.mp4"])]);
+    edit_config(&config, |config| config["source_media"][0]["recursive"] = true.into());
+    let locked = cards[0].join("locked");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    let readable = std::fs::read_dir(&locked).is_ok();
+    let args = ["-c", config.to_str().unwrap(), "-L", cards[0].to_str().unwrap()];
+    let runs = (run(&args), run(&[&args[..], &["--permission-error-policy", "skip-warn"]].concat()));
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // Permissions don't keep privileged users out, the test above covers the policy without them
+    if readable {
+        return;
+    }
+
+    let ((output, success), (skipped, skipped_success)) = runs;
+    assert!(!success, "{}", output);
+    assert!(skipped_success, "{}", skipped);
+    assert_eq!(file_names(&skipped), ["photo.jpg"]);
+    assert!(skipped["warnings"].to_string().contains("can't be read"), "{}", skipped);
+}
@@ -0,0 +1,105 @@
+/* snapshots.rs - Compares the output of every action on a card of each device against a golden copy
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Each fixture in tests/data/snapshots is a card of empty files made with the handler given in
+// it, along with the files to get the related files of. A fixture can also give the size of some
+// of the files, so that the metadata the output reports is the same on every run, and arguments
+// to run every action with. The output of every action on it, with the card directory written as
+// $CARD, is kept next to it in <fixture>.golden.json. Run the
+// tests with UPDATE_SNAPSHOTS=1 to write the golden copies anew after an intended change of the
+// output, then look over the diff
+
+const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/snapshots");
+
+fn fixtures() -> Vec<PathBuf> {
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(SNAPSHOTS).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json") && !path.to_string_lossy().ends_with(".golden.json"))
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+/// The output of `args` with the parts that differ from run to run taken out
+fn normalized_output(args: &[&str], card: &Path) -> Value {
+    let (mut output, _) = run(args);
+    output["version"] = Value::from("$VERSION");
+    let text = output.to_string().replace(&*card.to_string_lossy(), "$CARD");
+    serde_json::from_str(&text).unwrap()
+}
+
+fn actions_output(fixture: &Value) -> BTreeMap<String, Value> {
+    let files: Vec<&str> = fixture["files"].as_array().unwrap().iter().map(|file| file.as_str().unwrap()).collect();
+    let dir = TestDir::new();
+    let (config, cards) = dir.source_media(&[(fixture["handler"].as_str().unwrap(), &files)]);
+    let (config, card) = (config.to_str().unwrap(), &cards[0]);
+    for (file, size) in fixture.get("sizes").and_then(Value::as_object).into_iter().flatten() {
+        std::fs::write(card.join(file), vec![0; size.as_u64().unwrap() as usize]).unwrap();
+    }
+    let args: Vec<&str> = fixture.get("args").and_then(Value::as_array).into_iter().flatten().map(|arg| arg.as_str().unwrap()).collect();
+
+    let mut outputs = BTreeMap::new();
+    for (action, flag) in [("list_thumbnail", "-l"), ("list_high_quality", "-L")] {
+        outputs.insert(action.to_string(), normalized_output(&[&["-c", config, flag, card.to_str().unwrap()], &args[..]].concat(), card));
+    }
+    for file in fixture["get_related"].as_array().unwrap() {
+        let file = file.as_str().unwrap();
+        outputs.insert(format!("get_related {}", file), normalized_output(&[&["-c", config, "-g", card.join(file).to_str().unwrap()], &args[..]].concat(), card));
+    }
+    outputs
+}
+
+#[test]
+fn output_matches_the_golden_copies() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", SNAPSHOTS);
+
+    let mut mismatched = Vec::new();
+    for fixture_path in fixtures {
+        let fixture: Value = serde_json::from_str(&std::fs::read_to_string(&fixture_path).unwrap()).unwrap();
+        let outputs = actions_output(&fixture);
+        let golden_path = fixture_path.with_extension("golden.json");
+
+        if update {
+            std::fs::write(&golden_path, serde_json::to_string_pretty(&outputs).unwrap() + "\n").unwrap();
+            continue;
+        }
+        let golden: BTreeMap<String, Value> = std::fs::read_to_string(&golden_path).ok()
+            .and_then(|golden| serde_json::from_str(&golden).ok())
+            .unwrap_or_else(|| panic!("no golden copy at {:?}, run the tests with UPDATE_SNAPSHOTS=1 to write it", golden_path));
+        for (action, output) in &outputs {
+            if golden.get(action) != Some(output) {
+                mismatched.push(format!("{:?} {}:\n  expected {}\n  got      {}", fixture_path.file_name().unwrap(), action,
+                    golden.get(action).map_or("nothing".to_string(), Value::to_string), output));
+            }
+        }
+        for action in golden.keys().filter(|action| !outputs.contains_key(*action)) {
+            mismatched.push(format!("{:?} {}: in the golden copy but no longer run", fixture_path.file_name().unwrap(), action));
+        }
+    }
+    assert!(mismatched.is_empty(), "output differs from the golden copies, run the tests with UPDATE_SNAPSHOTS=1 if that's intended:\n{}", mismatched.join("\n"));
+}
@@ -0,0 +1,69 @@
+/* unknown_files.rs - Checks files the handlers don't know go through the unknown file policy
+
+   This file is part of the media-interface project
+
+   Copyright (c) 2025 Efthymios Kritikos
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.  */
+
+mod common;
+
+use common::*;
+use media_interface::{get_handler, FileItem, HandlerOptions, SourceMediaInterface, UnknownFilePolicy};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+type ListAction = fn(&dyn SourceMediaInterface, &HandlerOptions) -> anyhow::Result<Vec<FileItem>>;
+
+const LIST_ACTIONS: [ListAction; 2] = [
+    |handler, options| handler.list_thumbnail(Path::new(LOCATION), Path::new(CARD), options),
+    |handler, options| handler.list_high_quality(Path::new(LOCATION), Path::new(CARD), options),
+];
+
+fn listed(items: &[FileItem]) -> Vec<&str> {
+    items.iter().map(|item| item.file_path.as_str()).collect()
+}
+
+#[test]
+fn files_without_or_with_an_unknown_extension_follow_the_policy() {
+    for fixture in FIXTURES {
+        let handler = get_handler(fixture.handler).unwrap();
+        let card = Path::new(CARD);
+        let clean = options_for(fixture.fs());
+
+        // Next to the files of the card, where the handler looks for them
+        let mut fs = fixture.fs();
+        let dirs: BTreeSet<_> = fixture.files.iter().filter_map(|file| card.join(file).parent().map(Path::to_path_buf)).collect();
+        for dir in &dirs {
+            fs.add_file(&dir.join("README"), 0, None);
+            fs.add_file(&dir.join("notes.xyz"), 0, None);
+        }
+        let mut stray = options_for(fs);
+        stray.unknown_file_policy = UnknownFilePolicy::Ignore;
+
+        for list in LIST_ACTIONS {
+            let expected = list(handler.as_ref(), &clean).unwrap();
+            let items = list(handler.as_ref(), &stray).unwrap_or_else(|error| panic!("{} with the ignore policy: {:#}", fixture.handler, error));
+            assert_eq!(listed(&items), listed(&expected), "{}", fixture.handler);
+        }
+
+        // The manifest driven handler only ever looks at the files of its manifest
+        if fixture.handler != "Manifest-Driven-Generic-1" {
+            stray.unknown_file_policy = UnknownFilePolicy::Error;
+            for list in LIST_ACTIONS {
+                assert!(list(handler.as_ref(), &stray).is_err(), "{} with the error policy", fixture.handler);
+            }
+        }
+    }
+}